@@ -0,0 +1,65 @@
+//! Measures how many instructions per second the interpreter executes on a few representative
+//! programs, with and without the cache of decoded instructions and with the recompiler. Run
+//! with `cargo bench`, a name given after `--` only runs the programs containing it.
+
+use std::time::{Duration, Instant};
+
+use chip8::assembler;
+use chip8::interpreter::{Engine, Interpreter};
+
+/// The instructions of every frame, roughly what a fast game runs at.
+const CYCLES_PER_FRAME: u32 = 1000;
+/// Every program runs for at least this long.
+const MIN_DURATION: Duration = Duration::from_secs(1);
+
+/// The programs shared with the tests of the recompiler.
+const PROGRAMS: [(&str, &str); 4] = [
+    ("arithmetic", include_str!("../programs/arithmetic.asm")),
+    ("sprites", include_str!("../programs/sprites.asm")),
+    ("subroutines", include_str!("../programs/subroutines.asm")),
+    ("bcd", include_str!("../programs/bcd.asm")),
+];
+
+fn main() {
+    let filter = std::env::args().skip(1).find(|arg| !arg.starts_with("--"));
+
+    for (name, source) in PROGRAMS {
+        if filter.as_ref().is_some_and(|filter| !name.contains(filter.as_str())) {
+            continue;
+        }
+
+        let rom = assembler::assemble(source).unwrap_or_else(|e| panic!("{}: {}", name, e));
+        let uncached = instructions_per_second(&rom, Engine::Interpreter, false);
+        let cached = instructions_per_second(&rom, Engine::Interpreter, true);
+        let recompiled = instructions_per_second(&rom, Engine::Recompiler, true);
+
+        println!(
+            "{:<12} {:>8.2} M/s uncached {:>8.2} M/s cached {:>6.2}x {:>8.2} M/s recompiled {:>6.2}x",
+            name,
+            uncached / 1e6,
+            cached / 1e6,
+            cached / uncached,
+            recompiled / 1e6,
+            recompiled / uncached
+        );
+    }
+}
+
+fn instructions_per_second(rom: &[u8], engine: Engine, decode_cache: bool) -> f64 {
+    let mut interpreter = Interpreter::with_rom(rom);
+    interpreter.set_engine(engine);
+    interpreter.set_decode_cache(decode_cache);
+
+    let start = Instant::now();
+    let mut frames = 0u64;
+    while start.elapsed() < MIN_DURATION {
+        for _ in 0..100 {
+            interpreter
+                .run_frame(CYCLES_PER_FRAME)
+                .expect("the program runs forever");
+        }
+        frames += 100;
+    }
+
+    (frames * CYCLES_PER_FRAME as u64) as f64 / start.elapsed().as_secs_f64()
+}
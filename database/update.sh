@@ -0,0 +1,10 @@
+#!/bin/sh
+# Downloads the programs and the license of the community CHIP-8 database, which are bundled
+# into the interpreter at build time.
+set -eu
+
+REPOSITORY=https://raw.githubusercontent.com/chip-8/chip-8-database/master
+DIRECTORY=$(dirname "$0")
+
+curl -fsSL -o "$DIRECTORY/programs.json" "$REPOSITORY/database/programs.json"
+curl -fsSL -o "$DIRECTORY/LICENSE" "$REPOSITORY/LICENSE"
@@ -0,0 +1,16 @@
+; Register arithmetic in a tight loop with a skip.
+loop:
+    ADD V0, 1
+    LD V1, V0
+    SHR V1, V1
+    XOR V2, V1
+    SUB V4, V0
+    SHL V5, V4
+    OR V6, V5
+    AND V7, V6
+    SUBN V8, V7
+    ADD V9, V8
+    SE V0, 0
+    JP loop
+    ADD V3, 1
+    JP loop
@@ -0,0 +1,10 @@
+; Every store forgets the decoded instructions around the buffer.
+    LD I, buffer
+loop:
+    LD B, V5
+    LD V2, [I]
+    ADD V3, V2
+    ADD V5, 1
+    JP loop
+buffer:
+    db 0, 0, 0
@@ -0,0 +1,11 @@
+; Draws digits and random sprites all over the screen.
+loop:
+    LD F, V0
+    DRW V1, V2, 5
+    ADD V0, 1
+    ADD V1, 5
+    ADD V2, 3
+    RND V3, 0x0F
+    ADD I, V3
+    DRW V3, V2, 3
+    JP loop
@@ -0,0 +1,10 @@
+; Calls a short subroutine with a skip in it.
+loop:
+    CALL count
+    CALL count
+    JP loop
+count:
+    ADD V0, 1
+    SNE V0, 0
+    ADD V1, 1
+    RET
@@ -0,0 +1,700 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::{
+    instruction::{Addr, Byte, Instruction, Nibble, Vx},
+    memory::START_ROM,
+};
+
+/// How deep `include` directives may be nested before we assume a cycle.
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// An error in the assembler source, `line` and `column` are 1-based.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssembleError {
+    pub file: Option<PathBuf>,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}:", file.display())?;
+        }
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for AssembleError {}
+
+/// Where a statement came from, used to report errors.
+#[derive(Debug, Clone)]
+struct Location {
+    file: Option<PathBuf>,
+    line: usize,
+    column: usize,
+}
+
+impl Location {
+    fn error(&self, message: impl Into<String>) -> AssembleError {
+        AssembleError {
+            file: self.file.clone(),
+            line: self.line,
+            column: self.column,
+            message: message.into(),
+        }
+    }
+
+    fn at_column(&self, column: usize) -> Location {
+        Location { column, ..self.clone() }
+    }
+}
+
+/// A sum of numbers and symbols, e.g. `sprites + 5`.
+#[derive(Debug, Clone)]
+struct Expr {
+    terms: Vec<(i64, Term)>,
+}
+
+#[derive(Debug, Clone)]
+enum Term {
+    Number(i64),
+    Symbol(String),
+}
+
+#[derive(Debug, Clone)]
+enum Operand {
+    Register(u8),
+    I,
+    IndirectI,
+    DelayTimer,
+    SoundTimer,
+    Key,
+    Font,
+    BigFont,
+    Flags,
+    Bcd,
+    Audio,
+    Pitch,
+    RegisterRange(u8, u8),
+    Long(Expr),
+    Value(Expr),
+}
+
+#[derive(Debug, Clone)]
+struct Argument {
+    operand: Operand,
+    location: Location,
+}
+
+#[derive(Debug)]
+enum Statement {
+    Label(String),
+    Constant(String, Expr),
+    Instruction(String, Vec<Argument>),
+    Bytes(Vec<Argument>),
+    Words(Vec<Argument>),
+}
+
+/// Assembles source code written with the mnemonics from Cowgod's Chip-8 Technical Reference
+/// into a ROM that is loaded at `START_ROM`.
+///
+/// Besides instructions the source may contain labels (`loop:`), constants (`speed = 4`),
+/// data (`db 0xF0, 0x90` and `dw label`) and `include "file.asm"` directives, which are
+/// resolved relative to the current directory.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AssembleError> {
+    let mut statements = Vec::new();
+    parse(source, None, Path::new("."), 0, &mut statements)?;
+    emit(&statements)
+}
+
+/// Assembles the source in `path`, `include` directives are resolved relative to it.
+pub fn assemble_file(path: &Path) -> Result<Vec<u8>, AssembleError> {
+    let source = read_source(path, None)?;
+    let base = path.parent().unwrap_or_else(|| Path::new("."));
+
+    let mut statements = Vec::new();
+    parse(&source, Some(path), base, 0, &mut statements)?;
+    emit(&statements)
+}
+
+fn read_source(path: &Path, included_from: Option<&Location>) -> Result<String, AssembleError> {
+    std::fs::read_to_string(path).map_err(|e| {
+        let message = format!("cannot read {}: {}", path.display(), e);
+        match included_from {
+            Some(location) => location.error(message),
+            None => AssembleError {
+                file: Some(path.to_path_buf()),
+                line: 0,
+                column: 0,
+                message,
+            },
+        }
+    })
+}
+
+fn parse(
+    source: &str,
+    file: Option<&Path>,
+    base: &Path,
+    depth: usize,
+    statements: &mut Vec<(Location, Statement)>,
+) -> Result<(), AssembleError> {
+    for (idx, raw) in source.lines().enumerate() {
+        let text = raw.split(';').next().unwrap_or_default();
+        let mut location = Location {
+            file: file.map(Path::to_path_buf),
+            line: idx + 1,
+            column: 1,
+        };
+
+        let mut rest = text;
+        let mut offset = 0;
+
+        // Leading labels, there may be more than one on the same line.
+        loop {
+            let trimmed = rest.trim_start();
+            offset += rest.len() - trimmed.len();
+            rest = trimmed;
+
+            let word_len = rest.find(|c: char| c.is_whitespace()).unwrap_or(rest.len());
+            let word = &rest[..word_len];
+            match word.strip_suffix(':') {
+                Some(name) => {
+                    location.column = offset + 1;
+                    check_identifier(name, &location)?;
+                    statements.push((location.clone(), Statement::Label(name.to_string())));
+                    offset += word_len;
+                    rest = &rest[word_len..];
+                }
+                None => break,
+            }
+        }
+
+        if rest.trim().is_empty() {
+            continue;
+        }
+
+        location.column = offset + 1;
+        let word_len = rest.find(|c: char| c.is_whitespace()).unwrap_or(rest.len());
+        let word = &rest[..word_len];
+        let args_offset = offset + word_len;
+        let args = &rest[word_len..];
+
+        // Constants: `name = value`.
+        if let Some(value) = args.trim_start().strip_prefix('=') {
+            check_identifier(word, &location)?;
+            let value_offset = args_offset + (args.len() - args.trim_start().len()) + 1;
+            let argument = parse_argument(value, value_offset, &location)?;
+            match argument.operand {
+                Operand::Value(expr) => statements.push((location, Statement::Constant(word.to_string(), expr))),
+                _ => return Err(argument.location.error("constants must be numbers or symbols")),
+            }
+            continue;
+        }
+
+        match word.to_ascii_lowercase().as_str() {
+            "include" => {
+                if depth >= MAX_INCLUDE_DEPTH {
+                    return Err(location.error("includes are nested too deeply"));
+                }
+
+                let name = args.trim();
+                let name = name
+                    .strip_prefix('"')
+                    .and_then(|name| name.strip_suffix('"'))
+                    .ok_or_else(|| location.error("expected a quoted file name after include"))?;
+
+                let path = base.join(name);
+                let source = read_source(&path, Some(&location))?;
+                let base = path.parent().unwrap_or(base).to_path_buf();
+                parse(&source, Some(&path), &base, depth + 1, statements)?;
+            }
+            "db" => {
+                let arguments = parse_arguments(args, args_offset, &location)?;
+                statements.push((location, Statement::Bytes(arguments)));
+            }
+            "dw" => {
+                let arguments = parse_arguments(args, args_offset, &location)?;
+                statements.push((location, Statement::Words(arguments)));
+            }
+            mnemonic => {
+                let arguments = parse_arguments(args, args_offset, &location)?;
+                statements.push((
+                    location,
+                    Statement::Instruction(mnemonic.to_ascii_uppercase(), arguments),
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn check_identifier(name: &str, location: &Location) -> Result<(), AssembleError> {
+    let valid = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+
+    if !valid {
+        return Err(location.error(format!("invalid name `{}`", name)));
+    }
+
+    if !matches!(parse_reserved(name), Operand::Value(_)) {
+        return Err(location.error(format!("`{}` is reserved", name)));
+    }
+
+    Ok(())
+}
+
+/// Splits the comma separated arguments, `offset` is the position of `text` in the line.
+fn parse_arguments(text: &str, offset: usize, location: &Location) -> Result<Vec<Argument>, AssembleError> {
+    if text.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut arguments = Vec::new();
+    let mut start = 0;
+    for part in text.split(',') {
+        arguments.push(parse_argument(part, offset + start, location)?);
+        start += part.len() + 1;
+    }
+
+    Ok(arguments)
+}
+
+fn parse_argument(text: &str, offset: usize, location: &Location) -> Result<Argument, AssembleError> {
+    let trimmed = text.trim();
+    let location = location.at_column(offset + (text.len() - text.trim_start().len()) + 1);
+
+    if trimmed.is_empty() {
+        return Err(location.error("expected an operand"));
+    }
+
+    let long = trimmed
+        .get(..5)
+        .filter(|prefix| prefix.eq_ignore_ascii_case("long "))
+        .map(|_| &trimmed[5..]);
+
+    let operand = match (long, parse_reserved(trimmed)) {
+        (Some(address), _) => Operand::Long(parse_expr(address, &location)?),
+        (None, Operand::Value(_)) => Operand::Value(parse_expr(trimmed, &location)?),
+        (None, operand) => operand,
+    };
+
+    Ok(Argument { operand, location })
+}
+
+/// Parses register names and the special operands, anything else is returned as a symbol.
+fn parse_reserved(text: &str) -> Operand {
+    let upper = text.to_ascii_uppercase();
+
+    match upper.as_str() {
+        "I" => Operand::I,
+        "[I]" => Operand::IndirectI,
+        "DT" => Operand::DelayTimer,
+        "ST" => Operand::SoundTimer,
+        "K" => Operand::Key,
+        "F" => Operand::Font,
+        "HF" => Operand::BigFont,
+        "R" => Operand::Flags,
+        "B" => Operand::Bcd,
+        "AUDIO" => Operand::Audio,
+        "PITCH" => Operand::Pitch,
+        _ => match upper.split_once('-') {
+            Some((x, y)) => match (register(x.trim()), register(y.trim())) {
+                (Some(x), Some(y)) => Operand::RegisterRange(x, y),
+                _ => Operand::Value(symbol(text)),
+            },
+            None => match register(&upper) {
+                Some(x) => Operand::Register(x),
+                None => Operand::Value(symbol(text)),
+            },
+        },
+    }
+}
+
+/// Parses an upper case register name like `VA`.
+fn register(text: &str) -> Option<u8> {
+    match text.strip_prefix('V') {
+        Some(digit) if digit.len() == 1 => u8::from_str_radix(digit, 16).ok(),
+        _ => None,
+    }
+}
+
+fn symbol(text: &str) -> Expr {
+    Expr {
+        terms: vec![(1, Term::Symbol(text.to_string()))],
+    }
+}
+
+fn parse_expr(text: &str, location: &Location) -> Result<Expr, AssembleError> {
+    let mut terms = Vec::new();
+    let mut sign = 1;
+    let mut current = String::new();
+
+    let mut push_term = |current: &mut String, sign: i64| -> Result<(), AssembleError> {
+        let term = current.trim();
+        if term.is_empty() {
+            return Err(location.error(format!("invalid expression `{}`", text)));
+        }
+        terms.push((sign, parse_term(term, location)?));
+        current.clear();
+        Ok(())
+    };
+
+    for c in text.chars() {
+        match c {
+            '+' | '-' if current.trim().is_empty() && c == '-' => sign = -sign,
+            '+' | '-' => {
+                push_term(&mut current, sign)?;
+                sign = if c == '-' { -1 } else { 1 };
+            }
+            c => current.push(c),
+        }
+    }
+    push_term(&mut current, sign)?;
+
+    Ok(Expr { terms })
+}
+
+fn parse_term(text: &str, location: &Location) -> Result<Term, AssembleError> {
+    let lower = text.to_ascii_lowercase();
+
+    let number = if let Some(hex) = lower.strip_prefix("0x") {
+        Some(i64::from_str_radix(hex, 16))
+    } else if let Some(bin) = lower.strip_prefix("0b") {
+        Some(i64::from_str_radix(bin, 2))
+    } else if lower.starts_with(|c: char| c.is_ascii_digit()) {
+        Some(lower.parse::<i64>())
+    } else {
+        None
+    };
+
+    match number {
+        Some(Ok(n)) => Ok(Term::Number(n)),
+        Some(Err(_)) => Err(location.error(format!("invalid number `{}`", text))),
+        None => {
+            check_identifier(text, location)?;
+            Ok(Term::Symbol(text.to_string()))
+        }
+    }
+}
+
+/// Collects the value of every label and constant.
+struct Symbols<'a> {
+    labels: HashMap<&'a str, i64>,
+    constants: HashMap<&'a str, &'a Expr>,
+}
+
+impl Symbols<'_> {
+    fn resolve(&self, expr: &Expr, location: &Location, depth: usize) -> Result<i64, AssembleError> {
+        if depth > self.constants.len() {
+            return Err(location.error("constant refers to itself"));
+        }
+
+        let mut value: i64 = 0;
+        for (sign, term) in &expr.terms {
+            let term = match term {
+                Term::Number(n) => *n,
+                Term::Symbol(name) => match (self.labels.get(name.as_str()), self.constants.get(name.as_str())) {
+                    (Some(address), _) => *address,
+                    (None, Some(constant)) => self.resolve(constant, location, depth + 1)?,
+                    (None, None) => return Err(location.error(format!("unknown symbol `{}`", name))),
+                },
+            };
+            value = value.wrapping_add(sign * term);
+        }
+
+        Ok(value)
+    }
+
+    fn value(&self, argument: &Argument, max: i64) -> Result<i64, AssembleError> {
+        let expr = match &argument.operand {
+            Operand::Value(expr) => expr,
+            _ => return Err(argument.location.error("expected a number or symbol")),
+        };
+
+        let value = self.resolve(expr, &argument.location, 0)?;
+        if !(0..=max).contains(&value) {
+            return Err(argument
+                .location
+                .error(format!("value {} is out of range 0..={:#X}", value, max)));
+        }
+
+        Ok(value)
+    }
+
+    fn addr(&self, argument: &Argument) -> Result<Addr, AssembleError> {
+        Ok(Addr(self.value(argument, 0xFFF)? as u16))
+    }
+
+    fn long_addr(&self, argument: &Argument) -> Result<Addr, AssembleError> {
+        let expr = match &argument.operand {
+            Operand::Long(expr) => expr,
+            _ => return Err(argument.location.error("expected a long address")),
+        };
+
+        let value = self.resolve(expr, &argument.location, 0)?;
+        if !(0..=0xFFFF).contains(&value) {
+            return Err(argument
+                .location
+                .error(format!("value {} is out of range 0..=0xFFFF", value)));
+        }
+
+        Ok(Addr(value as u16))
+    }
+
+    fn byte(&self, argument: &Argument) -> Result<Byte, AssembleError> {
+        Ok(Byte(self.value(argument, 0xFF)? as u8))
+    }
+
+    fn nibble(&self, argument: &Argument) -> Result<Nibble, AssembleError> {
+        Ok(Nibble(self.value(argument, 0xF)? as u8))
+    }
+}
+
+fn size(statement: &Statement) -> usize {
+    match statement {
+        Statement::Label(_) | Statement::Constant(..) => 0,
+        Statement::Instruction(_, arguments) => {
+            if arguments
+                .iter()
+                .any(|argument| matches!(argument.operand, Operand::Long(_)))
+            {
+                4
+            } else {
+                2
+            }
+        }
+        Statement::Bytes(arguments) => arguments.len(),
+        Statement::Words(arguments) => arguments.len() * 2,
+    }
+}
+
+fn emit(statements: &[(Location, Statement)]) -> Result<Vec<u8>, AssembleError> {
+    let mut symbols = Symbols {
+        labels: HashMap::new(),
+        constants: HashMap::new(),
+    };
+
+    let mut address = START_ROM;
+    for (location, statement) in statements {
+        let name = match statement {
+            Statement::Label(name) | Statement::Constant(name, _) => name.as_str(),
+            _ => {
+                address += size(statement);
+                continue;
+            }
+        };
+
+        if symbols.labels.contains_key(name) || symbols.constants.contains_key(name) {
+            return Err(location.error(format!("`{}` is defined more than once", name)));
+        }
+
+        if let Statement::Constant(_, expr) = statement {
+            symbols.constants.insert(name, expr);
+        } else {
+            symbols.labels.insert(name, address as i64);
+        }
+    }
+
+    let mut bytes = Vec::new();
+    for (location, statement) in statements {
+        match statement {
+            Statement::Label(_) | Statement::Constant(..) => {}
+            Statement::Instruction(mnemonic, arguments) => {
+                let instruction = encode(&symbols, location, mnemonic, arguments)?;
+                bytes.extend(instruction.to_bytes());
+            }
+            Statement::Bytes(arguments) => {
+                for argument in arguments {
+                    bytes.push(symbols.value(argument, 0xFF)? as u8);
+                }
+            }
+            Statement::Words(arguments) => {
+                for argument in arguments {
+                    let word = symbols.value(argument, 0xFFFF)? as u16;
+                    bytes.extend_from_slice(&word.to_be_bytes());
+                }
+            }
+        }
+    }
+
+    Ok(bytes)
+}
+
+fn encode(
+    symbols: &Symbols,
+    location: &Location,
+    mnemonic: &str,
+    arguments: &[Argument],
+) -> Result<Instruction, AssembleError> {
+    use Instruction::*;
+    use Operand::*;
+
+    let operands: Vec<&Operand> = arguments.iter().map(|argument| &argument.operand).collect();
+    let a = arguments.first();
+    let b = arguments.get(1);
+    let c = arguments.get(2);
+
+    let instruction = match (mnemonic, operands.as_slice()) {
+        ("CLS", []) => Clear,
+        ("RET", []) => Ret,
+        ("SYS", [Value(_)]) => Sys(symbols.addr(a.unwrap())?),
+        ("JP", [Value(_)]) => Jump(symbols.addr(a.unwrap())?),
+        ("JP", [Register(0), Value(_)]) => JumpRelative(symbols.addr(b.unwrap())?),
+        ("CALL", [Value(_)]) => Call(symbols.addr(a.unwrap())?),
+        ("SE", [Register(x), Value(_)]) => SkipIfEqualImmediate(Vx(*x), symbols.byte(b.unwrap())?),
+        ("SE", [Register(x), Register(y)]) => SkipIfEqualRegister(Vx(*x), Vx(*y)),
+        ("SNE", [Register(x), Value(_)]) => SkipIfNotEqualImmediate(Vx(*x), symbols.byte(b.unwrap())?),
+        ("SNE", [Register(x), Register(y)]) => SkipIfNotEqualRegister(Vx(*x), Vx(*y)),
+        ("LD", [Register(x), Value(_)]) => LoadRegisterImmediate(Vx(*x), symbols.byte(b.unwrap())?),
+        ("LD", [Register(x), Register(y)]) => LoadRegisterRegister(Vx(*x), Vx(*y)),
+        ("LD", [I, Value(_)]) => LoadImmediateIntoI(symbols.addr(b.unwrap())?),
+        ("LD", [Register(x), DelayTimer]) => StoreDelayTimerRegister(Vx(*x)),
+        ("LD", [Register(x), Key]) => WaitForKeypress(Vx(*x)),
+        ("LD", [DelayTimer, Register(x)]) => LoadDelayTimerRegister(Vx(*x)),
+        ("LD", [SoundTimer, Register(x)]) => LoadSoundTimerRegister(Vx(*x)),
+        ("LD", [Font, Register(x)]) => LoadDigitSpriteLocation(Vx(*x)),
+        ("LD", [BigFont, Register(x)]) => LoadBigDigitSpriteLocation(Vx(*x)),
+        ("LD", [Flags, Register(x)]) => StoreFlags(Vx(*x)),
+        ("LD", [Register(x), Flags]) => LoadFlags(Vx(*x)),
+        ("LD", [Bcd, Register(x)]) => LoadBcd(Vx(*x)),
+        ("LD", [IndirectI, Register(x)]) => StoreRegistersInMemory(Vx(*x)),
+        ("LD", [Register(x), IndirectI]) => LoadRegistersFromMemory(Vx(*x)),
+        ("LD", [IndirectI, RegisterRange(x, y)]) => StoreRegisterRange(Vx(*x), Vx(*y)),
+        ("LD", [RegisterRange(x, y), IndirectI]) => LoadRegisterRange(Vx(*x), Vx(*y)),
+        ("LD", [Audio, IndirectI]) => LoadAudioPattern,
+        ("LD", [Pitch, Register(x)]) => LoadPitch(Vx(*x)),
+        ("LD", [I, Long(_)]) => LoadLongI(symbols.long_addr(b.unwrap())?),
+        ("ADD", [Register(x), Value(_)]) => AddRegisterImmediate(Vx(*x), symbols.byte(b.unwrap())?),
+        ("ADD", [Register(x), Register(y)]) => AddRegisterRegister(Vx(*x), Vx(*y)),
+        ("ADD", [I, Register(x)]) => AddIRegister(Vx(*x)),
+        ("OR", [Register(x), Register(y)]) => OrRegisterRegister(Vx(*x), Vx(*y)),
+        ("AND", [Register(x), Register(y)]) => AndRegisterRegister(Vx(*x), Vx(*y)),
+        ("XOR", [Register(x), Register(y)]) => XorRegisterRegister(Vx(*x), Vx(*y)),
+        ("SUB", [Register(x), Register(y)]) => SubRegisterRegister(Vx(*x), Vx(*y)),
+        ("SUBN", [Register(x), Register(y)]) => SubRegisterRegisterNegated(Vx(*x), Vx(*y)),
+        ("SHR", [Register(x)]) => ShiftRightRegisterOne(Vx(*x), Vx(*x)),
+        ("SHR", [Register(x), Register(y)]) => ShiftRightRegisterOne(Vx(*x), Vx(*y)),
+        ("SHL", [Register(x)]) => ShiftLeftRegisterOne(Vx(*x), Vx(*x)),
+        ("SHL", [Register(x), Register(y)]) => ShiftLeftRegisterOne(Vx(*x), Vx(*y)),
+        ("RND", [Register(x), Value(_)]) => Random(Vx(*x), symbols.byte(b.unwrap())?),
+        ("DRW", [Register(x), Register(y), Value(_)]) => DrawSprite(Vx(*x), Vx(*y), symbols.nibble(c.unwrap())?),
+        ("SKP", [Register(x)]) => SkipIfKeyPressed(Vx(*x)),
+        ("SKNP", [Register(x)]) => SkipIfKeyNotPressed(Vx(*x)),
+        ("SCD", [Value(_)]) => ScrollDown(symbols.nibble(a.unwrap())?),
+        ("SCR", []) => ScrollRight,
+        ("SCL", []) => ScrollLeft,
+        ("EXIT", []) => Exit,
+        ("LOW", []) => LowResolution,
+        ("HIGH", []) => HighResolution,
+        ("PLANE", [Value(_)]) => SelectPlanes(symbols.nibble(a.unwrap())?),
+        _ if KNOWN_MNEMONICS.contains(&mnemonic) => {
+            return Err(location.error(format!("invalid operands for {}", mnemonic)))
+        }
+        _ => return Err(location.error(format!("unknown instruction `{}`", mnemonic))),
+    };
+
+    Ok(instruction)
+}
+
+const KNOWN_MNEMONICS: &[&str] = &[
+    "CLS", "RET", "SYS", "JP", "CALL", "SE", "SNE", "LD", "ADD", "OR", "AND", "XOR", "SUB", "SUBN", "SHR", "SHL",
+    "RND", "DRW", "SKP", "SKNP", "SCD", "SCR", "SCL", "EXIT", "LOW", "HIGH", "PLANE",
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck_macros::quickcheck;
+
+    #[quickcheck]
+    fn test_assemble_disassembled_instruction(opcode: u16) {
+        if let Ok(instruction) = Instruction::decode(opcode) {
+            let bytes = assemble(&instruction.to_string()).unwrap();
+            assert_eq!(bytes, opcode.to_be_bytes());
+        }
+    }
+
+    #[test]
+    fn test_assemble_labels_and_data() {
+        let source = "
+            ; Draws a sprite forever
+            start:
+                LD I, sprite
+                ld v0, offset + 2
+                DRW V0, V0, height
+            loop: JP loop
+            offset = 0x10
+            height = 0b11
+            sprite:
+                db 0xF0, 0x90, 255
+                dw start
+        ";
+
+        let bytes = assemble(source).unwrap();
+
+        assert_eq!(
+            bytes,
+            vec![0xA2, 0x08, 0x60, 0x12, 0xD0, 0x03, 0x12, 0x06, 0xF0, 0x90, 0xFF, 0x02, 0x00]
+        );
+    }
+
+    #[test]
+    fn test_assemble_xo_chip() {
+        let source = "
+            LD I, long data
+            LD [I], V1-V3
+            LD V3 - V1, [I]
+            data: db 0xAA
+        ";
+
+        let bytes = assemble(source).unwrap();
+
+        assert_eq!(bytes, vec![0xF0, 0x00, 0x02, 0x08, 0x51, 0x32, 0x53, 0x13, 0xAA]);
+    }
+
+    #[test]
+    fn test_assemble_shift_without_vy() {
+        assert_eq!(assemble("SHR V3\nSHL VA").unwrap(), vec![0x83, 0x36, 0x8A, 0xAE]);
+    }
+
+    #[test]
+    fn test_assemble_include() {
+        let dir = std::env::temp_dir().join(format!("chip8-asm-include-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("sprites.asm"), "digit: db 0x20, 0x60\n").unwrap();
+        std::fs::write(dir.join("main.asm"), "LD I, digit\ninclude \"sprites.asm\"\n").unwrap();
+
+        let bytes = assemble_file(&dir.join("main.asm"));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(bytes.unwrap(), vec![0xA2, 0x02, 0x20, 0x60]);
+    }
+
+    #[test]
+    fn test_error_locations() {
+        let error = assemble("CLS\n  LD V1, missing").unwrap_err();
+        assert_eq!((error.line, error.column), (2, 10));
+        assert_eq!(error.message, "unknown symbol `missing`");
+
+        let error = assemble("  FOO V1").unwrap_err();
+        assert_eq!((error.line, error.column), (1, 3));
+
+        let error = assemble("DRW V1, V2, 16").unwrap_err();
+        assert_eq!((error.line, error.column), (1, 13));
+
+        let error = assemble("ADD DT, V1").unwrap_err();
+        assert_eq!(error.message, "invalid operands for ADD");
+
+        let error = assemble("a: CLS\na: RET").unwrap_err();
+        assert_eq!((error.line, error.column), (2, 1));
+
+        let error = assemble("x = y\ny = x\nLD V0, x").unwrap_err();
+        assert_eq!(error.message, "constant refers to itself");
+    }
+}
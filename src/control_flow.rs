@@ -0,0 +1,510 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use crate::{
+    instruction::{Addr, Instruction},
+    memory::START_ROM,
+};
+
+/// How execution gets from one block to another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum EdgeKind {
+    /// Continues with the next instruction, also when a skip is not taken.
+    Next,
+    /// A skip is taken.
+    Skip,
+    /// 1nnn - JP addr or 0nnn - SYS addr.
+    Jump,
+    /// 2nnn - CALL addr enters the subroutine.
+    Call,
+    /// The instruction after a `CALL`, where the subroutine returns to.
+    Return,
+}
+
+impl EdgeKind {
+    /// The name used in the DOT output.
+    pub fn name(self) -> &'static str {
+        match self {
+            EdgeKind::Next => "next",
+            EdgeKind::Skip => "skip",
+            EdgeKind::Jump => "jump",
+            EdgeKind::Call => "call",
+            EdgeKind::Return => "return",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Edge {
+    /// The first address of the block execution continues at.
+    pub to: u16,
+    pub kind: EdgeKind,
+}
+
+/// Instructions that are always executed one after the other. Only the first one is the target
+/// of a jump, call, skip or return, only the last one branches.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    /// The instructions and their addresses.
+    pub instructions: Vec<(u16, Instruction)>,
+    /// The blocks execution can continue at. A block without edges returns, exits, jumps to a
+    /// computed address or runs into data.
+    pub edges: Vec<Edge>,
+}
+
+impl Block {
+    pub fn start(&self) -> u16 {
+        self.instructions[0].0
+    }
+
+    /// The address after the last instruction.
+    pub fn end(&self) -> u16 {
+        let (address, instruction) = self.instructions[self.instructions.len() - 1];
+        address.wrapping_add(instruction.size())
+    }
+}
+
+/// The blocks reachable from the entry of a subroutine without entering the subroutines it calls.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Subroutine {
+    pub entry: u16,
+    /// The first addresses of the blocks of the subroutine.
+    pub blocks: BTreeSet<u16>,
+    /// The entries of the subroutines it calls.
+    pub calls: BTreeSet<u16>,
+}
+
+/// The basic blocks of a ROM as it would be loaded at `START_ROM` and the edges between them.
+///
+/// Code is found by following the control flow from the entry point: jumps and calls are
+/// followed, skips continue at both possible successors and `RET` ends a subroutine. Computed
+/// jumps (`JP V0, addr`) cannot be resolved statically, they end the code found this way and
+/// are listed by [`ControlFlowGraph::computed_jumps`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ControlFlowGraph {
+    blocks: BTreeMap<u16, Block>,
+    subroutines: BTreeMap<u16, Subroutine>,
+    computed_jumps: Vec<u16>,
+}
+
+impl ControlFlowGraph {
+    pub fn new(rom: &[u8]) -> Self {
+        let code = find_code(rom);
+
+        // Every branch target and every instruction after a branch starts a block.
+        let mut leaders = BTreeSet::from([START_ROM as u16]);
+        for (&address, instruction) in &code {
+            let edges = edges(rom, address, instruction);
+            if ends_block(address, instruction, &edges) {
+                leaders.extend(edges.iter().map(|edge| edge.to));
+            }
+        }
+
+        let mut blocks = BTreeMap::new();
+        for &start in &leaders {
+            if let Some(block) = build_block(rom, &code, &leaders, start) {
+                blocks.insert(start, block);
+            }
+        }
+
+        let computed_jumps = code
+            .iter()
+            .filter(|(_, instruction)| matches!(instruction, Instruction::JumpRelative(_)))
+            .map(|(address, _)| *address)
+            .collect();
+
+        let mut graph = ControlFlowGraph {
+            blocks,
+            subroutines: BTreeMap::new(),
+            computed_jumps,
+        };
+        graph.subroutines = graph
+            .edges()
+            .filter(|(_, edge)| edge.kind == EdgeKind::Call)
+            .map(|(_, edge)| (edge.to, graph.subroutine(edge.to)))
+            .collect();
+
+        graph
+    }
+
+    /// The blocks ordered by their first address.
+    pub fn blocks(&self) -> impl Iterator<Item = &Block> {
+        self.blocks.values()
+    }
+
+    /// The block starting at `address`.
+    pub fn block(&self, address: u16) -> Option<&Block> {
+        self.blocks.get(&address)
+    }
+
+    /// The first address of every block and its edges.
+    pub fn edges(&self) -> impl Iterator<Item = (u16, Edge)> + '_ {
+        self.blocks
+            .iter()
+            .flat_map(|(start, block)| block.edges.iter().map(move |edge| (*start, *edge)))
+    }
+
+    /// The subroutines called by the ROM, ordered by their entry.
+    pub fn subroutines(&self) -> impl Iterator<Item = &Subroutine> {
+        self.subroutines.values()
+    }
+
+    /// The addresses of the computed jumps (`JP V0, addr`), whose targets are unknown.
+    pub fn computed_jumps(&self) -> &[u16] {
+        &self.computed_jumps
+    }
+
+    /// The instructions of all blocks and their addresses, ordered by address.
+    pub fn instructions(&self) -> impl Iterator<Item = (u16, Instruction)> + '_ {
+        self.blocks().flat_map(|block| block.instructions.iter().copied())
+    }
+
+    /// Whether `address` is part of an instruction that was found, everything else is data or
+    /// only reached through computed jumps.
+    pub fn is_code(&self, address: u16) -> bool {
+        self.blocks
+            .range(..=address)
+            .next_back()
+            .is_some_and(|(_, block)| address < block.end())
+    }
+
+    /// The graph in the DOT language of Graphviz, e.g. for `dot -Tsvg`. Every subroutine is a
+    /// cluster, blocks with a computed jump point to a red `?`.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+        writeln!(dot, "digraph rom {{").unwrap();
+        writeln!(dot, "    node [shape=box, fontname=\"monospace\"];").unwrap();
+
+        // Blocks shared by several subroutines are drawn in the first one.
+        let mut drawn = BTreeSet::new();
+        for subroutine in self.subroutines() {
+            writeln!(dot, "    subgraph \"cluster_{:#05X}\" {{", subroutine.entry).unwrap();
+            writeln!(dot, "        label=\"sub {:#05X}\";", subroutine.entry).unwrap();
+            for start in &subroutine.blocks {
+                if drawn.insert(*start) {
+                    self.write_dot_block(&mut dot, "        ", *start);
+                }
+            }
+            writeln!(dot, "    }}").unwrap();
+        }
+        for start in self.blocks.keys() {
+            if drawn.insert(*start) {
+                self.write_dot_block(&mut dot, "    ", *start);
+            }
+        }
+
+        for (start, edge) in self.edges() {
+            let style = match edge.kind {
+                EdgeKind::Call => ", style=bold",
+                EdgeKind::Return => ", style=dashed",
+                _ => "",
+            };
+            writeln!(
+                dot,
+                "    \"{:#05X}\" -> \"{:#05X}\" [label=\"{}\"{}];",
+                start,
+                edge.to,
+                edge.kind.name(),
+                style
+            )
+            .unwrap();
+        }
+
+        if !self.computed_jumps.is_empty() {
+            writeln!(dot, "    computed [label=\"?\", shape=diamond, color=red];").unwrap();
+            for block in self.blocks() {
+                if matches!(block.instructions.last(), Some((_, Instruction::JumpRelative(_)))) {
+                    writeln!(dot, "    \"{:#05X}\" -> computed [color=red];", block.start()).unwrap();
+                }
+            }
+        }
+
+        writeln!(dot, "}}").unwrap();
+        dot
+    }
+
+    fn write_dot_block(&self, dot: &mut String, indent: &str, start: u16) {
+        let label: String = self.blocks[&start]
+            .instructions
+            .iter()
+            .map(|(address, instruction)| format!("{:#05X}: {}\\l", address, instruction))
+            .collect();
+        writeln!(dot, "{}\"{:#05X}\" [label=\"{}\"];", indent, start, label).unwrap();
+    }
+
+    /// The blocks reachable from `entry` without following calls.
+    fn subroutine(&self, entry: u16) -> Subroutine {
+        let mut blocks = BTreeSet::new();
+        let mut calls = BTreeSet::new();
+        let mut pending = vec![entry];
+
+        while let Some(start) = pending.pop() {
+            if !blocks.insert(start) {
+                continue;
+            }
+
+            for edge in &self.blocks[&start].edges {
+                match edge.kind {
+                    EdgeKind::Call => {
+                        calls.insert(edge.to);
+                    }
+                    _ => pending.push(edge.to),
+                }
+            }
+        }
+
+        Subroutine { entry, blocks, calls }
+    }
+}
+
+/// Returns the instructions reachable from `START_ROM`, keyed by their address.
+fn find_code(rom: &[u8]) -> BTreeMap<u16, Instruction> {
+    let mut code = BTreeMap::new();
+    let mut pending = vec![START_ROM as u16];
+
+    while let Some(address) = pending.pop() {
+        if code.contains_key(&address) {
+            continue;
+        }
+
+        let instruction = match decode(rom, address) {
+            Some(instruction) => instruction,
+            None => continue,
+        };
+
+        code.insert(address, instruction);
+        pending.extend(edges(rom, address, &instruction).iter().map(|edge| edge.to));
+    }
+
+    code
+}
+
+/// The instruction at `address`, `None` if it is outside of the ROM or invalid.
+fn decode(rom: &[u8], address: u16) -> Option<Instruction> {
+    let offset = (address as usize).checked_sub(START_ROM)?;
+    Instruction::decode_bytes(rom.get(offset..)?).ok()
+}
+
+/// Where execution can continue after `instruction` at `address`. Computed jumps
+/// (`JP V0, addr`) cannot be resolved statically and have no successors.
+fn edges(rom: &[u8], address: u16, instruction: &Instruction) -> Vec<Edge> {
+    use Instruction::*;
+
+    let next = address.wrapping_add(instruction.size());
+    let edge = |to, kind| Edge { to, kind };
+
+    match *instruction {
+        Sys(Addr(n)) | Jump(Addr(n)) => vec![edge(n, EdgeKind::Jump)],
+        Call(Addr(n)) => vec![edge(n, EdgeKind::Call), edge(next, EdgeKind::Return)],
+        Ret | JumpRelative(_) | Exit => vec![],
+        SkipIfEqualImmediate(..)
+        | SkipIfNotEqualImmediate(..)
+        | SkipIfEqualRegister(..)
+        | SkipIfNotEqualRegister(..)
+        | SkipIfKeyPressed(_)
+        | SkipIfKeyNotPressed(_) => {
+            // Skips jump over all 4 bytes of a following `F000 nnnn`.
+            let skipped = decode(rom, next).map_or(2, |skipped| skipped.size());
+            vec![
+                edge(next, EdgeKind::Next),
+                edge(next.wrapping_add(skipped), EdgeKind::Skip),
+            ]
+        }
+        _ => vec![edge(next, EdgeKind::Next)],
+    }
+}
+
+/// Whether `instruction` is the last one of its block, because it does not simply continue
+/// with the next instruction.
+fn ends_block(address: u16, instruction: &Instruction, edges: &[Edge]) -> bool {
+    let next = address.wrapping_add(instruction.size());
+    edges
+        != [Edge {
+            to: next,
+            kind: EdgeKind::Next,
+        }]
+}
+
+/// The block starting at `start`, `None` if there is no instruction at `start`.
+fn build_block(rom: &[u8], code: &BTreeMap<u16, Instruction>, leaders: &BTreeSet<u16>, start: u16) -> Option<Block> {
+    let mut instructions = Vec::new();
+    let mut address = start;
+
+    let mut edges = loop {
+        let instruction = *code.get(&address)?;
+        instructions.push((address, instruction));
+
+        let successors = edges(rom, address, &instruction);
+        let next = address.wrapping_add(instruction.size());
+        if ends_block(address, &instruction, &successors) || leaders.contains(&next) || !code.contains_key(&next) {
+            break successors;
+        }
+        address = next;
+    };
+
+    // Drops the edges into data, e.g. past the end of the ROM.
+    edges.retain(|edge| code.contains_key(&edge.to));
+    Some(Block { instructions, edges })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler;
+    use crate::instruction::{Byte, Vx};
+
+    fn graph(source: &str) -> ControlFlowGraph {
+        ControlFlowGraph::new(&assembler::assemble(source).unwrap())
+    }
+
+    fn edges(graph: &ControlFlowGraph) -> Vec<(u16, u16, EdgeKind)> {
+        graph.edges().map(|(from, edge)| (from, edge.to, edge.kind)).collect()
+    }
+
+    #[test]
+    fn test_blocks() {
+        let graph = graph(
+            "
+            loop:
+                LD V0, 1
+                SE V0, 2
+                CALL sub
+                ADD V1, 1
+                JP loop
+            sub:
+                LD V2, 3
+                RET
+            ",
+        );
+
+        let blocks: Vec<(u16, u16)> = graph.blocks().map(|block| (block.start(), block.end())).collect();
+        assert_eq!(
+            blocks,
+            vec![(0x200, 0x204), (0x204, 0x206), (0x206, 0x20A), (0x20A, 0x20E)]
+        );
+        assert_eq!(
+            edges(&graph),
+            vec![
+                (0x200, 0x204, EdgeKind::Next),
+                (0x200, 0x206, EdgeKind::Skip),
+                (0x204, 0x20A, EdgeKind::Call),
+                (0x204, 0x206, EdgeKind::Return),
+                (0x206, 0x200, EdgeKind::Jump),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_subroutines() {
+        let graph = graph(
+            "
+                CALL outer
+                EXIT
+            outer:
+                SNE V0, 0
+                RET
+                CALL inner
+                RET
+            inner:
+                RET
+            ",
+        );
+
+        let subroutines: Vec<Subroutine> = graph.subroutines().cloned().collect();
+        assert_eq!(
+            subroutines,
+            vec![
+                Subroutine {
+                    entry: 0x204,
+                    blocks: BTreeSet::from([0x204, 0x206, 0x208, 0x20A]),
+                    calls: BTreeSet::from([0x20C]),
+                },
+                Subroutine {
+                    entry: 0x20C,
+                    blocks: BTreeSet::from([0x20C]),
+                    calls: BTreeSet::new(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_computed_jump() {
+        let graph = graph(
+            "
+                LD V0, 2
+                JP V0, table
+            table:
+                JP table
+            ",
+        );
+
+        assert_eq!(graph.computed_jumps(), &[0x202]);
+        assert_eq!(graph.blocks().count(), 1);
+        assert!(graph.is_code(0x203));
+        assert!(!graph.is_code(0x204));
+        assert!(graph.to_dot().contains("\"0x200\" -> computed [color=red];"));
+    }
+
+    #[test]
+    fn test_jump_into_data() {
+        // JP 0x204; an invalid instruction; CLS; and a jump past the end of the ROM.
+        let rom: &[u8] = &[0x12, 0x04, 0xFF, 0xFF, 0x00, 0xE0, 0x13, 0x00];
+
+        let graph = ControlFlowGraph::new(rom);
+
+        assert_eq!(edges(&graph), vec![(0x200, 0x204, EdgeKind::Jump)]);
+        assert_eq!(
+            graph.block(0x204).unwrap().instructions,
+            vec![(0x204, Instruction::Clear), (0x206, Instruction::Jump(Addr(0x300)))]
+        );
+        assert!(graph.block(0x300).is_none());
+    }
+
+    #[test]
+    fn test_instructions() {
+        // LD VA, 0x02; RET; data
+        let rom: &[u8] = &[0x6A, 0x02, 0x00, 0xEE, 0xF0];
+
+        let graph = ControlFlowGraph::new(rom);
+
+        let instructions: Vec<(u16, Instruction)> = graph.instructions().collect();
+        assert_eq!(
+            instructions,
+            vec![
+                (0x200, Instruction::LoadRegisterImmediate(Vx(0xA), Byte(0x2))),
+                (0x202, Instruction::Ret)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let graph = graph(
+            "
+            loop:
+                CALL sub
+                JP loop
+            sub:
+                RET
+            ",
+        );
+
+        assert_eq!(
+            graph.to_dot(),
+            r#"digraph rom {
+    node [shape=box, fontname="monospace"];
+    subgraph "cluster_0x204" {
+        label="sub 0x204";
+        "0x204" [label="0x204: RET\l"];
+    }
+    "0x200" [label="0x200: CALL 0x204\l"];
+    "0x202" [label="0x202: JP 0x200\l"];
+    "0x200" -> "0x204" [label="call", style=bold];
+    "0x200" -> "0x202" [label="return", style=dashed];
+    "0x202" -> "0x200" [label="jump"];
+}
+"#
+        );
+    }
+}
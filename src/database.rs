@@ -0,0 +1,336 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use serde::Deserialize;
+
+use crate::quirks::{Platform, Quirks};
+
+/// The `programs.json` of the community CHIP-8 database bundled with the interpreter.
+const BUNDLED: &str = include_str!("../database/programs.json");
+
+/// Returned when a database is not valid JSON or does not have the layout of `programs.json`.
+#[derive(Debug)]
+pub struct DatabaseError(serde_json::Error);
+
+impl fmt::Display for DatabaseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid rom database: {}", self.0)
+    }
+}
+
+impl std::error::Error for DatabaseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.0)
+    }
+}
+
+/// A program of the database, possibly released as several roms.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Program {
+    pub title: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub authors: Vec<String>,
+    /// The roms of the program keyed by the SHA-1 hash of their bytes.
+    #[serde(default)]
+    pub roms: HashMap<String, Rom>,
+}
+
+/// How a single rom is meant to be run.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Rom {
+    #[serde(default)]
+    pub file: Option<String>,
+    /// The identifiers of the platforms the rom runs on, the preferred one first, e.g.
+    /// `originalChip8` or `xochip`.
+    #[serde(default)]
+    pub platforms: Vec<String>,
+    /// Quirks that differ from those of a platform, keyed by its identifier.
+    #[serde(default)]
+    pub quirky_platforms: HashMap<String, QuirkOverrides>,
+    /// The number of instructions per frame the rom is meant to run at.
+    #[serde(default)]
+    pub tickrate: Option<u32>,
+    #[serde(default)]
+    pub colors: Option<Colors>,
+    /// The Chip-8 keys of the actions of the game, e.g. `up` or `a`.
+    #[serde(default)]
+    pub keys: BTreeMap<String, u8>,
+}
+
+/// The quirks of the database that differ from a platform, `None` where it keeps them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuirkOverrides {
+    /// 8xy6 and 8xyE shift Vx in place, ignoring Vy.
+    pub shift: Option<bool>,
+    /// Fx55 and Fx65 leave I unchanged.
+    pub memory_leave_i_unchanged: Option<bool>,
+    /// Fx55 and Fx65 increment I by x instead of x + 1, which is not supported and ignored.
+    pub memory_increment_by_x: Option<bool>,
+    /// Sprites wrap around the edges of the screen.
+    pub wrap: Option<bool>,
+    /// Bnnn jumps to nnn plus Vx.
+    pub jump: Option<bool>,
+    /// Dxyn waits for the next vertical blank.
+    pub vblank: Option<bool>,
+    /// 8xy1, 8xy2 and 8xy3 reset VF.
+    pub logic: Option<bool>,
+}
+
+impl QuirkOverrides {
+    /// Applies the overrides to `quirks`.
+    pub fn apply(&self, quirks: &mut Quirks) {
+        let overrides = [
+            (self.shift.map(|shift| !shift), &mut quirks.shift_uses_vy),
+            (
+                self.memory_leave_i_unchanged.map(|unchanged| !unchanged),
+                &mut quirks.load_store_increments_i,
+            ),
+            (self.wrap.map(|wrap| !wrap), &mut quirks.clip_sprites),
+            (self.jump, &mut quirks.jump_uses_vx),
+            (self.vblank, &mut quirks.display_wait),
+            (self.logic, &mut quirks.vf_reset),
+        ];
+        for (value, quirk) in overrides {
+            if let Some(value) = value {
+                *quirk = value;
+            }
+        }
+    }
+}
+
+/// The colors of a rom as `#RRGGBB`.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Colors {
+    /// The colors of the combinations of the bitplanes, starting with the background.
+    #[serde(default)]
+    pub pixels: Vec<String>,
+    #[serde(default)]
+    pub buzzer: Option<String>,
+    #[serde(default)]
+    pub silence: Option<String>,
+}
+
+impl Colors {
+    /// The pixel colors as red, green and blue, `None` if any of them is not `#RRGGBB`.
+    pub fn pixel_rgb(&self) -> Option<Vec<[u8; 3]>> {
+        self.pixels.iter().map(|color| parse_color(color)).collect()
+    }
+}
+
+fn parse_color(color: &str) -> Option<[u8; 3]> {
+    let hex = color.strip_prefix('#')?;
+    if hex.len() != 6 {
+        return None;
+    }
+
+    let component = |index: usize| u8::from_str_radix(hex.get(index..index + 2)?, 16).ok();
+    Some([component(0)?, component(2)?, component(4)?])
+}
+
+impl Rom {
+    /// The first of the platforms of the rom that can be emulated, with its quirks and the
+    /// overrides of the rom applied.
+    pub fn quirks(&self) -> Option<(Platform, Quirks)> {
+        self.platforms.iter().find_map(|id| {
+            let platform = platform(id)?;
+            let mut quirks = platform.quirks();
+            if let Some(overrides) = self.quirky_platforms.get(id) {
+                overrides.apply(&mut quirks);
+            }
+            Some((platform, quirks))
+        })
+    }
+}
+
+/// The platform of a database platform identifier, `None` for those that cannot be emulated.
+fn platform(id: &str) -> Option<Platform> {
+    match id {
+        "originalChip8" | "hybridVIP" => Some(Platform::CosmacVip),
+        "modernChip8" | "chip48" => Some(Platform::Chip48),
+        "superchip1" | "superchip" => Some(Platform::SuperChip),
+        "xochip" => Some(Platform::XoChip),
+        _ => None,
+    }
+}
+
+/// A program and the rom that matched, see [`Database::lookup`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entry<'a> {
+    pub program: &'a Program,
+    pub rom: &'a Rom,
+}
+
+/// Programs and how to run their roms, in the format of the `programs.json` of the community
+/// CHIP-8 database (<https://github.com/chip-8/chip-8-database>). Roms are found by the SHA-1
+/// hash of their bytes, so the separate `sha1-hashes.json` is not needed.
+#[derive(Debug, Clone, Default)]
+pub struct Database {
+    programs: Vec<Program>,
+    /// The index into `programs` of every rom hash.
+    hashes: HashMap<String, usize>,
+}
+
+impl Database {
+    /// The database bundled with the interpreter.
+    pub fn bundled() -> Database {
+        Database::from_json(BUNDLED).expect("the bundled rom database is valid")
+    }
+
+    pub fn from_json(json: &str) -> Result<Database, DatabaseError> {
+        let programs: Vec<Program> = serde_json::from_str(json).map_err(DatabaseError)?;
+
+        let mut hashes = HashMap::new();
+        for (index, program) in programs.iter().enumerate() {
+            for hash in program.roms.keys() {
+                hashes.insert(hash.to_ascii_lowercase(), index);
+            }
+        }
+
+        Ok(Database { programs, hashes })
+    }
+
+    pub fn programs(&self) -> &[Program] {
+        &self.programs
+    }
+
+    /// The program and rom whose hash matches `rom`.
+    pub fn lookup(&self, rom: &[u8]) -> Option<Entry<'_>> {
+        self.lookup_hash(&sha1(rom))
+    }
+
+    /// The program and rom with the SHA-1 `hash`, in hex.
+    pub fn lookup_hash(&self, hash: &str) -> Option<Entry<'_>> {
+        let hash = hash.to_ascii_lowercase();
+        let program = &self.programs[*self.hashes.get(&hash)?];
+        let rom = program
+            .roms
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(&hash))
+            .map(|(_, rom)| rom)?;
+
+        Some(Entry { program, rom })
+    }
+}
+
+/// The SHA-1 hash of `rom` in lowercase hex, as used to key the database.
+pub fn sha1(rom: &[u8]) -> String {
+    sha1_smol::Sha1::from(rom).digest().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    const ROM: &[u8] = &[0x00, 0xE0, 0x12, 0x00];
+
+    fn database() -> Database {
+        let json = format!(
+            r##"[
+                {{
+                    "title": "Test",
+                    "authors": ["Someone"],
+                    "release": "2024",
+                    "roms": {{
+                        "{}": {{
+                            "file": "test.ch8",
+                            "platforms": ["megachip8", "superchip", "xochip"],
+                            "quirkyPlatforms": {{ "superchip": {{ "shift": false, "vblank": true }} }},
+                            "tickrate": 30,
+                            "colors": {{ "pixels": ["#000000", "#FF8000"] }},
+                            "keys": {{ "up": 5, "down": 8 }}
+                        }}
+                    }}
+                }},
+                {{ "title": "Unknown", "roms": {{}} }}
+            ]"##,
+            sha1(ROM).to_uppercase()
+        );
+
+        Database::from_json(&json).unwrap()
+    }
+
+    #[test]
+    fn test_sha1() {
+        assert_eq!(sha1(b"abc"), "a9993e364706816aba3e25717850c26c9cd0d89d");
+    }
+
+    #[test]
+    fn test_lookup() {
+        let database = database();
+
+        let entry = database.lookup(ROM).unwrap();
+
+        assert_eq!(entry.program.title, "Test");
+        assert_eq!(entry.program.authors, vec!["Someone"]);
+        assert_eq!(entry.rom.file.as_deref(), Some("test.ch8"));
+        assert_eq!(entry.rom.tickrate, Some(30));
+        assert_eq!(entry.rom.keys["up"], 5);
+        assert!(database.lookup(&[0x00, 0xE0]).is_none());
+    }
+
+    #[test]
+    fn test_quirks() {
+        let database = database();
+        let rom = database.lookup(ROM).unwrap().rom;
+
+        let (platform, quirks) = rom.quirks().unwrap();
+
+        assert_eq!(platform, Platform::SuperChip);
+        assert_eq!(
+            quirks,
+            Quirks {
+                shift_uses_vy: true,
+                display_wait: true,
+                ..Platform::SuperChip.quirks()
+            }
+        );
+        assert_eq!(Rom::default().quirks(), None);
+    }
+
+    #[test]
+    fn test_colors() {
+        let colors = database().lookup(ROM).unwrap().rom.colors.clone().unwrap();
+
+        assert_eq!(colors.pixel_rgb(), Some(vec![[0, 0, 0], [0xFF, 0x80, 0x00]]));
+    }
+
+    #[test_case("#0a0B0c" => Some([0x0A, 0x0B, 0x0C]))]
+    #[test_case("0a0b0c" => None)]
+    #[test_case("#fff" => None)]
+    #[test_case("#gg0000" => None)]
+    fn test_parse_color(color: &str) -> Option<[u8; 3]> {
+        parse_color(color)
+    }
+
+    #[test]
+    fn test_invalid_database() {
+        assert!(Database::from_json("{}").is_err());
+        assert!(Database::from_json("[{\"roms\": {}}]").is_err());
+    }
+
+    #[test]
+    fn test_bundled() {
+        let database = Database::bundled();
+
+        for program in database.programs() {
+            for (hash, rom) in &program.roms {
+                assert_eq!(hash.len(), 40, "{}", program.title);
+                assert!(database.lookup_hash(hash).is_some(), "{}", program.title);
+                if let Some(colors) = &rom.colors {
+                    assert!(
+                        colors.pixels.is_empty() || colors.pixel_rgb().is_some(),
+                        "{}",
+                        program.title
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,889 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+
+use crate::{
+    disassembler::{Item, Line},
+    headless,
+    instruction::Instruction,
+    interpreter::{ExecutionError, Interpreter},
+};
+
+/// The number of instructions `list` shows before the address.
+const LIST_BEFORE: u16 = 4;
+/// The number of lines `list` shows.
+const LIST_LINES: usize = 10;
+/// The number of bytes `mem` shows without a length.
+const DEFAULT_DUMP_LEN: usize = 64;
+const BYTES_PER_DUMP_LINE: usize = 16;
+
+const HELP: &str = "\
+Execution:
+  s, step [N]           Execute N instructions, 1 by default
+  n, next               Execute the next instruction, stepping over subroutine calls
+  f, finish             Run until the current subroutine returns
+  c, continue           Run until a breakpoint is hit
+  b, break ADDR         Pause before the instruction at ADDR is executed
+  d, delete ADDR        Remove the breakpoint at ADDR
+  breakpoints           List the breakpoints
+  watch ADDR [LEN]      Pause after an instruction writes LEN bytes of memory, 1 by default
+  rwatch ADDR [LEN]     Pause after an instruction reads memory, e.g. a sprite
+  awatch ADDR [LEN]     Pause after an instruction reads or writes memory
+  watch REG             Pause after an instruction changes a register
+  unwatch N             Remove watchpoint N
+  watchpoints           List the watchpoints
+Inspection:
+  r, regs               Print all registers and the call stack
+  p, print REG          Print V0-VF, I, PC, SP, DT or ST
+  set REG VALUE         Change a register
+  x, mem ADDR [LEN]     Print LEN bytes of memory
+  w, write ADDR BYTE..  Change memory
+  l, list [ADDR]        Disassemble around ADDR, the program counter by default
+  display               Print the screen
+  q, quit               Stop the program
+Numbers are decimal or hexadecimal with a 0x prefix. An empty line repeats the last step.";
+
+/// A register that can be printed and changed from the debugger.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    V(u8),
+    I,
+    Pc,
+    Sp,
+    Delay,
+    Sound,
+}
+
+impl FromStr for Register {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let register = match s.to_ascii_uppercase().as_str() {
+            "I" => Register::I,
+            "PC" => Register::Pc,
+            "SP" => Register::Sp,
+            "DT" => Register::Delay,
+            "ST" => Register::Sound,
+            name => name
+                .strip_prefix('V')
+                .filter(|x| x.len() == 1)
+                .and_then(|x| u8::from_str_radix(x, 16).ok())
+                .map(Register::V)
+                .ok_or_else(|| format!("Unknown register `{}`", s))?,
+        };
+
+        Ok(register)
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Register::V(x) => write!(f, "V{:X}", x),
+            Register::I => write!(f, "I"),
+            Register::Pc => write!(f, "PC"),
+            Register::Sp => write!(f, "SP"),
+            Register::Delay => write!(f, "DT"),
+            Register::Sound => write!(f, "ST"),
+        }
+    }
+}
+
+/// The memory accesses a watchpoint pauses at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+/// Pauses the debugger after an instruction accessed memory or changed a register. Only memory
+/// accessed through I counts, e.g. by Fx55, Fx65, Fx33 and Dxyn, but not instruction fetches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Watchpoint {
+    Memory { address: u16, len: usize, access: Access },
+    Register(Register),
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Watchpoint::Memory { address, len, access } => {
+                let access = match access {
+                    Access::Read => "read",
+                    Access::Write => "write",
+                    Access::ReadWrite => "access",
+                };
+                write!(f, "{} {:#05X}", access, address)?;
+                if *len > 1 {
+                    write!(f, "-{:#05X}", *address as usize + len - 1)?;
+                }
+                Ok(())
+            }
+            Watchpoint::Register(register) => write!(f, "change {}", register),
+        }
+    }
+}
+
+/// A line entered at the debugger prompt, see `help` for the syntax.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Step(u32),
+    Next,
+    Finish,
+    Continue,
+    Break(u16),
+    Delete(u16),
+    Breakpoints,
+    Watch(Watchpoint),
+    Unwatch(usize),
+    Watchpoints,
+    Registers,
+    Print(Register),
+    Set(Register, u16),
+    Memory { address: u16, len: usize },
+    Write { address: u16, bytes: Vec<u8> },
+    List(Option<u16>),
+    Display,
+    Help,
+    Quit,
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        let name = words.next().unwrap_or_default();
+        let arguments: Vec<&str> = words.collect();
+
+        let argument = |index: usize| -> Result<&str, String> {
+            arguments
+                .get(index)
+                .copied()
+                .ok_or_else(|| format!("`{}` is missing an argument, see `help`", name))
+        };
+        let address = |index: usize| argument(index).and_then(|text| parse_number(text, 0xFFFF));
+
+        let command = match name {
+            "s" | "step" => match arguments.first() {
+                Some(count) => Command::Step(parse_number(count, u32::MAX.into())? as u32),
+                None => Command::Step(1),
+            },
+            "n" | "next" => Command::Next,
+            "f" | "finish" => Command::Finish,
+            "c" | "continue" => Command::Continue,
+            "b" | "break" => Command::Break(address(0)? as u16),
+            "d" | "delete" => Command::Delete(address(0)? as u16),
+            "breakpoints" => Command::Breakpoints,
+            "watch" | "rwatch" | "awatch" => match argument(0)?.parse() {
+                Ok(register) if name == "watch" => Command::Watch(Watchpoint::Register(register)),
+                Ok(_) => return Err(String::from("Registers can only be watched for changes with `watch`")),
+                Err(_) => Command::Watch(Watchpoint::Memory {
+                    address: address(0)? as u16,
+                    len: match arguments.get(1) {
+                        Some(len) => parse_number(len, 0x10000)? as usize,
+                        None => 1,
+                    },
+                    access: match name {
+                        "rwatch" => Access::Read,
+                        "awatch" => Access::ReadWrite,
+                        _ => Access::Write,
+                    },
+                }),
+            },
+            "unwatch" => Command::Unwatch(parse_number(argument(0)?, u32::MAX.into())? as usize),
+            "watchpoints" => Command::Watchpoints,
+            "r" | "regs" => Command::Registers,
+            "p" | "print" => Command::Print(argument(0)?.parse()?),
+            "set" => Command::Set(argument(0)?.parse()?, parse_number(argument(1)?, 0xFFFF)? as u16),
+            "x" | "mem" => Command::Memory {
+                address: address(0)? as u16,
+                len: match arguments.get(1) {
+                    Some(len) => parse_number(len, 0x10000)? as usize,
+                    None => DEFAULT_DUMP_LEN,
+                },
+            },
+            "w" | "write" => Command::Write {
+                address: address(0)? as u16,
+                bytes: match &arguments[1..] {
+                    [] => return Err(format!("`{}` is missing the bytes to write", name)),
+                    bytes => bytes
+                        .iter()
+                        .map(|byte| parse_number(byte, 0xFF).map(|byte| byte as u8))
+                        .collect::<Result<_, _>>()?,
+                },
+            },
+            "l" | "list" => Command::List(arguments.first().map(|_| address(0)).transpose()?.map(|a| a as u16)),
+            "display" => Command::Display,
+            "h" | "help" => Command::Help,
+            "q" | "quit" => Command::Quit,
+            _ => return Err(format!("Unknown command `{}`, see `help`", name)),
+        };
+
+        Ok(command)
+    }
+}
+
+/// Parses a decimal or `0x` prefixed hexadecimal number of at most `max`.
+fn parse_number(text: &str, max: u64) -> Result<u64, String> {
+    let number = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+
+    number
+        .ok()
+        .filter(|number| *number <= max)
+        .ok_or_else(|| format!("`{}` is not a number up to {:#X}", text, max))
+}
+
+/// Why the debugger paused, shown when the prompt is entered.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Pause {
+    Requested,
+    Breakpoint(u16),
+    /// A description of the access and the instruction responsible.
+    Watchpoint(String),
+    Stepped,
+    Error(ExecutionError),
+}
+
+/// When a stepping command pauses again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stop {
+    /// After this many more instructions.
+    Steps(u32),
+    /// As soon as the stack pointer is at most this deep, i.e. after a call returned.
+    Depth(u8),
+}
+
+/// What the prompt does after a command.
+enum Action {
+    Prompt,
+    Resume,
+    Quit,
+}
+
+/// An interactive debugger, which runs the interpreter frame by frame like
+/// [`Interpreter::run_frame`] and pauses at breakpoints and after stepping commands. Commands are
+/// read by [`Debugger::repl`] while it is paused.
+pub struct Debugger {
+    breakpoints: BTreeSet<u16>,
+    watchpoints: Vec<Watchpoint>,
+    pause: Option<Pause>,
+    stop: Option<Stop>,
+    /// The instructions left of a frame that was interrupted by a pause.
+    remaining: u32,
+    /// Set when resuming, so that a breakpoint at the program counter does not pause right away.
+    resumed: bool,
+    /// The last stepping command, repeated by an empty line.
+    repeat: Option<Command>,
+}
+
+impl Debugger {
+    /// Creates a debugger without breakpoints that is paused before the first instruction.
+    pub fn new() -> Self {
+        Debugger {
+            breakpoints: BTreeSet::new(),
+            watchpoints: Vec::new(),
+            pause: Some(Pause::Requested),
+            stop: None,
+            remaining: 0,
+            resumed: false,
+            repeat: None,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.pause.is_some()
+    }
+
+    /// Pauses before the next instruction, e.g. when the user presses a key.
+    pub fn pause(&mut self) {
+        self.pause.get_or_insert(Pause::Requested);
+    }
+
+    /// Continues until a breakpoint or watchpoint pauses again.
+    pub fn resume(&mut self) {
+        self.stop = None;
+        self.unpause();
+    }
+
+    /// Continues for `steps` instructions, at least one.
+    pub fn step(&mut self, steps: u32) {
+        self.stop = Some(Stop::Steps(steps.max(1)));
+        self.unpause();
+    }
+
+    fn unpause(&mut self) {
+        self.pause = None;
+        self.resumed = true;
+    }
+
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.breakpoints.insert(address);
+    }
+
+    /// Returns whether there was a breakpoint at `address`.
+    pub fn remove_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.push(watchpoint);
+    }
+
+    /// Removes the watchpoint at `index` of [`Debugger::watchpoints`].
+    pub fn remove_watchpoint(&mut self, index: usize) -> Option<Watchpoint> {
+        (index < self.watchpoints.len()).then(|| self.watchpoints.remove(index))
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    /// Runs the current frame until it is complete or the debugger pauses, and returns whether it
+    /// completed. A paused frame is continued by the next call once the debugger was resumed.
+    ///
+    /// A failing instruction pauses the debugger as well, it is executed again after resuming.
+    pub fn run_frame(&mut self, interpreter: &mut Interpreter, cycles_per_frame: u32) -> Result<bool, ExecutionError> {
+        if self.is_paused() {
+            return Ok(false);
+        }
+
+        if self.remaining == 0 {
+            interpreter.vblank();
+            self.remaining = cycles_per_frame;
+        }
+
+        while self.remaining > 0 && !interpreter.has_exited() {
+            let pc = interpreter.registers().pc;
+            if !self.resumed && self.breakpoints.contains(&pc) {
+                self.pause = Some(Pause::Breakpoint(pc));
+                return Ok(false);
+            }
+            self.resumed = false;
+
+            // Decoded up front, the instruction might overwrite itself.
+            let instruction = (!self.watchpoints.is_empty()).then(|| line(interpreter, pc));
+            let before = self.watched_registers(interpreter);
+
+            if let Err(e) = interpreter.step() {
+                self.pause = Some(Pause::Error(e));
+                return Err(e);
+            }
+            self.remaining -= 1;
+
+            let hit = instruction.and_then(|instruction| self.watchpoint_hit(interpreter, &before, &instruction));
+            if self.stop_reached(interpreter) || hit.is_some() {
+                self.stop = None;
+                self.pause = Some(hit.map_or(Pause::Stepped, Pause::Watchpoint));
+                if self.remaining > 0 {
+                    return Ok(false);
+                }
+            }
+        }
+
+        self.remaining = 0;
+        interpreter.tick_timers();
+        Ok(true)
+    }
+
+    /// The values of the watched registers, one per watchpoint.
+    fn watched_registers(&self, interpreter: &Interpreter) -> Vec<u16> {
+        self.watchpoints
+            .iter()
+            .map(|watchpoint| match watchpoint {
+                Watchpoint::Register(register) => read_register(interpreter, *register),
+                Watchpoint::Memory { .. } => 0,
+            })
+            .collect()
+    }
+
+    /// Describes the first watchpoint triggered by `instruction`, `before` are the watched
+    /// registers before it was executed.
+    fn watchpoint_hit(&self, interpreter: &Interpreter, before: &[u16], instruction: &Line) -> Option<String> {
+        let access = interpreter.last_memory_access();
+
+        self.watchpoints
+            .iter()
+            .zip(before)
+            .find_map(|(watchpoint, before)| match *watchpoint {
+                Watchpoint::Memory {
+                    address,
+                    len,
+                    access: watched,
+                } => {
+                    let access = access?;
+                    let start = access.address.max(address as usize);
+                    let end = (access.address + access.len).min(address as usize + len);
+                    let matches = match watched {
+                        Access::Read => !access.write,
+                        Access::Write => access.write,
+                        Access::ReadWrite => true,
+                    };
+                    let verb = if access.write { "written" } else { "read" };
+
+                    (start < end && matches).then(|| format!("{:#05X} {} by {}", start, verb, instruction))
+                }
+                Watchpoint::Register(register) => {
+                    let after = read_register(interpreter, register);
+                    (after != *before).then(|| {
+                        format!(
+                            "{} changed from {:#X} to {:#X} by {}",
+                            register, before, after, instruction
+                        )
+                    })
+                }
+            })
+    }
+
+    fn stop_reached(&mut self, interpreter: &Interpreter) -> bool {
+        match &mut self.stop {
+            Some(Stop::Steps(steps)) => {
+                *steps -= 1;
+                *steps == 0
+            }
+            Some(Stop::Depth(depth)) => interpreter.registers().sp <= *depth,
+            None => false,
+        }
+    }
+
+    /// Shows why the debugger paused and executes commands from `input` until one resumes
+    /// execution. Returns `Ok(false)` if the user quit or the input ended.
+    pub fn repl(
+        &mut self,
+        interpreter: &mut Interpreter,
+        input: &mut impl BufRead,
+        output: &mut impl Write,
+    ) -> io::Result<bool> {
+        match &self.pause {
+            Some(Pause::Breakpoint(address)) => writeln!(output, "Breakpoint at {:#05X}", address)?,
+            Some(Pause::Watchpoint(hit)) => writeln!(output, "Watchpoint: {}", hit)?,
+            Some(Pause::Error(e)) => writeln!(output, "Error: {}", e)?,
+            _ => {}
+        }
+        if interpreter.has_exited() {
+            writeln!(output, "The program exited")?;
+        } else {
+            writeln!(output, "{}", line(interpreter, interpreter.registers().pc))?;
+        }
+
+        loop {
+            write!(output, "(chip8) ")?;
+            output.flush()?;
+
+            let mut text = String::new();
+            if input.read_line(&mut text)? == 0 {
+                return Ok(false);
+            }
+
+            let command = match (text.trim(), &self.repeat) {
+                ("", Some(command)) => command.clone(),
+                ("", None) => continue,
+                (text, _) => match text.parse() {
+                    Ok(command) => command,
+                    Err(e) => {
+                        writeln!(output, "{}", e)?;
+                        continue;
+                    }
+                },
+            };
+
+            match self.execute(interpreter, command, output)? {
+                Action::Prompt => {}
+                Action::Resume => {
+                    self.unpause();
+                    return Ok(true);
+                }
+                Action::Quit => return Ok(false),
+            }
+        }
+    }
+
+    fn execute(
+        &mut self,
+        interpreter: &mut Interpreter,
+        command: Command,
+        output: &mut impl Write,
+    ) -> io::Result<Action> {
+        if let Command::Step(_) | Command::Next | Command::Finish = command {
+            self.repeat = Some(command.clone());
+        }
+
+        let sp = interpreter.registers().sp;
+        match command {
+            Command::Step(steps) => {
+                self.stop = Some(Stop::Steps(steps.max(1)));
+                return Ok(Action::Resume);
+            }
+            // A call returns to the same depth, every other instruction stays there or returns.
+            Command::Next => {
+                self.stop = Some(Stop::Depth(sp));
+                return Ok(Action::Resume);
+            }
+            Command::Finish => match sp.checked_sub(1) {
+                Some(depth) => {
+                    self.stop = Some(Stop::Depth(depth));
+                    return Ok(Action::Resume);
+                }
+                None => writeln!(output, "Not in a subroutine")?,
+            },
+            Command::Continue => {
+                self.stop = None;
+                return Ok(Action::Resume);
+            }
+            Command::Break(address) => {
+                self.add_breakpoint(address);
+                writeln!(output, "Breakpoint at {:#05X}", address)?;
+            }
+            Command::Delete(address) => {
+                if !self.remove_breakpoint(address) {
+                    writeln!(output, "No breakpoint at {:#05X}", address)?;
+                }
+            }
+            Command::Breakpoints => {
+                if self.breakpoints.is_empty() {
+                    writeln!(output, "No breakpoints")?;
+                }
+                for address in self.breakpoints() {
+                    writeln!(output, "{}", line(interpreter, address))?;
+                }
+            }
+            Command::Watch(watchpoint) => {
+                self.add_watchpoint(watchpoint);
+                writeln!(output, "Watchpoint {}: {}", self.watchpoints.len(), watchpoint)?;
+            }
+            Command::Unwatch(number) => {
+                if number
+                    .checked_sub(1)
+                    .and_then(|index| self.remove_watchpoint(index))
+                    .is_none()
+                {
+                    writeln!(output, "No watchpoint {}", number)?;
+                }
+            }
+            Command::Watchpoints => {
+                if self.watchpoints.is_empty() {
+                    writeln!(output, "No watchpoints")?;
+                }
+                for (index, watchpoint) in self.watchpoints.iter().enumerate() {
+                    writeln!(output, "{}: {}", index + 1, watchpoint)?;
+                }
+            }
+            Command::Registers => print_registers(interpreter, output)?,
+            Command::Print(register) => {
+                let value = read_register(interpreter, register);
+                writeln!(output, "{} = {:#X} ({})", register, value, value)?;
+            }
+            Command::Set(register, value) => {
+                if let Err(e) = write_register(interpreter, register, value) {
+                    writeln!(output, "{}", e)?;
+                }
+            }
+            Command::Memory { address, len } => {
+                let start = address as usize;
+                let end = (start + len).min(interpreter.ram().len());
+                if start >= end {
+                    writeln!(output, "{:#05X} is past the end of memory", address)?;
+                }
+                for (row, bytes) in interpreter.ram()[start.min(end)..end]
+                    .chunks(BYTES_PER_DUMP_LINE)
+                    .enumerate()
+                {
+                    let hex: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+                    writeln!(output, "{:#05X}: {}", start + row * BYTES_PER_DUMP_LINE, hex.join(" "))?;
+                }
+            }
+            Command::Write { address, bytes } => {
+                let start = address as usize;
+                match interpreter.ram_mut().get_mut(start..start + bytes.len()) {
+                    Some(memory) => memory.copy_from_slice(&bytes),
+                    None => writeln!(
+                        output,
+                        "Writing {} bytes at {:#05X} leaves memory",
+                        bytes.len(),
+                        address
+                    )?,
+                }
+            }
+            Command::List(address) => {
+                let pc = interpreter.registers().pc;
+                let address = address.unwrap_or(pc);
+                let mut current = address.saturating_sub(LIST_BEFORE * 2);
+                for _ in 0..LIST_LINES {
+                    if current as usize >= interpreter.ram().len() {
+                        break;
+                    }
+                    let line = line(interpreter, current);
+                    let marker = if current == pc { "=>" } else { "  " };
+                    writeln!(output, "{} {}", marker, line)?;
+                    current = match &line.item {
+                        Item::Instruction(instruction) => current.wrapping_add(instruction.size()),
+                        Item::Data(bytes) => current.wrapping_add(bytes.len() as u16),
+                    };
+                }
+            }
+            Command::Display => write!(output, "{}", headless::to_ascii(interpreter.display()))?,
+            Command::Help => writeln!(output, "{}", HELP)?,
+            Command::Quit => return Ok(Action::Quit),
+        }
+
+        Ok(Action::Prompt)
+    }
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The instruction at `address`, or its bytes if they do not encode one.
+fn line(interpreter: &Interpreter, address: u16) -> Line {
+    let ram = interpreter.ram();
+    let start = (address as usize).min(ram.len());
+    let bytes = &ram[start..(start + 4).min(ram.len())];
+
+    let item = match Instruction::decode_bytes(bytes) {
+        Ok(instruction) => Item::Instruction(instruction),
+        Err(_) => Item::Data(bytes[..bytes.len().min(2)].to_vec()),
+    };
+
+    Line { address, item }
+}
+
+fn print_registers(interpreter: &Interpreter, output: &mut impl Write) -> io::Result<()> {
+    let registers = interpreter.registers();
+
+    for (row, values) in registers.vx.chunks(8).enumerate() {
+        let values: Vec<String> = values
+            .iter()
+            .enumerate()
+            .map(|(x, value)| format!("V{:X}={:02X}", row * 8 + x, value))
+            .collect();
+        writeln!(output, "{}", values.join(" "))?;
+    }
+    writeln!(
+        output,
+        "I={:04X} PC={:04X} SP={:X} DT={:02X} ST={:02X}",
+        registers.i, registers.pc, registers.sp, registers.delay, registers.sound
+    )?;
+
+    let stack: Vec<String> = registers.stack[..registers.sp as usize]
+        .iter()
+        .rev()
+        .map(|address| format!("{:#05X}", address))
+        .collect();
+    if !stack.is_empty() {
+        writeln!(output, "Called from {}", stack.join(" <- "))?;
+    }
+
+    Ok(())
+}
+
+pub(crate) fn read_register(interpreter: &Interpreter, register: Register) -> u16 {
+    let registers = interpreter.registers();
+    match register {
+        Register::V(x) => registers.vx[x as usize].into(),
+        Register::I => registers.i,
+        Register::Pc => registers.pc,
+        Register::Sp => registers.sp.into(),
+        Register::Delay => registers.delay.into(),
+        Register::Sound => registers.sound.into(),
+    }
+}
+
+/// Fails if `value` does not fit into the register.
+pub(crate) fn write_register(interpreter: &mut Interpreter, register: Register, value: u16) -> Result<(), String> {
+    let max = match register {
+        Register::I | Register::Pc => 0xFFFF,
+        Register::Sp => interpreter.registers().stack.len() as u16,
+        Register::V(_) | Register::Delay | Register::Sound => 0xFF,
+    };
+    if value > max {
+        return Err(format!("{} can be at most {:#X}", register, max));
+    }
+
+    let registers = interpreter.registers_mut();
+    match register {
+        Register::V(x) => registers.vx[x as usize] = value as u8,
+        Register::I => registers.i = value,
+        Register::Pc => registers.pc = value,
+        Register::Sp => registers.sp = value as u8,
+        Register::Delay => registers.delay = value as u8,
+        Register::Sound => registers.sound = value as u8,
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    // 0x200: CALL 0x206; JP 0x200; ADD V0, 1; ADD V1, 1; RET
+    const ROM: &[u8] = &[0x22, 0x06, 0x12, 0x00, 0x00, 0x00, 0x70, 0x01, 0x71, 0x01, 0x00, 0xEE];
+
+    #[test_case("s" => Command::Step(1))]
+    #[test_case("step 0x10" => Command::Step(16))]
+    #[test_case("b 0x208" => Command::Break(0x208))]
+    #[test_case("set vA 255" => Command::Set(Register::V(0xA), 255))]
+    #[test_case("p pc" => Command::Print(Register::Pc))]
+    #[test_case("x 0x300" => Command::Memory { address: 0x300, len: DEFAULT_DUMP_LEN })]
+    #[test_case("w 0x300 1 0x2" => Command::Write { address: 0x300, bytes: vec![1, 2] })]
+    #[test_case("l" => Command::List(None))]
+    #[test_case("watch vF" => Command::Watch(Watchpoint::Register(Register::V(0xF))))]
+    #[test_case("rwatch 0x300 3" => Command::Watch(Watchpoint::Memory { address: 0x300, len: 3, access: Access::Read }))]
+    #[test_case("awatch 0x300" => Command::Watch(Watchpoint::Memory { address: 0x300, len: 1, access: Access::ReadWrite }))]
+    fn test_parse_command(text: &str) -> Command {
+        text.parse().unwrap()
+    }
+
+    #[test_case("jump")]
+    #[test_case("b")]
+    #[test_case("b 0x10000")]
+    #[test_case("p vg")]
+    #[test_case("w 0x300 256")]
+    #[test_case("rwatch i")]
+    fn test_parse_invalid_command(text: &str) {
+        assert!(text.parse::<Command>().is_err());
+    }
+
+    /// Runs the commands and returns the output of the prompt.
+    fn repl(debugger: &mut Debugger, interpreter: &mut Interpreter, commands: &str) -> (bool, String) {
+        let mut output = Vec::new();
+        let resumed = debugger
+            .repl(interpreter, &mut commands.as_bytes(), &mut output)
+            .unwrap();
+        (resumed, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn test_breakpoint() {
+        let mut interpreter = Interpreter::with_rom(ROM);
+        let mut debugger = Debugger::new();
+        assert_eq!(debugger.run_frame(&mut interpreter, 10), Ok(false));
+
+        assert!(repl(&mut debugger, &mut interpreter, "b 0x208\nc\n").0);
+        assert_eq!(debugger.run_frame(&mut interpreter, 10), Ok(false));
+        assert_eq!(interpreter.registers().pc, 0x208);
+        assert_eq!(interpreter.registers().vx[0], 1);
+
+        let (resumed, output) = repl(&mut debugger, &mut interpreter, "c\n");
+        assert!(resumed);
+        assert!(output.starts_with("Breakpoint at 0x208\n0x208: 7101  ADD V1, 0x01\n"));
+
+        // The rest of the frame runs from the breakpoint without pausing there right away.
+        assert_eq!(debugger.run_frame(&mut interpreter, 10), Ok(false));
+        assert_eq!(interpreter.registers().pc, 0x208);
+        assert_eq!(interpreter.registers().vx[..2], [2, 1]);
+    }
+
+    #[test]
+    fn test_step_next_finish() {
+        let mut interpreter = Interpreter::with_rom(ROM);
+        let mut debugger = Debugger::new();
+
+        repl(&mut debugger, &mut interpreter, "s\n");
+        debugger.run_frame(&mut interpreter, 10).unwrap();
+        assert_eq!(interpreter.registers().pc, 0x206);
+
+        repl(&mut debugger, &mut interpreter, "f\n");
+        debugger.run_frame(&mut interpreter, 10).unwrap();
+        assert_eq!(interpreter.registers().pc, 0x202);
+        assert_eq!(interpreter.registers().vx[..2], [1, 1]);
+
+        // JP 0x200, then CALL 0x206 is executed as a whole by the repeated next.
+        repl(&mut debugger, &mut interpreter, "n\n\n");
+        debugger.run_frame(&mut interpreter, 10).unwrap();
+        assert_eq!(interpreter.registers().pc, 0x200);
+        repl(&mut debugger, &mut interpreter, "\n");
+        debugger.run_frame(&mut interpreter, 10).unwrap();
+        assert_eq!(interpreter.registers().pc, 0x202);
+        assert_eq!(interpreter.registers().vx[..2], [2, 2]);
+
+        let (_, output) = repl(&mut debugger, &mut interpreter, "finish\nq\n");
+        assert!(output.contains("Not in a subroutine"));
+    }
+
+    #[test]
+    fn test_frame_completes_after_pause() {
+        let mut interpreter = Interpreter::with_rom(ROM);
+        let mut debugger = Debugger::new();
+        interpreter.registers_mut().delay = 2;
+
+        repl(&mut debugger, &mut interpreter, "s 2\n");
+        assert_eq!(debugger.run_frame(&mut interpreter, 5), Ok(false));
+        assert_eq!(interpreter.registers().delay, 2);
+
+        repl(&mut debugger, &mut interpreter, "c\n");
+        assert_eq!(debugger.run_frame(&mut interpreter, 5), Ok(true));
+        assert_eq!(interpreter.registers().delay, 1, "the timers tick once per frame");
+    }
+
+    #[test]
+    fn test_inspect_and_change() {
+        let mut interpreter = Interpreter::with_rom(ROM);
+        let mut debugger = Debugger::new();
+
+        let (resumed, output) = repl(
+            &mut debugger,
+            &mut interpreter,
+            "set v3 0x2A\np v3\nset dt 256\nw 0x300 1 2 3\nx 0x300 4\nl\nq\n",
+        );
+
+        assert!(!resumed);
+        assert_eq!(interpreter.registers().vx[3], 0x2A);
+        assert_eq!(interpreter.ram()[0x300..0x303], [1, 2, 3]);
+        assert!(output.contains("V3 = 0x2A (42)"));
+        assert!(output.contains("DT can be at most 0xFF"));
+        assert!(output.contains("0x300: 01 02 03 00\n"));
+        assert!(output.contains("=> 0x200: 2206  CALL 0x206\n"));
+    }
+
+    #[test]
+    fn test_error_pauses() {
+        let mut interpreter = Interpreter::with_rom(&[0xFF, 0xFF]);
+        let mut debugger = Debugger::new();
+        repl(&mut debugger, &mut interpreter, "c\n");
+
+        assert!(debugger.run_frame(&mut interpreter, 10).is_err());
+
+        let (_, output) = repl(&mut debugger, &mut interpreter, "set pc 0x202\nq\n");
+        assert!(output.starts_with("Error: Unknown instruction: 0xffff at 0x200\n"));
+    }
+
+    #[test]
+    fn test_watchpoints() {
+        // 0x200: LD I, 0x300; LD V0, 5; LD B, V0; DRW V0, V1, 5; ADD V3, 1; JP 0x200
+        let rom = [0xA3, 0x00, 0x60, 0x05, 0xF0, 0x33, 0xD0, 0x15, 0x73, 0x01, 0x12, 0x00];
+        let mut interpreter = Interpreter::with_rom(&rom);
+        let mut debugger = Debugger::new();
+        repl(
+            &mut debugger,
+            &mut interpreter,
+            "rwatch 0x302 2\nawatch 0x301\nwatch v3\nc\n",
+        );
+
+        debugger.run_frame(&mut interpreter, 10).unwrap();
+        let (_, output) = repl(&mut debugger, &mut interpreter, "unwatch 2\nc\n");
+        assert!(output.starts_with("Watchpoint: 0x301 written by 0x204: F033  LD B, V0\n"));
+
+        debugger.run_frame(&mut interpreter, 10).unwrap();
+        let (_, output) = repl(&mut debugger, &mut interpreter, "c\n");
+        assert!(output.starts_with("Watchpoint: 0x302 read by 0x206: D015  DRW V0, V1, 5\n"));
+
+        debugger.run_frame(&mut interpreter, 10).unwrap();
+        let (_, output) = repl(&mut debugger, &mut interpreter, "watchpoints\nq\n");
+        assert!(output.starts_with("Watchpoint: V3 changed from 0x0 to 0x1 by 0x208: 7301  ADD V3, 0x01\n"));
+        assert!(output.contains("1: read 0x302-0x303\n2: change V3\n"));
+    }
+}
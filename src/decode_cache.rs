@@ -0,0 +1,67 @@
+use crate::instruction::Instruction;
+
+/// The longest instruction, `F000 nnnn`, is 4 bytes long.
+const MAX_INSTRUCTION_SIZE: usize = 4;
+
+/// The decoded instruction at every address of memory, so that the instructions of loops are
+/// only decoded once. Writes to memory have to be passed to [`DecodeCache::invalidate`].
+pub(crate) struct DecodeCache(Vec<Option<Instruction>>);
+
+impl DecodeCache {
+    pub fn new(memory_size: usize) -> Self {
+        DecodeCache(vec![None; memory_size])
+    }
+
+    pub fn get(&self, address: usize) -> Option<Instruction> {
+        self.0.get(address).copied().flatten()
+    }
+
+    pub fn insert(&mut self, address: usize, instruction: Instruction) {
+        if let Some(entry) = self.0.get_mut(address) {
+            *entry = Some(instruction);
+        }
+    }
+
+    /// Forgets the instructions that overlap `address..address + len`, including those starting
+    /// up to 3 bytes before it.
+    pub fn invalidate(&mut self, address: usize, len: usize) {
+        let start = address.saturating_sub(MAX_INSTRUCTION_SIZE - 1).min(self.0.len());
+        let end = (address + len).min(self.0.len());
+        for entry in &mut self.0[start..end] {
+            *entry = None;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.0.iter_mut().for_each(|entry| *entry = None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalidate() {
+        let mut cache = DecodeCache::new(0x1000);
+        for address in (0x200..0x210).step_by(2) {
+            cache.insert(address, Instruction::Clear);
+        }
+
+        cache.invalidate(0x206, 2);
+
+        let cached: Vec<usize> = (0x200..0x210).filter(|address| cache.get(*address).is_some()).collect();
+        assert_eq!(cached, vec![0x200, 0x202, 0x208, 0x20A, 0x20C, 0x20E]);
+    }
+
+    #[test]
+    fn test_out_of_range() {
+        let mut cache = DecodeCache::new(0x1000);
+
+        cache.insert(0x1000, Instruction::Clear);
+        cache.invalidate(0xFFF, 4);
+        cache.invalidate(0x2000, 1);
+
+        assert_eq!(cache.get(0x1000), None);
+    }
+}
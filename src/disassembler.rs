@@ -0,0 +1,191 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::{control_flow::ControlFlowGraph, instruction::Instruction, memory::START_ROM};
+
+/// The maximum number of data bytes emitted on a single `db` line.
+const BYTES_PER_DATA_LINE: usize = 8;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Item {
+    Instruction(Instruction),
+    Data(Vec<u8>),
+}
+
+/// A single line of a listing, `address` is the location in memory when the ROM is loaded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    pub address: u16,
+    pub item: Item,
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.item {
+            Item::Instruction(instruction) => {
+                let hex: String = instruction.to_bytes().iter().map(|b| format!("{:02X}", b)).collect();
+                write!(f, "{:#05X}: {:<4}  {}", self.address, hex, instruction)
+            }
+            Item::Data(bytes) => {
+                let values: Vec<String> = bytes.iter().map(|b| format!("{:#04X}", b)).collect();
+                write!(f, "{:#05X}: {:<4}  db {}", self.address, "", values.join(", "))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Listing {
+    pub lines: Vec<Line>,
+}
+
+impl fmt::Display for Listing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in &self.lines {
+            writeln!(f, "{}", line)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Disassembles a ROM as it would be loaded at `START_ROM`.
+///
+/// Code is discovered by following the control flow from the entry point, see
+/// [`ControlFlowGraph`]. Everything that is never reached this way is emitted as `db` data.
+pub fn disassemble(rom: &[u8]) -> Listing {
+    let code: BTreeMap<u16, Instruction> = ControlFlowGraph::new(rom).instructions().collect();
+
+    let mut lines = Vec::new();
+    let mut data: Vec<u8> = Vec::new();
+    let mut data_start = 0;
+    let mut offset = 0;
+
+    while offset < rom.len() {
+        let address = (START_ROM + offset) as u16;
+
+        if let Some(instruction) = code.get(&address) {
+            flush_data(&mut lines, &mut data, data_start);
+            lines.push(Line {
+                address,
+                item: Item::Instruction(*instruction),
+            });
+            offset += instruction.size() as usize;
+        } else {
+            if data.is_empty() {
+                data_start = address;
+            }
+            data.push(rom[offset]);
+            if data.len() == BYTES_PER_DATA_LINE {
+                flush_data(&mut lines, &mut data, data_start);
+            }
+            offset += 1;
+        }
+    }
+
+    flush_data(&mut lines, &mut data, data_start);
+
+    Listing { lines }
+}
+
+fn flush_data(lines: &mut Vec<Line>, data: &mut Vec<u8>, address: u16) {
+    if !data.is_empty() {
+        lines.push(Line {
+            address,
+            item: Item::Data(std::mem::take(data)),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::{Addr, Byte, Vx};
+
+    #[test]
+    fn test_disassemble_follows_jumps() {
+        // JP 0x206, two data bytes, a sprite and CLS at the jump target.
+        let rom: &[u8] = &[0x12, 0x06, 0xF0, 0x90, 0x00, 0x00, 0x00, 0xE0];
+
+        let listing = disassemble(rom);
+
+        assert_eq!(
+            listing.lines,
+            vec![
+                Line {
+                    address: 0x200,
+                    item: Item::Instruction(Instruction::Jump(Addr(0x206)))
+                },
+                Line {
+                    address: 0x202,
+                    item: Item::Data(vec![0xF0, 0x90, 0x00, 0x00])
+                },
+                Line {
+                    address: 0x206,
+                    item: Item::Instruction(Instruction::Clear)
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_disassemble_follows_skips_and_calls() {
+        // SE V1, 0x02; CALL 0x208; RET; RET
+        let rom: &[u8] = &[0x31, 0x02, 0x22, 0x08, 0x00, 0xEE, 0xFF, 0xFF, 0x00, 0xEE];
+
+        let listing = disassemble(rom);
+
+        let instructions: Vec<u16> = listing
+            .lines
+            .iter()
+            .filter(|line| matches!(line.item, Item::Instruction(_)))
+            .map(|line| line.address)
+            .collect();
+        assert_eq!(instructions, vec![0x200, 0x202, 0x204, 0x208]);
+    }
+
+    #[test]
+    fn test_disassemble_long_i() {
+        // SE V0, 0x00; LD I, long 0x1234; CLS
+        let rom: &[u8] = &[0x30, 0x00, 0xF0, 0x00, 0x12, 0x34, 0x00, 0xE0];
+
+        let listing = disassemble(rom);
+
+        assert_eq!(listing.lines.len(), 3);
+        assert_eq!(listing.lines[2].address, 0x206);
+        assert_eq!(listing.lines[1].to_string(), "0x202: F0001234  LD I, long 0x1234");
+    }
+
+    #[test]
+    fn test_disassemble_splits_long_data() {
+        let mut rom = vec![0x00, 0xEE];
+        rom.extend(0..10);
+
+        let listing = disassemble(&rom);
+
+        assert_eq!(listing.lines.len(), 3);
+        assert_eq!(listing.lines[1].item, Item::Data((0..8).collect()));
+        assert_eq!(listing.lines[2].address, 0x20A);
+    }
+
+    #[test]
+    fn test_display_listing() {
+        let listing = Listing {
+            lines: vec![
+                Line {
+                    address: 0x200,
+                    item: Item::Instruction(Instruction::LoadRegisterImmediate(Vx(0xA), Byte(0x2))),
+                },
+                Line {
+                    address: 0x202,
+                    item: Item::Data(vec![0xF0, 0x10]),
+                },
+            ],
+        };
+
+        assert_eq!(
+            listing.to_string(),
+            "0x200: 6A02  LD VA, 0x02\n0x202:       db 0xF0, 0x10\n"
+        );
+    }
+}
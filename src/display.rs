@@ -1,51 +1,181 @@
-const DISPLAY_WIDTH: usize = 64;
-const DISPLAY_HEIGHT: usize = 32;
+use crate::state::{StateError, StateReader, StateWriter};
+
+const LORES_WIDTH: usize = 64;
+const LORES_HEIGHT: usize = 32;
+const HIRES_WIDTH: usize = 128;
+const HIRES_HEIGHT: usize = 64;
+
+/// The number of bitplanes XO-CHIP programs can draw to.
+pub const PLANES: usize = 2;
 
 /// The original implementation of the Chip-8 language used a 64x32-pixel monochrome display with this format:
 /// ( 0, 0)   (63, 0)
 /// ( 0,31)   (63,31)
-pub struct Display([bool; DISPLAY_WIDTH * DISPLAY_HEIGHT]);
+///
+/// SUPER-CHIP added a high resolution mode with 128x64 pixels that programs can switch to.
+///
+/// XO-CHIP added a second bitplane, so every pixel stores one bit per plane and has one of
+/// four colors. Drawing, clearing and scrolling only affect the planes selected with FN01.
+pub struct Display {
+    /// The color index of every pixel, bit `p` is set if the pixel is lit on plane `p`.
+    pixels: Vec<u8>,
+    hires: bool,
+    /// The mask of the planes that are drawn to, cleared and scrolled.
+    selected_planes: u8,
+}
 
 impl Display {
     pub fn new() -> Self {
-        Display([false; DISPLAY_WIDTH * DISPLAY_HEIGHT])
+        Display {
+            pixels: vec![0; LORES_WIDTH * LORES_HEIGHT],
+            hires: false,
+            selected_planes: 0b01,
+        }
     }
 
+    /// Clears the selected planes.
     pub fn clear(&mut self) {
-        for i in &mut self.0 {
-            *i = false
+        for i in &mut self.pixels {
+            *i &= !self.selected_planes
         }
     }
 
+    /// Whether the pixel is lit on any plane.
     pub fn pixel(&self, x: usize, y: usize) -> bool {
-        self.0[self.compute_idx(x, y)]
+        self.color_index(x, y) != 0
     }
 
-    /// Xors the pixel at position (`x`, `y`) and returns `true`
+    /// The color of the pixel, a combination of the bits of all planes in `0..1 << PLANES`.
+    pub fn color_index(&self, x: usize, y: usize) -> u8 {
+        self.pixels[self.compute_idx(x, y)]
+    }
+
+    /// Xors the pixel at position (`x`, `y`) on the first plane and returns `true`
     /// if the pixel was cleared.
     pub fn xor_pixel(&mut self, x: usize, y: usize, value: bool) -> bool {
+        self.xor_plane_pixel(0, x, y, value)
+    }
+
+    /// Xors the pixel at position (`x`, `y`) on `plane` and returns `true`
+    /// if the pixel was cleared.
+    pub fn xor_plane_pixel(&mut self, plane: usize, x: usize, y: usize, value: bool) -> bool {
         let idx = self.compute_idx(x, y);
-        let last_value = self.0[idx];
-        let new_value = last_value ^ value;
-        self.0[idx] = new_value;
+        let bit = 1 << plane;
+        let last_value = self.pixels[idx] & bit != 0;
+        if value {
+            self.pixels[idx] ^= bit;
+        }
 
-        last_value && !new_value
+        last_value && value
     }
 
     pub fn compute_idx(&self, x: usize, y: usize) -> usize {
-        (y % DISPLAY_HEIGHT) * self.width() + (x % DISPLAY_WIDTH)
+        (y % self.height()) * self.width() + (x % self.width())
     }
 
-    pub fn pixels(&self) -> &[bool] {
-        &self.0
+    /// The color index of every pixel, row by row, see [`Display::color_index`].
+    pub fn color_indices(&self) -> &[u8] {
+        &self.pixels
     }
 
     pub fn width(&self) -> usize {
-        DISPLAY_WIDTH
+        if self.hires {
+            HIRES_WIDTH
+        } else {
+            LORES_WIDTH
+        }
     }
 
     pub fn height(&self) -> usize {
-        DISPLAY_HEIGHT
+        if self.hires {
+            HIRES_HEIGHT
+        } else {
+            LORES_HEIGHT
+        }
+    }
+
+    pub fn is_high_resolution(&self) -> bool {
+        self.hires
+    }
+
+    /// Switches between the 64x32 and the 128x64 mode, all planes are cleared when doing so.
+    pub fn set_high_resolution(&mut self, hires: bool) {
+        self.hires = hires;
+        self.pixels = vec![0; self.width() * self.height()];
+    }
+
+    pub fn selected_planes(&self) -> u8 {
+        self.selected_planes
+    }
+
+    /// Selects the planes that are drawn to, cleared and scrolled. Bits for planes that do not
+    /// exist are ignored.
+    pub fn select_planes(&mut self, mask: u8) {
+        self.selected_planes = mask & ((1 << PLANES) - 1);
+    }
+
+    pub(crate) fn write_state(&self, writer: &mut StateWriter) {
+        writer.bool(self.hires);
+        writer.u8(self.selected_planes);
+        writer.vec(&self.pixels);
+    }
+
+    pub(crate) fn read_state(reader: &mut StateReader) -> Result<Self, StateError> {
+        let mut display = Display::new();
+        display.set_high_resolution(reader.bool()?);
+        display.selected_planes = reader.u8()?;
+        let pixels = reader.vec()?;
+
+        if display.selected_planes >= 1 << PLANES {
+            return Err(StateError::Invalid("selected planes"));
+        }
+        if pixels.len() != display.pixels.len() || pixels.iter().any(|color| *color >= 1 << PLANES) {
+            return Err(StateError::Invalid("display pixels"));
+        }
+        display.pixels = pixels;
+
+        Ok(display)
+    }
+
+    /// Moves every pixel `n` rows down, the rows at the top are cleared.
+    pub fn scroll_down(&mut self, n: usize) {
+        self.scroll(0, n as isize);
+    }
+
+    /// Moves every pixel `n` columns to the right, the columns at the left are cleared.
+    pub fn scroll_right(&mut self, n: usize) {
+        self.scroll(n as isize, 0);
+    }
+
+    /// Moves every pixel `n` columns to the left, the columns at the right are cleared.
+    pub fn scroll_left(&mut self, n: usize) {
+        self.scroll(-(n as isize), 0);
+    }
+
+    /// Moves the selected planes by (`dx`, `dy`), pixels scrolled in from outside are cleared.
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let width = self.width() as isize;
+        let height = self.height() as isize;
+        let mask = self.selected_planes;
+        let old = self.pixels.clone();
+
+        for (idx, pixel) in self.pixels.iter_mut().enumerate() {
+            let x = idx as isize % width - dx;
+            let y = idx as isize / width - dy;
+            let moved = if (0..width).contains(&x) && (0..height).contains(&y) {
+                old[(y * width + x) as usize] & mask
+            } else {
+                0
+            };
+
+            *pixel = (*pixel & !mask) | moved;
+        }
+    }
+}
+
+impl Default for Display {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -89,4 +219,63 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_set_high_resolution() {
+        let mut display = Display::new();
+        display.xor_pixel(3, 4, true);
+
+        display.set_high_resolution(true);
+
+        assert_eq!((display.width(), display.height()), (128, 64));
+        assert_eq!(display.color_indices().len(), 128 * 64);
+        assert!(display.color_indices().iter().all(|color| *color == 0));
+
+        display.set_high_resolution(false);
+
+        assert_eq!((display.width(), display.height()), (64, 32));
+    }
+
+    #[test]
+    fn test_scroll() {
+        let mut display = Display::new();
+        display.xor_pixel(10, 10, true);
+        display.xor_pixel(63, 31, true);
+
+        display.scroll_down(2);
+        assert!(display.pixel(10, 12));
+        assert!(!display.pixel(10, 10));
+        assert!(!display.pixel(63, 1), "scrolled out pixels must not wrap");
+
+        display.scroll_right(4);
+        assert!(display.pixel(14, 12));
+        assert!(!display.pixel(10, 12));
+
+        display.scroll_left(4);
+        assert!(display.pixel(10, 12));
+        assert_eq!(display.color_indices().iter().filter(|color| **color != 0).count(), 1);
+    }
+
+    #[test]
+    fn test_planes() {
+        let mut display = Display::new();
+        display.xor_plane_pixel(0, 1, 1, true);
+        display.xor_plane_pixel(1, 1, 1, true);
+        display.xor_plane_pixel(1, 2, 2, true);
+
+        assert_eq!(display.color_index(1, 1), 3);
+        assert_eq!(display.color_index(2, 2), 2);
+
+        display.select_planes(0b10);
+        display.scroll_right(1);
+        assert_eq!(display.color_index(1, 1), 1);
+        assert_eq!(display.color_index(2, 1), 2);
+
+        display.clear();
+        assert_eq!(display.color_index(1, 1), 1);
+        assert_eq!(display.color_index(3, 2), 0);
+
+        display.select_planes(0xFF);
+        assert_eq!(display.selected_planes(), 0b11);
+    }
 }
@@ -0,0 +1,565 @@
+use std::io::{self, BufRead, BufReader, ErrorKind, Write};
+use std::net::TcpStream;
+
+use crate::{
+    debugger::{self, Access, Debugger, Register, Watchpoint},
+    interpreter::{ExecutionError, Interpreter},
+};
+
+/// The registers in the order of `g` packets and register numbers of `p` packets. Every register
+/// is sent big-endian, like the words in memory.
+const REGISTERS: [Register; 21] = [
+    Register::V(0x0),
+    Register::V(0x1),
+    Register::V(0x2),
+    Register::V(0x3),
+    Register::V(0x4),
+    Register::V(0x5),
+    Register::V(0x6),
+    Register::V(0x7),
+    Register::V(0x8),
+    Register::V(0x9),
+    Register::V(0xA),
+    Register::V(0xB),
+    Register::V(0xC),
+    Register::V(0xD),
+    Register::V(0xE),
+    Register::V(0xF),
+    Register::I,
+    Register::Pc,
+    Register::Sp,
+    Register::Delay,
+    Register::Sound,
+];
+
+/// Sent by the client to interrupt a running program.
+const INTERRUPT: u8 = 0x03;
+
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+/// What the server does after handling a packet.
+#[derive(Debug, PartialEq, Eq)]
+enum Response {
+    Reply(String),
+    /// Replies and closes the connection, after a detach.
+    ReplyAndClose(String),
+    /// Closes the connection without a reply, after a kill.
+    Close,
+}
+
+/// A server for the GDB remote serial protocol, to debug programs with gdb or any other client
+/// that speaks it. Breakpoints, watchpoints and single steps are handled by a [`Debugger`].
+///
+/// The program starts stopped before its first instruction. While it runs, the timers tick
+/// after every `cycles_per_frame` instructions, but not in real time.
+pub struct GdbServer {
+    debugger: Debugger,
+    cycles_per_frame: u32,
+}
+
+impl GdbServer {
+    pub fn new(cycles_per_frame: u32) -> Self {
+        GdbServer {
+            debugger: Debugger::new(),
+            cycles_per_frame,
+        }
+    }
+
+    /// Serves a single client until it detaches, kills the program or disconnects.
+    pub fn serve(&mut self, interpreter: &mut Interpreter, stream: TcpStream) -> io::Result<()> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = stream;
+
+        while let Some(packet) = read_packet(&mut reader, &mut writer)? {
+            let response = self.handle(interpreter, &packet, &mut || interrupted(&mut reader));
+
+            match response {
+                Response::Reply(reply) => write_packet(&mut writer, &reply)?,
+                Response::ReplyAndClose(reply) => return write_packet(&mut writer, &reply),
+                Response::Close => return Ok(()),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handles the data of a packet. `interrupted` is polled while the program runs.
+    fn handle(
+        &mut self,
+        interpreter: &mut Interpreter,
+        packet: &str,
+        interrupted: &mut dyn FnMut() -> bool,
+    ) -> Response {
+        let kind = packet.get(..1).unwrap_or_default();
+        let reply = match (kind, packet.get(1..).unwrap_or_default()) {
+            ("?", _) => format!("S{:02x}", SIGTRAP),
+            ("g", "") => REGISTERS
+                .iter()
+                .map(|register| register_hex(interpreter, *register))
+                .collect(),
+            ("G", values) => ok_or_error(write_registers(interpreter, values)),
+            ("p", number) => parse_hex(number)
+                .and_then(|number| REGISTERS.get(number))
+                .map_or_else(error, |register| register_hex(interpreter, *register)),
+            ("P", assignment) => ok_or_error(write_register(interpreter, assignment)),
+            ("m", range) => parse_range(range)
+                .and_then(|(address, len)| {
+                    let ram = interpreter.ram();
+                    ram.get(address..address.saturating_add(len).min(ram.len()))
+                })
+                .filter(|bytes| !bytes.is_empty())
+                .map_or_else(error, hex),
+            ("M", write) => ok_or_error(write_memory(interpreter, write)),
+            ("c", address) | ("s", address) => {
+                if !address.is_empty() {
+                    match parse_hex(address).filter(|address| *address <= 0xFFFF) {
+                        Some(address) => interpreter.registers_mut().pc = address as u16,
+                        None => return Response::Reply(error()),
+                    }
+                }
+
+                if kind == "s" {
+                    self.debugger.step(1);
+                } else {
+                    self.debugger.resume();
+                }
+                self.run(interpreter, interrupted)
+            }
+            ("Z", point) => ok_or_error(self.insert_point(point)),
+            ("z", point) => ok_or_error(self.remove_point(point)),
+            ("H", _) => String::from("OK"),
+            ("D", _) => return Response::ReplyAndClose(String::from("OK")),
+            ("k", _) => return Response::Close,
+            _ if packet.starts_with("qSupported") => String::from("PacketSize=1000;qXfer:features:read+"),
+            _ if packet == "qAttached" => String::from("1"),
+            _ => match packet.strip_prefix("qXfer:features:read:target.xml:") {
+                Some(range) => parse_range(range).map_or_else(error, |(offset, len)| target_xml_chunk(offset, len)),
+                // An empty reply tells the client that the packet is not supported.
+                None => String::new(),
+            },
+        };
+
+        Response::Reply(reply)
+    }
+
+    /// Runs until the debugger pauses, the program exits or fails, or the client interrupts it,
+    /// and returns the stop reply.
+    fn run(&mut self, interpreter: &mut Interpreter, interrupted: &mut dyn FnMut() -> bool) -> String {
+        loop {
+            if interpreter.has_exited() {
+                return String::from("W00");
+            }
+
+            match self.debugger.run_frame(interpreter, self.cycles_per_frame) {
+                Err(e) => return format!("S{:02x}", signal(e)),
+                Ok(_) if self.debugger.is_paused() => return format!("S{:02x}", SIGTRAP),
+                Ok(_) => {
+                    if interrupted() {
+                        self.debugger.pause();
+                        return format!("S{:02x}", SIGINT);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Adds the breakpoint or watchpoint of a `Z` packet, `type,address,kind`.
+    fn insert_point(&mut self, point: &str) -> Option<()> {
+        match parse_point(point)? {
+            Point::Breakpoint(address) => self.debugger.add_breakpoint(address),
+            Point::Watchpoint(watchpoint) => self.debugger.add_watchpoint(watchpoint),
+        }
+
+        Some(())
+    }
+
+    /// Removes the breakpoint or watchpoint of a `z` packet.
+    fn remove_point(&mut self, point: &str) -> Option<()> {
+        match parse_point(point)? {
+            Point::Breakpoint(address) => self.debugger.remove_breakpoint(address).then_some(()),
+            Point::Watchpoint(watchpoint) => {
+                let index = self.debugger.watchpoints().iter().position(|w| *w == watchpoint)?;
+                self.debugger.remove_watchpoint(index).map(|_| ())
+            }
+        }
+    }
+}
+
+enum Point {
+    Breakpoint(u16),
+    Watchpoint(Watchpoint),
+}
+
+/// Parses `type,address,kind`, where the kind of a watchpoint is its length.
+fn parse_point(point: &str) -> Option<Point> {
+    let mut fields = point.split(',');
+    let kind = fields.next()?;
+    let address = parse_hex(fields.next()?).filter(|address| *address <= 0xFFFF)? as u16;
+    let len = parse_hex(fields.next()?).filter(|len| *len <= 0x10000)?;
+
+    let access = match kind {
+        // Software and hardware breakpoints are the same to the interpreter.
+        "0" | "1" => return Some(Point::Breakpoint(address)),
+        "2" => Access::Write,
+        "3" => Access::Read,
+        "4" => Access::ReadWrite,
+        _ => return None,
+    };
+
+    Some(Point::Watchpoint(Watchpoint::Memory { address, len, access }))
+}
+
+/// The signal reported for an instruction that failed.
+fn signal(error: ExecutionError) -> u8 {
+    match error {
+        ExecutionError::InvalidOpcode { .. } => SIGILL,
+        _ => SIGSEGV,
+    }
+}
+
+fn register_size(register: Register) -> usize {
+    match register {
+        Register::I | Register::Pc => 2,
+        _ => 1,
+    }
+}
+
+fn register_hex(interpreter: &Interpreter, register: Register) -> String {
+    let value = debugger::read_register(interpreter, register);
+    format!("{:0width$x}", value, width = register_size(register) * 2)
+}
+
+/// Sets all registers from the data of a `G` packet.
+fn write_registers(interpreter: &mut Interpreter, values: &str) -> Option<()> {
+    let bytes = parse_hex_bytes(values)?;
+    if bytes.len() != REGISTERS.iter().map(|register| register_size(*register)).sum::<usize>() {
+        return None;
+    }
+
+    // Checked before anything is written, a failed packet leaves the registers unchanged.
+    let mut values = Vec::new();
+    let mut bytes = bytes.as_slice();
+    for register in REGISTERS {
+        let (value, rest) = bytes.split_at(register_size(register));
+        values.push(value.iter().fold(0, |value, byte| value << 8 | u16::from(*byte)));
+        bytes = rest;
+    }
+
+    let backup = interpreter.save_state();
+    for (register, value) in REGISTERS.iter().zip(values) {
+        if debugger::write_register(interpreter, *register, value).is_err() {
+            interpreter.load_state(&backup).expect("the state was just saved");
+            return None;
+        }
+    }
+
+    Some(())
+}
+
+/// Sets the register of a `P` packet, `number=value`.
+fn write_register(interpreter: &mut Interpreter, assignment: &str) -> Option<()> {
+    let (number, value) = assignment.split_once('=')?;
+    let register = *REGISTERS.get(parse_hex(number)?)?;
+    let bytes = parse_hex_bytes(value).filter(|bytes| bytes.len() == register_size(register))?;
+    let value = bytes.iter().fold(0, |value, byte| value << 8 | u16::from(*byte));
+
+    debugger::write_register(interpreter, register, value).ok()
+}
+
+/// Writes the memory of an `M` packet, `address,length:bytes`.
+fn write_memory(interpreter: &mut Interpreter, write: &str) -> Option<()> {
+    let (range, bytes) = write.split_once(':')?;
+    let (address, len) = parse_range(range)?;
+    let bytes = parse_hex_bytes(bytes).filter(|bytes| bytes.len() == len)?;
+
+    interpreter
+        .ram_mut()
+        .get_mut(address..address.checked_add(len)?)?
+        .copy_from_slice(&bytes);
+    Some(())
+}
+
+/// A chunk of the target description, which names the registers for the client.
+fn target_xml_chunk(offset: usize, len: usize) -> String {
+    let mut xml = String::from(concat!(
+        r#"<?xml version="1.0"?><!DOCTYPE target SYSTEM "gdb-target.dtd">"#,
+        r#"<target version="1.0"><feature name="org.chip8.core">"#,
+    ));
+    for register in REGISTERS {
+        let bits = register_size(register) * 8;
+        let kind = match register {
+            Register::Pc => String::from("code_ptr"),
+            Register::I => String::from("data_ptr"),
+            _ => format!("uint{}", bits),
+        };
+        xml.push_str(&format!(
+            r#"<reg name="{}" bitsize="{}" type="{}"/>"#,
+            register.to_string().to_lowercase(),
+            bits,
+            kind
+        ));
+    }
+    xml.push_str("</feature></target>");
+
+    let chunk = xml.get(offset..).unwrap_or_default();
+    if chunk.len() > len {
+        format!("m{}", &chunk[..len])
+    } else {
+        format!("l{}", chunk)
+    }
+}
+
+fn ok_or_error(result: Option<()>) -> String {
+    result.map_or_else(error, |()| String::from("OK"))
+}
+
+fn error() -> String {
+    String::from("E01")
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn parse_hex(text: &str) -> Option<usize> {
+    usize::from_str_radix(text, 16).ok()
+}
+
+fn parse_hex_bytes(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Parses `address,length`.
+fn parse_range(range: &str) -> Option<(usize, usize)> {
+    let (address, len) = range.split_once(',')?;
+    Some((parse_hex(address)?, parse_hex(len)?))
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, byte| sum.wrapping_add(*byte))
+}
+
+/// Reads the next packet, `$data#checksum`, and acknowledges it. Returns `None` at the end of
+/// the stream. Acknowledgements and interrupts outside of packets are skipped.
+fn read_packet(reader: &mut impl BufRead, writer: &mut impl Write) -> io::Result<Option<String>> {
+    loop {
+        let mut skipped = Vec::new();
+        if reader.read_until(b'$', &mut skipped)? == 0 || skipped.last() != Some(&b'$') {
+            return Ok(None);
+        }
+
+        let mut data = Vec::new();
+        reader.read_until(b'#', &mut data)?;
+        if data.pop() != Some(b'#') {
+            return Ok(None);
+        }
+        let mut sum = [0; 2];
+        reader.read_exact(&mut sum)?;
+
+        let valid = std::str::from_utf8(&sum)
+            .ok()
+            .and_then(|sum| u8::from_str_radix(sum, 16).ok())
+            == Some(checksum(&data));
+        match String::from_utf8(data) {
+            Ok(data) if valid => {
+                writer.write_all(b"+")?;
+                return Ok(Some(data));
+            }
+            _ => writer.write_all(b"-")?,
+        }
+    }
+}
+
+fn write_packet(writer: &mut impl Write, data: &str) -> io::Result<()> {
+    write!(writer, "${}#{:02x}", data, checksum(data.as_bytes()))?;
+    writer.flush()
+}
+
+/// Whether the client sent an interrupt or disconnected, without waiting for it.
+fn interrupted(reader: &mut BufReader<TcpStream>) -> bool {
+    if reader.get_ref().set_nonblocking(true).is_err() {
+        return true;
+    }
+    let result = match reader.fill_buf() {
+        Ok([]) => true,
+        Ok(bytes) => {
+            let interrupt = bytes[0] == INTERRUPT;
+            if interrupt {
+                reader.consume(1);
+            }
+            interrupt
+        }
+        Err(e) => e.kind() != ErrorKind::WouldBlock,
+    };
+
+    reader.get_ref().set_nonblocking(false).is_err() || result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    // 0x200: LD V0, 0x12; LD I, 0x300; LD [I], V0; EXIT
+    const ROM: &[u8] = &[0x60, 0x12, 0xA3, 0x00, 0xF0, 0x55, 0x00, 0xFD];
+
+    fn handle(server: &mut GdbServer, interpreter: &mut Interpreter, packet: &str) -> String {
+        match server.handle(interpreter, packet, &mut || false) {
+            Response::Reply(reply) => reply,
+            response => panic!("unexpected {:?}", response),
+        }
+    }
+
+    #[test]
+    fn test_read_packet() {
+        let mut input: &[u8] = b"+$g#67$m200,2#00$m200,2#5d";
+        let mut output = Vec::new();
+
+        assert_eq!(read_packet(&mut input, &mut output).unwrap(), Some(String::from("g")));
+        assert_eq!(
+            read_packet(&mut input, &mut output).unwrap(),
+            Some(String::from("m200,2"))
+        );
+        assert_eq!(read_packet(&mut input, &mut output).unwrap(), None);
+        assert_eq!(output, b"+-+");
+    }
+
+    #[test]
+    fn test_write_packet() {
+        let mut output = Vec::new();
+
+        write_packet(&mut output, "OK").unwrap();
+
+        assert_eq!(output, b"$OK#9a");
+    }
+
+    #[test]
+    fn test_registers() {
+        let mut interpreter = Interpreter::with_rom(ROM);
+        let mut server = GdbServer::new(10);
+
+        assert_eq!(
+            handle(&mut server, &mut interpreter, "g"),
+            format!("{}0000{}000000", "00".repeat(16), "0200")
+        );
+        assert_eq!(handle(&mut server, &mut interpreter, "P3=2a"), "OK");
+        assert_eq!(handle(&mut server, &mut interpreter, "P10=0abc"), "OK");
+        assert_eq!(handle(&mut server, &mut interpreter, "p3"), "2a");
+        assert_eq!(handle(&mut server, &mut interpreter, "p10"), "0abc");
+        assert_eq!(
+            handle(&mut server, &mut interpreter, "P12=11"),
+            "E01",
+            "SP is at most 16"
+        );
+        assert_eq!(handle(&mut server, &mut interpreter, "p15"), "E01");
+
+        let registers = format!("{}0300{}010203", "01".repeat(16), "0204");
+        assert_eq!(handle(&mut server, &mut interpreter, &format!("G{}", registers)), "OK");
+        assert_eq!(handle(&mut server, &mut interpreter, "g"), registers);
+    }
+
+    #[test_case("m200,4" => "6012a300")]
+    #[test_case("mffe,4" => "0000"; "partial read at the end of memory")]
+    #[test_case("m1000,1" => "E01")]
+    fn test_read_memory(packet: &str) -> String {
+        let mut interpreter = Interpreter::with_rom(ROM);
+
+        handle(&mut GdbServer::new(10), &mut interpreter, packet)
+    }
+
+    #[test]
+    fn test_write_memory() {
+        let mut interpreter = Interpreter::with_rom(ROM);
+        let mut server = GdbServer::new(10);
+
+        assert_eq!(handle(&mut server, &mut interpreter, "M300,2:abcd"), "OK");
+        assert_eq!(handle(&mut server, &mut interpreter, "m300,2"), "abcd");
+        assert_eq!(handle(&mut server, &mut interpreter, "M300,2:ab"), "E01");
+        assert_eq!(handle(&mut server, &mut interpreter, "Mfff,2:abcd"), "E01");
+    }
+
+    #[test]
+    fn test_breakpoints_and_steps() {
+        let mut interpreter = Interpreter::with_rom(ROM);
+        let mut server = GdbServer::new(10);
+
+        assert_eq!(handle(&mut server, &mut interpreter, "Z0,204,2"), "OK");
+        assert_eq!(handle(&mut server, &mut interpreter, "c"), "S05");
+        assert_eq!(interpreter.registers().pc, 0x204);
+
+        assert_eq!(handle(&mut server, &mut interpreter, "s"), "S05");
+        assert_eq!(interpreter.registers().pc, 0x206);
+        assert_eq!(interpreter.ram()[0x300], 0x12);
+
+        assert_eq!(handle(&mut server, &mut interpreter, "z0,204,2"), "OK");
+        assert_eq!(handle(&mut server, &mut interpreter, "z0,204,2"), "E01");
+        assert_eq!(handle(&mut server, &mut interpreter, "c"), "W00");
+    }
+
+    #[test]
+    fn test_watchpoint() {
+        let mut interpreter = Interpreter::with_rom(ROM);
+        let mut server = GdbServer::new(10);
+
+        assert_eq!(handle(&mut server, &mut interpreter, "Z2,300,1"), "OK");
+        assert_eq!(handle(&mut server, &mut interpreter, "c"), "S05");
+
+        assert_eq!(interpreter.registers().pc, 0x206);
+        assert_eq!(handle(&mut server, &mut interpreter, "z2,300,1"), "OK");
+    }
+
+    #[test]
+    fn test_stop_replies() {
+        // JP 0x200
+        let mut interpreter = Interpreter::with_rom(&[0x12, 0x00]);
+        let mut server = GdbServer::new(10);
+        let mut polls = 0;
+
+        let response = server.handle(&mut interpreter, "c", &mut || {
+            polls += 1;
+            polls == 3
+        });
+        assert_eq!(response, Response::Reply(String::from("S02")));
+
+        let mut interpreter = Interpreter::with_rom(&[0xFF, 0xFF]);
+        assert_eq!(handle(&mut server, &mut interpreter, "c"), "S04");
+    }
+
+    #[test]
+    fn test_target_xml() {
+        let mut interpreter = Interpreter::with_rom(ROM);
+        let mut server = GdbServer::new(10);
+
+        let first = handle(&mut server, &mut interpreter, "qXfer:features:read:target.xml:0,15");
+        assert_eq!(first, "m<?xml version=\"1.0\"?>");
+
+        let rest = handle(&mut server, &mut interpreter, "qXfer:features:read:target.xml:15,1000");
+        assert!(rest.starts_with("l<!DOCTYPE"));
+        assert!(rest.contains(r#"<reg name="v0" bitsize="8" type="uint8"/>"#));
+        assert!(rest.contains(r#"<reg name="pc" bitsize="16" type="code_ptr"/>"#));
+        assert!(rest.ends_with("</target>"));
+    }
+
+    #[test]
+    fn test_detach_and_kill() {
+        let mut interpreter = Interpreter::with_rom(ROM);
+        let mut server = GdbServer::new(10);
+
+        assert_eq!(
+            server.handle(&mut interpreter, "D", &mut || false),
+            Response::ReplyAndClose(String::from("OK"))
+        );
+        assert_eq!(server.handle(&mut interpreter, "k", &mut || false), Response::Close);
+        assert_eq!(handle(&mut server, &mut interpreter, "vMustReplyEmpty"), "");
+    }
+}
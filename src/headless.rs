@@ -0,0 +1,226 @@
+use std::fmt::{self, Write};
+use std::str::FromStr;
+
+use crate::{
+    display::Display,
+    interpreter::{ExecutionError, Interpreter},
+    keyboard::Keyboard,
+};
+
+/// Presses or releases `key` at the start of `frame`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub frame: u32,
+    pub key: u8,
+    pub pressed: bool,
+}
+
+impl fmt::Display for KeyEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}{:X}",
+            self.frame,
+            if self.pressed { '+' } else { '-' },
+            self.key
+        )
+    }
+}
+
+/// Scripted key input for runs without a keyboard, written as comma separated
+/// `FRAME:+KEY` (press) and `FRAME:-KEY` (release) events, e.g. `30:+5,35:-5`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct KeyScript {
+    pub events: Vec<KeyEvent>,
+}
+
+impl KeyScript {
+    /// Presses and releases the keys of the events of `frame`.
+    pub fn apply(&self, frame: u32, keyboard: &mut Keyboard) {
+        for event in self.events.iter().filter(|event| event.frame == frame) {
+            if event.pressed {
+                keyboard.press_key(event.key);
+            } else {
+                keyboard.release_key(event.key);
+            }
+        }
+    }
+}
+
+impl FromStr for KeyScript {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut events = Vec::new();
+
+        for event in s.split(',').map(str::trim).filter(|event| !event.is_empty()) {
+            let invalid = || format!("invalid key event `{}`, expected FRAME:+KEY or FRAME:-KEY", event);
+
+            let (frame, key) = event.split_once(':').ok_or_else(invalid)?;
+            let key = key.trim();
+            let (pressed, key) = match (key.strip_prefix('+'), key.strip_prefix('-')) {
+                (Some(key), _) => (true, key),
+                (_, Some(key)) => (false, key),
+                _ => return Err(invalid()),
+            };
+
+            events.push(KeyEvent {
+                frame: frame.trim().parse().map_err(|_| invalid())?,
+                key: u8::from_str_radix(key, 16)
+                    .ok()
+                    .filter(|key| *key <= 0xF)
+                    .ok_or_else(invalid)?,
+                pressed,
+            });
+        }
+
+        Ok(KeyScript { events })
+    }
+}
+
+/// Runs `frames` frames of `cycles_per_frame` instructions, applying the key events of each
+/// frame before it runs. Stops early if the program exits or an instruction fails.
+pub fn run(
+    interpreter: &mut Interpreter,
+    frames: u32,
+    cycles_per_frame: u32,
+    script: &KeyScript,
+) -> Result<(), ExecutionError> {
+    run_with(interpreter, frames, script, |interpreter| {
+        interpreter.run_frame(cycles_per_frame).map(|()| true)
+    })
+}
+
+/// Like [`run`], but every frame is run by `run_frame`, e.g. under a
+/// [`Debugger`](crate::debugger::Debugger). Also stops early if it returns `Ok(false)`.
+pub fn run_with<E>(
+    interpreter: &mut Interpreter,
+    frames: u32,
+    script: &KeyScript,
+    mut run_frame: impl FnMut(&mut Interpreter) -> Result<bool, E>,
+) -> Result<(), E> {
+    for frame in 0..frames {
+        script.apply(frame, interpreter.keyboard_mut());
+
+        if !run_frame(interpreter)? || interpreter.has_exited() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Renders the display as a binary PBM (P4) image, every lit pixel is black.
+pub fn to_pbm(display: &Display) -> Vec<u8> {
+    let mut pbm = format!("P4\n{} {}\n", display.width(), display.height()).into_bytes();
+
+    for row in display.color_indices().chunks(display.width()) {
+        for pixels in row.chunks(8) {
+            let byte = pixels
+                .iter()
+                .enumerate()
+                .filter(|(_, color)| **color != 0)
+                .fold(0u8, |byte, (bit, _)| byte | 0x80 >> bit);
+            pbm.push(byte);
+        }
+    }
+
+    pbm
+}
+
+/// Renders the display as text, one line per row. Unlit pixels are `.`, lit ones `#` and the
+/// colors of the second XO-CHIP plane `+` and `@`.
+pub fn to_ascii(display: &Display) -> String {
+    const CHARS: [char; 4] = ['.', '#', '+', '@'];
+
+    let mut ascii = String::new();
+    for row in display.color_indices().chunks(display.width()) {
+        let line: String = row.iter().map(|color| CHARS[*color as usize]).collect();
+        writeln!(ascii, "{}", line).unwrap();
+    }
+
+    ascii
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_key_script() {
+        let script: KeyScript = "10:+5, 12:-5,0:+F".parse().unwrap();
+
+        assert_eq!(
+            script.events,
+            vec![
+                KeyEvent {
+                    frame: 10,
+                    key: 0x5,
+                    pressed: true
+                },
+                KeyEvent {
+                    frame: 12,
+                    key: 0x5,
+                    pressed: false
+                },
+                KeyEvent {
+                    frame: 0,
+                    key: 0xF,
+                    pressed: true
+                },
+            ]
+        );
+        assert_eq!("".parse::<KeyScript>(), Ok(KeyScript::default()));
+        assert!("10:5".parse::<KeyScript>().is_err());
+        assert!("10:+10".parse::<KeyScript>().is_err());
+        assert!("x:+1".parse::<KeyScript>().is_err());
+    }
+
+    #[test]
+    fn test_run_with_script() {
+        // LD V0, K; LD F, V0; DRW V1, V1, 5; EXIT
+        let rom: &[u8] = &[0xF0, 0x0A, 0xF0, 0x29, 0xD1, 0x15, 0x00, 0xFD];
+        let mut interpreter = Interpreter::with_rom(rom);
+        let script: KeyScript = "3:+7,4:-7".parse().unwrap();
+
+        run(&mut interpreter, 100, 10, &script).unwrap();
+
+        assert!(interpreter.has_exited());
+        let ascii = to_ascii(interpreter.display());
+        let lines: Vec<&str> = ascii.lines().collect();
+        assert_eq!(lines.len(), 32);
+        assert!(lines[0].starts_with("####...."));
+        assert!(lines[1].starts_with("...#...."));
+    }
+
+    #[test]
+    fn test_run_stops_on_error() {
+        let mut interpreter = Interpreter::with_rom(&[0xFF, 0xFF]);
+
+        let result = run(&mut interpreter, 10, 10, &KeyScript::default());
+
+        assert_eq!(
+            result,
+            Err(ExecutionError::InvalidOpcode {
+                pc: 0x200,
+                opcode: 0xFFFF
+            })
+        );
+    }
+
+    #[test]
+    fn test_to_pbm() {
+        let mut display = Display::new();
+        display.xor_pixel(0, 0, true);
+        display.xor_pixel(9, 0, true);
+        display.xor_pixel(63, 31, true);
+
+        let pbm = to_pbm(&display);
+
+        let header = b"P4\n64 32\n";
+        assert_eq!(&pbm[..header.len()], header);
+        assert_eq!(pbm.len(), header.len() + 8 * 32);
+        assert_eq!(pbm[header.len()..header.len() + 2], [0x80, 0x40]);
+        assert_eq!(pbm.last(), Some(&0x01));
+    }
+}
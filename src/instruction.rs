@@ -0,0 +1,558 @@
+use std::fmt;
+
+/// One of the 16 general purpose registers V0 through VF.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Vx(pub u8);
+
+impl Vx {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// A memory address, the lowest 12 bits of the instruction or the full 16-bit word following
+/// `F000` (XO-CHIP).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Addr(pub u16);
+
+/// An 8-bit immediate value, the lowest 8 bits of the instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Byte(pub u8);
+
+/// A 4-bit immediate value, the lowest 4 bits of the instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Nibble(pub u8);
+
+/// Returned when a 16-bit word does not encode any known instruction, or when the operand
+/// of a 4-byte instruction is missing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeError {
+    pub opcode: u16,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unknown instruction: {:#06x}", self.opcode)
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// A decoded Chip-8 instruction. The variants follow the instruction set as described in
+/// Cowgod's Chip-8 Technical Reference, `x` and `y` are registers, `nnn` addresses,
+/// `kk` bytes and `n` nibbles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Instruction {
+    /// 0nnn - SYS addr
+    Sys(Addr),
+    /// 00E0 - CLS
+    Clear,
+    /// 00EE - RET
+    Ret,
+    /// 1nnn - JP addr
+    Jump(Addr),
+    /// 2nnn - CALL addr
+    Call(Addr),
+    /// 3xkk - SE Vx, byte
+    SkipIfEqualImmediate(Vx, Byte),
+    /// 4xkk - SNE Vx, byte
+    SkipIfNotEqualImmediate(Vx, Byte),
+    /// 5xy0 - SE Vx, Vy
+    SkipIfEqualRegister(Vx, Vx),
+    /// 6xkk - LD Vx, byte
+    LoadRegisterImmediate(Vx, Byte),
+    /// 7xkk - ADD Vx, byte
+    AddRegisterImmediate(Vx, Byte),
+    /// 8xy0 - LD Vx, Vy
+    LoadRegisterRegister(Vx, Vx),
+    /// 8xy1 - OR Vx, Vy
+    OrRegisterRegister(Vx, Vx),
+    /// 8xy2 - AND Vx, Vy
+    AndRegisterRegister(Vx, Vx),
+    /// 8xy3 - XOR Vx, Vy
+    XorRegisterRegister(Vx, Vx),
+    /// 8xy4 - ADD Vx, Vy
+    AddRegisterRegister(Vx, Vx),
+    /// 8xy5 - SUB Vx, Vy
+    SubRegisterRegister(Vx, Vx),
+    /// 8xy6 - SHR Vx {, Vy}
+    ShiftRightRegisterOne(Vx, Vx),
+    /// 8xy7 - SUBN Vx, Vy
+    SubRegisterRegisterNegated(Vx, Vx),
+    /// 8xyE - SHL Vx {, Vy}
+    ShiftLeftRegisterOne(Vx, Vx),
+    /// 9xy0 - SNE Vx, Vy
+    SkipIfNotEqualRegister(Vx, Vx),
+    /// Annn - LD I, addr
+    LoadImmediateIntoI(Addr),
+    /// Bnnn - JP V0, addr
+    JumpRelative(Addr),
+    /// Cxkk - RND Vx, byte
+    Random(Vx, Byte),
+    /// Dxyn - DRW Vx, Vy, nibble, a nibble of 0 draws a 16x16 sprite (SUPER-CHIP)
+    DrawSprite(Vx, Vx, Nibble),
+    /// Ex9E - SKP Vx
+    SkipIfKeyPressed(Vx),
+    /// ExA1 - SKNP Vx
+    SkipIfKeyNotPressed(Vx),
+    /// Fx07 - LD Vx, DT
+    StoreDelayTimerRegister(Vx),
+    /// Fx0A - LD Vx, K
+    WaitForKeypress(Vx),
+    /// Fx15 - LD DT, Vx
+    LoadDelayTimerRegister(Vx),
+    /// Fx18 - LD ST, Vx
+    LoadSoundTimerRegister(Vx),
+    /// Fx1E - ADD I, Vx
+    AddIRegister(Vx),
+    /// Fx29 - LD F, Vx
+    LoadDigitSpriteLocation(Vx),
+    /// Fx33 - LD B, Vx
+    LoadBcd(Vx),
+    /// Fx55 - LD [I], Vx
+    StoreRegistersInMemory(Vx),
+    /// Fx65 - LD Vx, [I]
+    LoadRegistersFromMemory(Vx),
+    /// 00Cn - SCD nibble (SUPER-CHIP)
+    ScrollDown(Nibble),
+    /// 00FB - SCR (SUPER-CHIP)
+    ScrollRight,
+    /// 00FC - SCL (SUPER-CHIP)
+    ScrollLeft,
+    /// 00FD - EXIT (SUPER-CHIP)
+    Exit,
+    /// 00FE - LOW (SUPER-CHIP)
+    LowResolution,
+    /// 00FF - HIGH (SUPER-CHIP)
+    HighResolution,
+    /// Fx30 - LD HF, Vx (SUPER-CHIP)
+    LoadBigDigitSpriteLocation(Vx),
+    /// Fx75 - LD R, Vx (SUPER-CHIP)
+    StoreFlags(Vx),
+    /// Fx85 - LD Vx, R (SUPER-CHIP)
+    LoadFlags(Vx),
+    /// 5xy2 - LD [I], Vx-Vy (XO-CHIP)
+    StoreRegisterRange(Vx, Vx),
+    /// 5xy3 - LD Vx-Vy, [I] (XO-CHIP)
+    LoadRegisterRange(Vx, Vx),
+    /// F000 nnnn - LD I, long addr (XO-CHIP), the only instruction that is 4 bytes long
+    LoadLongI(Addr),
+    /// Fn01 - PLANE nibble (XO-CHIP)
+    SelectPlanes(Nibble),
+    /// F002 - LD AUDIO, [I] (XO-CHIP)
+    LoadAudioPattern,
+    /// Fx3A - LD PITCH, Vx (XO-CHIP)
+    LoadPitch(Vx),
+}
+
+impl Instruction {
+    /// The opcode of `F000 nnnn`, whose address is stored in the word after it.
+    pub const LONG_PREFIX: u16 = 0xF000;
+
+    /// Decodes a big-endian 16-bit word into the instruction it represents. `F000 nnnn` needs
+    /// the word after it and is rejected here, use [`Instruction::decode_bytes`] for it.
+    pub fn decode(opcode: u16) -> Result<Instruction, DecodeError> {
+        use Instruction::*;
+
+        let first_nibble = ((opcode & 0xF000) >> 12) as u8;
+        let x = Vx(((opcode & 0x0F00) >> 8) as u8);
+        let y = Vx(((opcode & 0x00F0) >> 4) as u8);
+        let n = (opcode & 0x000F) as u8;
+        let kk = (opcode & 0x00FF) as u8;
+        let nnn = Addr(opcode & 0x0FFF);
+
+        let instruction = match (first_nibble, n) {
+            _ if opcode == 0x00E0 => Clear,
+            _ if opcode == 0x00EE => Ret,
+            _ if opcode & 0xFFF0 == 0x00C0 => ScrollDown(Nibble(n)),
+            _ if opcode == 0x00FB => ScrollRight,
+            _ if opcode == 0x00FC => ScrollLeft,
+            _ if opcode == 0x00FD => Exit,
+            _ if opcode == 0x00FE => LowResolution,
+            _ if opcode == 0x00FF => HighResolution,
+            _ if opcode == 0xF002 => LoadAudioPattern,
+            (0x0, _) => Sys(nnn),
+            (0x1, _) => Jump(nnn),
+            (0x2, _) => Call(nnn),
+            (0x3, _) => SkipIfEqualImmediate(x, Byte(kk)),
+            (0x4, _) => SkipIfNotEqualImmediate(x, Byte(kk)),
+            (0x5, 0x0) => SkipIfEqualRegister(x, y),
+            (0x5, 0x2) => StoreRegisterRange(x, y),
+            (0x5, 0x3) => LoadRegisterRange(x, y),
+            (0x6, _) => LoadRegisterImmediate(x, Byte(kk)),
+            (0x7, _) => AddRegisterImmediate(x, Byte(kk)),
+            (0x8, 0x0) => LoadRegisterRegister(x, y),
+            (0x8, 0x1) => OrRegisterRegister(x, y),
+            (0x8, 0x2) => AndRegisterRegister(x, y),
+            (0x8, 0x3) => XorRegisterRegister(x, y),
+            (0x8, 0x4) => AddRegisterRegister(x, y),
+            (0x8, 0x5) => SubRegisterRegister(x, y),
+            (0x8, 0x6) => ShiftRightRegisterOne(x, y),
+            (0x8, 0x7) => SubRegisterRegisterNegated(x, y),
+            (0x8, 0xE) => ShiftLeftRegisterOne(x, y),
+            (0x9, 0x0) => SkipIfNotEqualRegister(x, y),
+            (0xA, _) => LoadImmediateIntoI(nnn),
+            (0xB, _) => JumpRelative(nnn),
+            (0xC, _) => Random(x, Byte(kk)),
+            (0xD, _) => DrawSprite(x, y, Nibble(n)),
+            (0xE, _) if kk == 0x9E => SkipIfKeyPressed(x),
+            (0xE, _) if kk == 0xA1 => SkipIfKeyNotPressed(x),
+            (0xF, _) if kk == 0x07 => StoreDelayTimerRegister(x),
+            (0xF, _) if kk == 0x0A => WaitForKeypress(x),
+            (0xF, _) if kk == 0x15 => LoadDelayTimerRegister(x),
+            (0xF, _) if kk == 0x18 => LoadSoundTimerRegister(x),
+            (0xF, _) if kk == 0x1E => AddIRegister(x),
+            (0xF, _) if kk == 0x29 => LoadDigitSpriteLocation(x),
+            (0xF, _) if kk == 0x33 => LoadBcd(x),
+            (0xF, _) if kk == 0x55 => StoreRegistersInMemory(x),
+            (0xF, _) if kk == 0x65 => LoadRegistersFromMemory(x),
+            (0xF, _) if kk == 0x30 => LoadBigDigitSpriteLocation(x),
+            (0xF, _) if kk == 0x75 => StoreFlags(x),
+            (0xF, _) if kk == 0x85 => LoadFlags(x),
+            (0xF, _) if kk == 0x01 => SelectPlanes(Nibble(x.0)),
+            (0xF, _) if kk == 0x3A => LoadPitch(x),
+            _ => return Err(DecodeError { opcode }),
+        };
+
+        Ok(instruction)
+    }
+
+    /// Decodes the instruction at the start of `bytes`, reading the second word of `F000 nnnn`
+    /// when it is present.
+    pub fn decode_bytes(bytes: &[u8]) -> Result<Instruction, DecodeError> {
+        let word = |offset: usize| {
+            let high = bytes.get(offset).copied().unwrap_or(0);
+            let low = bytes.get(offset + 1).copied().unwrap_or(0);
+            u16::from_be_bytes([high, low])
+        };
+
+        let opcode = word(0);
+        match opcode {
+            _ if bytes.len() < 2 => Err(DecodeError { opcode }),
+            Instruction::LONG_PREFIX if bytes.len() < 4 => Err(DecodeError { opcode }),
+            Instruction::LONG_PREFIX => Ok(Instruction::LoadLongI(Addr(word(2)))),
+            _ => Instruction::decode(opcode),
+        }
+    }
+
+    /// The number of bytes the instruction occupies in memory.
+    pub fn size(&self) -> u16 {
+        match self {
+            Instruction::LoadLongI(_) => 4,
+            _ => 2,
+        }
+    }
+
+    /// The mnemonic with placeholders instead of operands as in Cowgod's Chip-8 Technical
+    /// Reference, e.g. `LD Vx, byte`. Instructions of the same kind share the same pattern.
+    pub fn pattern(&self) -> &'static str {
+        use Instruction::*;
+
+        match self {
+            Sys(_) => "SYS addr",
+            Clear => "CLS",
+            Ret => "RET",
+            Jump(_) => "JP addr",
+            Call(_) => "CALL addr",
+            SkipIfEqualImmediate(..) => "SE Vx, byte",
+            SkipIfNotEqualImmediate(..) => "SNE Vx, byte",
+            SkipIfEqualRegister(..) => "SE Vx, Vy",
+            LoadRegisterImmediate(..) => "LD Vx, byte",
+            AddRegisterImmediate(..) => "ADD Vx, byte",
+            LoadRegisterRegister(..) => "LD Vx, Vy",
+            OrRegisterRegister(..) => "OR Vx, Vy",
+            AndRegisterRegister(..) => "AND Vx, Vy",
+            XorRegisterRegister(..) => "XOR Vx, Vy",
+            AddRegisterRegister(..) => "ADD Vx, Vy",
+            SubRegisterRegister(..) => "SUB Vx, Vy",
+            ShiftRightRegisterOne(..) => "SHR Vx, Vy",
+            SubRegisterRegisterNegated(..) => "SUBN Vx, Vy",
+            ShiftLeftRegisterOne(..) => "SHL Vx, Vy",
+            SkipIfNotEqualRegister(..) => "SNE Vx, Vy",
+            LoadImmediateIntoI(_) => "LD I, addr",
+            JumpRelative(_) => "JP V0, addr",
+            Random(..) => "RND Vx, byte",
+            DrawSprite(..) => "DRW Vx, Vy, nibble",
+            SkipIfKeyPressed(_) => "SKP Vx",
+            SkipIfKeyNotPressed(_) => "SKNP Vx",
+            StoreDelayTimerRegister(_) => "LD Vx, DT",
+            WaitForKeypress(_) => "LD Vx, K",
+            LoadDelayTimerRegister(_) => "LD DT, Vx",
+            LoadSoundTimerRegister(_) => "LD ST, Vx",
+            AddIRegister(_) => "ADD I, Vx",
+            LoadDigitSpriteLocation(_) => "LD F, Vx",
+            LoadBcd(_) => "LD B, Vx",
+            StoreRegistersInMemory(_) => "LD [I], Vx",
+            LoadRegistersFromMemory(_) => "LD Vx, [I]",
+            ScrollDown(_) => "SCD nibble",
+            ScrollRight => "SCR",
+            ScrollLeft => "SCL",
+            Exit => "EXIT",
+            LowResolution => "LOW",
+            HighResolution => "HIGH",
+            LoadBigDigitSpriteLocation(_) => "LD HF, Vx",
+            StoreFlags(_) => "LD R, Vx",
+            LoadFlags(_) => "LD Vx, R",
+            StoreRegisterRange(..) => "LD [I], Vx-Vy",
+            LoadRegisterRange(..) => "LD Vx-Vy, [I]",
+            LoadLongI(_) => "LD I, long addr",
+            SelectPlanes(_) => "PLANE nibble",
+            LoadAudioPattern => "LD AUDIO, [I]",
+            LoadPitch(_) => "LD PITCH, Vx",
+        }
+    }
+
+    /// Encodes the instruction into the big-endian bytes it occupies in memory.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.encode().to_be_bytes().to_vec();
+        if let Instruction::LoadLongI(n) = self {
+            bytes.extend(n.0.to_be_bytes());
+        }
+        bytes
+    }
+
+    /// Encodes the instruction back into its big-endian 16-bit word. For `F000 nnnn` this is
+    /// only the first word, use [`Instruction::to_bytes`] to include the address.
+    pub fn encode(&self) -> u16 {
+        use Instruction::*;
+
+        fn xkk(high: u16, x: Vx, k: Byte) -> u16 {
+            high << 12 | (x.0 as u16 & 0xF) << 8 | k.0 as u16
+        }
+
+        fn xyn(high: u16, x: Vx, y: Vx, n: u8) -> u16 {
+            high << 12 | (x.0 as u16 & 0xF) << 8 | (y.0 as u16 & 0xF) << 4 | (n as u16 & 0xF)
+        }
+
+        fn nnn(high: u16, n: Addr) -> u16 {
+            high << 12 | (n.0 & 0x0FFF)
+        }
+
+        match *self {
+            Sys(n) => nnn(0x0, n),
+            Clear => 0x00E0,
+            Ret => 0x00EE,
+            Jump(n) => nnn(0x1, n),
+            Call(n) => nnn(0x2, n),
+            SkipIfEqualImmediate(x, k) => xkk(0x3, x, k),
+            SkipIfNotEqualImmediate(x, k) => xkk(0x4, x, k),
+            SkipIfEqualRegister(x, y) => xyn(0x5, x, y, 0x0),
+            LoadRegisterImmediate(x, k) => xkk(0x6, x, k),
+            AddRegisterImmediate(x, k) => xkk(0x7, x, k),
+            LoadRegisterRegister(x, y) => xyn(0x8, x, y, 0x0),
+            OrRegisterRegister(x, y) => xyn(0x8, x, y, 0x1),
+            AndRegisterRegister(x, y) => xyn(0x8, x, y, 0x2),
+            XorRegisterRegister(x, y) => xyn(0x8, x, y, 0x3),
+            AddRegisterRegister(x, y) => xyn(0x8, x, y, 0x4),
+            SubRegisterRegister(x, y) => xyn(0x8, x, y, 0x5),
+            ShiftRightRegisterOne(x, y) => xyn(0x8, x, y, 0x6),
+            SubRegisterRegisterNegated(x, y) => xyn(0x8, x, y, 0x7),
+            ShiftLeftRegisterOne(x, y) => xyn(0x8, x, y, 0xE),
+            SkipIfNotEqualRegister(x, y) => xyn(0x9, x, y, 0x0),
+            LoadImmediateIntoI(n) => nnn(0xA, n),
+            JumpRelative(n) => nnn(0xB, n),
+            Random(x, k) => xkk(0xC, x, k),
+            DrawSprite(x, y, n) => xyn(0xD, x, y, n.0),
+            SkipIfKeyPressed(x) => xkk(0xE, x, Byte(0x9E)),
+            SkipIfKeyNotPressed(x) => xkk(0xE, x, Byte(0xA1)),
+            StoreDelayTimerRegister(x) => xkk(0xF, x, Byte(0x07)),
+            WaitForKeypress(x) => xkk(0xF, x, Byte(0x0A)),
+            LoadDelayTimerRegister(x) => xkk(0xF, x, Byte(0x15)),
+            LoadSoundTimerRegister(x) => xkk(0xF, x, Byte(0x18)),
+            AddIRegister(x) => xkk(0xF, x, Byte(0x1E)),
+            LoadDigitSpriteLocation(x) => xkk(0xF, x, Byte(0x29)),
+            LoadBcd(x) => xkk(0xF, x, Byte(0x33)),
+            StoreRegistersInMemory(x) => xkk(0xF, x, Byte(0x55)),
+            LoadRegistersFromMemory(x) => xkk(0xF, x, Byte(0x65)),
+            ScrollDown(n) => 0x00C0 | (n.0 as u16 & 0xF),
+            ScrollRight => 0x00FB,
+            ScrollLeft => 0x00FC,
+            Exit => 0x00FD,
+            LowResolution => 0x00FE,
+            HighResolution => 0x00FF,
+            LoadBigDigitSpriteLocation(x) => xkk(0xF, x, Byte(0x30)),
+            StoreFlags(x) => xkk(0xF, x, Byte(0x75)),
+            LoadFlags(x) => xkk(0xF, x, Byte(0x85)),
+            StoreRegisterRange(x, y) => xyn(0x5, x, y, 0x2),
+            LoadRegisterRange(x, y) => xyn(0x5, x, y, 0x3),
+            LoadLongI(_) => Instruction::LONG_PREFIX,
+            SelectPlanes(n) => xkk(0xF, Vx(n.0), Byte(0x01)),
+            LoadAudioPattern => 0xF002,
+            LoadPitch(x) => xkk(0xF, x, Byte(0x3A)),
+        }
+    }
+}
+
+impl fmt::Display for Vx {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "V{:X}", self.0)
+    }
+}
+
+impl fmt::Display for Addr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#05X}", self.0)
+    }
+}
+
+impl fmt::Display for Byte {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#04X}", self.0)
+    }
+}
+
+impl fmt::Display for Nibble {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Formats the instruction using the mnemonics from Cowgod's Chip-8 Technical Reference.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Instruction::*;
+
+        match self {
+            Sys(n) => write!(f, "SYS {}", n),
+            Clear => write!(f, "CLS"),
+            Ret => write!(f, "RET"),
+            Jump(n) => write!(f, "JP {}", n),
+            Call(n) => write!(f, "CALL {}", n),
+            SkipIfEqualImmediate(x, k) => write!(f, "SE {}, {}", x, k),
+            SkipIfNotEqualImmediate(x, k) => write!(f, "SNE {}, {}", x, k),
+            SkipIfEqualRegister(x, y) => write!(f, "SE {}, {}", x, y),
+            LoadRegisterImmediate(x, k) => write!(f, "LD {}, {}", x, k),
+            AddRegisterImmediate(x, k) => write!(f, "ADD {}, {}", x, k),
+            LoadRegisterRegister(x, y) => write!(f, "LD {}, {}", x, y),
+            OrRegisterRegister(x, y) => write!(f, "OR {}, {}", x, y),
+            AndRegisterRegister(x, y) => write!(f, "AND {}, {}", x, y),
+            XorRegisterRegister(x, y) => write!(f, "XOR {}, {}", x, y),
+            AddRegisterRegister(x, y) => write!(f, "ADD {}, {}", x, y),
+            SubRegisterRegister(x, y) => write!(f, "SUB {}, {}", x, y),
+            ShiftRightRegisterOne(x, y) => write!(f, "SHR {}, {}", x, y),
+            SubRegisterRegisterNegated(x, y) => write!(f, "SUBN {}, {}", x, y),
+            ShiftLeftRegisterOne(x, y) => write!(f, "SHL {}, {}", x, y),
+            SkipIfNotEqualRegister(x, y) => write!(f, "SNE {}, {}", x, y),
+            LoadImmediateIntoI(n) => write!(f, "LD I, {}", n),
+            JumpRelative(n) => write!(f, "JP V0, {}", n),
+            Random(x, k) => write!(f, "RND {}, {}", x, k),
+            DrawSprite(x, y, n) => write!(f, "DRW {}, {}, {}", x, y, n),
+            SkipIfKeyPressed(x) => write!(f, "SKP {}", x),
+            SkipIfKeyNotPressed(x) => write!(f, "SKNP {}", x),
+            StoreDelayTimerRegister(x) => write!(f, "LD {}, DT", x),
+            WaitForKeypress(x) => write!(f, "LD {}, K", x),
+            LoadDelayTimerRegister(x) => write!(f, "LD DT, {}", x),
+            LoadSoundTimerRegister(x) => write!(f, "LD ST, {}", x),
+            AddIRegister(x) => write!(f, "ADD I, {}", x),
+            LoadDigitSpriteLocation(x) => write!(f, "LD F, {}", x),
+            LoadBcd(x) => write!(f, "LD B, {}", x),
+            StoreRegistersInMemory(x) => write!(f, "LD [I], {}", x),
+            LoadRegistersFromMemory(x) => write!(f, "LD {}, [I]", x),
+            ScrollDown(n) => write!(f, "SCD {}", n),
+            ScrollRight => write!(f, "SCR"),
+            ScrollLeft => write!(f, "SCL"),
+            Exit => write!(f, "EXIT"),
+            LowResolution => write!(f, "LOW"),
+            HighResolution => write!(f, "HIGH"),
+            LoadBigDigitSpriteLocation(x) => write!(f, "LD HF, {}", x),
+            StoreFlags(x) => write!(f, "LD R, {}", x),
+            LoadFlags(x) => write!(f, "LD {}, R", x),
+            StoreRegisterRange(x, y) => write!(f, "LD [I], {}-{}", x, y),
+            LoadRegisterRange(x, y) => write!(f, "LD {}-{}, [I]", x, y),
+            LoadLongI(n) => write!(f, "LD I, long {:#06X}", n.0),
+            SelectPlanes(n) => write!(f, "PLANE {}", n),
+            LoadAudioPattern => write!(f, "LD AUDIO, [I]"),
+            LoadPitch(x) => write!(f, "LD PITCH, {}", x),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck_macros::quickcheck;
+    use test_case::test_case;
+
+    #[quickcheck]
+    fn test_decode_encode_roundtrip(opcode: u16) {
+        if let Ok(instruction) = Instruction::decode(opcode) {
+            assert_eq!(instruction.encode(), opcode);
+        }
+    }
+
+    #[quickcheck]
+    fn test_decode_bytes_roundtrip(bytes: (u8, u8, u8, u8)) {
+        let bytes = [bytes.0, bytes.1, bytes.2, bytes.3];
+        if let Ok(instruction) = Instruction::decode_bytes(&bytes) {
+            let size = instruction.size() as usize;
+            assert_eq!(instruction.to_bytes(), &bytes[..size]);
+        }
+    }
+
+    #[test]
+    fn test_decode_long_i() {
+        let instruction = Instruction::decode_bytes(&[0xF0, 0x00, 0xAB, 0xCD]);
+
+        assert_eq!(instruction, Ok(Instruction::LoadLongI(Addr(0xABCD))));
+        assert_eq!(
+            Instruction::decode_bytes(&[0xF0, 0x00, 0xAB]),
+            Err(DecodeError { opcode: 0xF000 })
+        );
+        assert_eq!(Instruction::decode(0xF000), Err(DecodeError { opcode: 0xF000 }));
+    }
+
+    #[test_case(0x00E0, Instruction::Clear; "CLS")]
+    #[test_case(0x00EE, Instruction::Ret; "RET")]
+    #[test_case(0x0123, Instruction::Sys(Addr(0x123)); "SYS addr")]
+    #[test_case(0x1789, Instruction::Jump(Addr(0x789)); "JP addr")]
+    #[test_case(0x3A42, Instruction::SkipIfEqualImmediate(Vx(0xA), Byte(0x42)); "SE Vx, byte")]
+    #[test_case(0x8BD1, Instruction::OrRegisterRegister(Vx(0xB), Vx(0xD)); "OR Vx, Vy")]
+    #[test_case(0x8A3E, Instruction::ShiftLeftRegisterOne(Vx(0xA), Vx(0x3)); "SHL Vx, Vy")]
+    #[test_case(0xD125, Instruction::DrawSprite(Vx(0x1), Vx(0x2), Nibble(0x5)); "DRW Vx, Vy, nibble")]
+    #[test_case(0xE7A1, Instruction::SkipIfKeyNotPressed(Vx(0x7)); "SKNP Vx")]
+    #[test_case(0xF465, Instruction::LoadRegistersFromMemory(Vx(0x4)); "LD Vx, [I]")]
+    #[test_case(0x00C3, Instruction::ScrollDown(Nibble(0x3)); "SCD nibble")]
+    #[test_case(0x00FD, Instruction::Exit; "EXIT")]
+    #[test_case(0x00FF, Instruction::HighResolution; "HIGH")]
+    #[test_case(0xF330, Instruction::LoadBigDigitSpriteLocation(Vx(0x3)); "LD HF, Vx")]
+    #[test_case(0xF775, Instruction::StoreFlags(Vx(0x7)); "LD R, Vx")]
+    #[test_case(0x5132, Instruction::StoreRegisterRange(Vx(0x1), Vx(0x3)); "LD [I], Vx-Vy")]
+    #[test_case(0x5A43, Instruction::LoadRegisterRange(Vx(0xA), Vx(0x4)); "LD Vx-Vy, [I]")]
+    #[test_case(0xF201, Instruction::SelectPlanes(Nibble(0x2)); "PLANE nibble")]
+    #[test_case(0xF002, Instruction::LoadAudioPattern; "LD AUDIO, [I]")]
+    #[test_case(0xF83A, Instruction::LoadPitch(Vx(0x8)); "LD PITCH, Vx")]
+    fn test_decode(opcode: u16, expected: Instruction) {
+        assert_eq!(Instruction::decode(opcode), Ok(expected));
+    }
+
+    #[test_case(0x00E0, "CLS"; "CLS")]
+    #[test_case(0x1228, "JP 0x228"; "JP addr")]
+    #[test_case(0x6A0F, "LD VA, 0x0F"; "LD Vx, byte")]
+    #[test_case(0x8126, "SHR V1, V2"; "SHR Vx, Vy")]
+    #[test_case(0xB300, "JP V0, 0x300"; "JP V0, addr")]
+    #[test_case(0xD01F, "DRW V0, V1, 15"; "DRW Vx, Vy, nibble")]
+    #[test_case(0xF30A, "LD V3, K"; "LD Vx, K")]
+    #[test_case(0xFE55, "LD [I], VE"; "LD [I], Vx")]
+    #[test_case(0x5292, "LD [I], V2-V9"; "LD [I], Vx-Vy")]
+    fn test_display(opcode: u16, mnemonic: &str) {
+        assert_eq!(Instruction::decode(opcode).unwrap().to_string(), mnemonic);
+    }
+
+    #[test_case(0x1228 => "JP addr")]
+    #[test_case(0x6A0F => "LD Vx, byte")]
+    #[test_case(0xD01F => "DRW Vx, Vy, nibble")]
+    #[test_case(0x5292 => "LD [I], Vx-Vy")]
+    fn test_pattern(opcode: u16) -> &'static str {
+        Instruction::decode(opcode).unwrap().pattern()
+    }
+
+    #[test_case(0x5121; "5xy1")]
+    #[test_case(0x8128; "8xy8")]
+    #[test_case(0x9ABF; "9xyF")]
+    #[test_case(0xE19F; "Ex9F")]
+    #[test_case(0xF0FF; "FxFF")]
+    #[test_case(0xF102; "Fx02")]
+    fn test_decode_unknown(opcode: u16) {
+        assert_eq!(Instruction::decode(opcode), Err(DecodeError { opcode }));
+    }
+}
@@ -1,141 +1,412 @@
-use std::convert::TryInto;
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+use std::str::FromStr;
 
 use rand::prelude::*;
 use rand_chacha::ChaCha8Rng;
 
 use crate::{
-    display::Display,
+    decode_cache::DecodeCache,
+    display::{Display, PLANES},
+    instruction::{Addr, Byte, Instruction, Nibble},
     keyboard::Keyboard,
-    memory::{Memory, START_ROM},
-    registers::Registers,
+    memory::{Memory, BIG_FONT_START, START_ROM},
+    quirks::Quirks,
+    random::{RandomSource, DEFAULT_SEED},
+    registers::StackError,
+    state::{StateError, StateReader, StateWriter},
 };
 
+mod recompiler;
+
+use recompiler::Blocks;
+
+pub use crate::memory::MEMORY_SIZE;
+pub use crate::registers::Registers;
+
+/// The pitch XO-CHIP starts with, which plays the audio pattern at 4000 bits per second.
+const DEFAULT_PITCH: u8 = 64;
+
+/// Why the interpreter could not execute an instruction or load a rom. Failing instructions
+/// leave the state unchanged, `pc` is the address of the instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExecutionError {
+    /// A CALL with all 16 levels of the stack in use.
+    StackOverflow { pc: u16 },
+    /// A RET without a matching CALL.
+    StackUnderflow { pc: u16 },
+    /// An access to `address`, which is past the end of memory.
+    MemoryOutOfRange { pc: u16, address: usize },
+    /// The word at `pc` does not encode any known instruction.
+    InvalidOpcode { pc: u16, opcode: u16 },
+    /// A rom of `size` bytes, when only `max` bytes fit into memory.
+    RomTooLarge { size: usize, max: usize },
+}
+
+impl fmt::Display for ExecutionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExecutionError::StackOverflow { pc } => write!(f, "Stack overflow at {:#05X}", pc),
+            ExecutionError::StackUnderflow { pc } => write!(f, "Stack underflow at {:#05X}", pc),
+            ExecutionError::MemoryOutOfRange { pc, address } => {
+                write!(f, "Memory access to {:#06X} out of range at {:#05X}", address, pc)
+            }
+            ExecutionError::InvalidOpcode { pc, opcode } => {
+                write!(f, "Unknown instruction: {:#06x} at {:#05X}", opcode, pc)
+            }
+            ExecutionError::RomTooLarge { size, max } => {
+                write!(f, "ROM of {} bytes does not fit into the {} bytes of memory", size, max)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ExecutionError {}
+
+/// The memory an instruction read or wrote, see [`Interpreter::last_memory_access`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryAccess {
+    pub address: usize,
+    pub len: usize,
+    pub write: bool,
+}
+
+/// Watches the instructions an interpreter executes, e.g. to trace or profile a program, see
+/// [`Interpreter::add_observer`].
+pub trait Observer {
+    /// Called after the instruction at `pc` was executed, with the registers before and after it.
+    /// Instructions that wait for a key or the next frame are reported once they complete.
+    fn executed(&mut self, pc: u16, instruction: Instruction, before: &Registers, after: &Registers);
+}
+
+/// Lets the caller keep a handle to an observer, e.g. to read a profile after the run.
+impl<T: Observer> Observer for Rc<RefCell<T>> {
+    fn executed(&mut self, pc: u16, instruction: Instruction, before: &Registers, after: &Registers) {
+        self.borrow_mut().executed(pc, instruction, before, after);
+    }
+}
+
+/// What happened during a successful [`Interpreter::step`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepOutcome {
+    /// The instruction was executed and the program counter moved on.
+    Executed,
+    /// The instruction waits for a key press or the next vertical blank and is executed again
+    /// by the next step.
+    Waiting,
+    /// The program exited with 00FD - EXIT, no further instructions are executed.
+    Exited,
+}
+
+/// How [`Interpreter::run_frame`] executes instructions, see [`Interpreter::set_engine`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Engine {
+    /// Decodes and executes one instruction at a time.
+    Interpreter,
+    /// Compiles the straight-line instructions up to the next jump, call, return, skip or key
+    /// wait into a block of closures once and runs whole blocks. Blocks are forgotten when the
+    /// program writes over them.
+    Recompiler,
+}
+
+impl Engine {
+    pub const ALL: [Engine; 2] = [Engine::Interpreter, Engine::Recompiler];
+
+    /// The name used to select the engine on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            Engine::Interpreter => "interpreter",
+            Engine::Recompiler => "recompiler",
+        }
+    }
+}
+
+impl fmt::Display for Engine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Engine {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Engine::ALL
+            .iter()
+            .copied()
+            .find(|engine| engine.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                let names: Vec<&str> = Engine::ALL.iter().map(|engine| engine.name()).collect();
+                format!("unknown engine `{}`, expected one of {}", s, names.join(", "))
+            })
+    }
+}
+
 pub struct Interpreter {
     registers: Registers,
     memory: Memory,
     display: Display,
     keyboard: Keyboard,
+    /// The built-in generator, whose position is part of save states.
     rng: ChaCha8Rng,
+    /// Replaces the built-in generator for Cxkk if set, see [`Interpreter::set_random_source`].
+    random_source: Option<Box<dyn RandomSource>>,
+    quirks: Quirks,
+    /// Whether a vertical blank happened since the last sprite was drawn, see `Quirks::display_wait`.
+    vblank: bool,
+    /// Set once the program executed 00FD - EXIT.
+    exited: bool,
+    /// The RPL user flags of the HP-48, used by SUPER-CHIP games to persist e.g. high scores.
+    rpl_flags: [u8; 16],
+    /// The 1-bit XO-CHIP audio pattern loaded with F002, `None` plays the classic tone.
+    audio_pattern: Option<[u8; 16]>,
+    /// The XO-CHIP playback pitch set with Fx3A.
+    pitch: u8,
+    /// The memory accessed by the last instruction, for watchpoints.
+    memory_access: Option<MemoryAccess>,
+    observers: Vec<Box<dyn Observer>>,
+    /// The instructions decoded so far, `None` if every instruction is decoded when it is executed.
+    decode_cache: Option<DecodeCache>,
+    /// The blocks compiled so far, `None` unless the recompiler is the engine.
+    blocks: Option<Blocks>,
 }
 
 impl Interpreter {
+    /// # Panics
+    ///
+    /// If the rom does not fit into memory, see [`Interpreter::try_new`].
     pub fn with_rom(bytes: &[u8]) -> Self {
-        let mut memory = Memory::new();
+        Self::with_quirks(bytes, Quirks::default())
+    }
+
+    /// # Panics
+    ///
+    /// If the rom does not fit into memory, see [`Interpreter::try_new`].
+    pub fn with_quirks(bytes: &[u8], quirks: Quirks) -> Self {
+        Self::new(bytes, quirks, MEMORY_SIZE)
+    }
+
+    /// # Panics
+    ///
+    /// If the rom does not fit into memory, see [`Interpreter::try_new`].
+    pub fn new(bytes: &[u8], quirks: Quirks, memory_size: usize) -> Self {
+        Self::try_new(bytes, quirks, memory_size).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Creates an interpreter with `memory_size` bytes of RAM, see [`Platform::memory_size`].
+    /// Fails with [`ExecutionError::RomTooLarge`] if the rom does not fit.
+    ///
+    /// [`Platform::memory_size`]: crate::quirks::Platform::memory_size
+    pub fn try_new(bytes: &[u8], quirks: Quirks, memory_size: usize) -> Result<Self, ExecutionError> {
+        let mut memory = Memory::with_size(memory_size);
+        if bytes.len() > memory.max_rom_size() {
+            return Err(ExecutionError::RomTooLarge {
+                size: bytes.len(),
+                max: memory.max_rom_size(),
+            });
+        }
         memory.load_rom(bytes);
 
-        let mut registers = Registers::default();
-        registers.pc = START_ROM as u16;
+        let registers = Registers {
+            pc: START_ROM as u16,
+            ..Default::default()
+        };
 
         let display = Display::new();
         let keyboard = Keyboard::new();
 
-        let rng = ChaCha8Rng::seed_from_u64(09122022);
-        Interpreter {
+        let rng = ChaCha8Rng::seed_from_u64(DEFAULT_SEED);
+        let decode_cache = Some(DecodeCache::new(memory.0.len()));
+        Ok(Interpreter {
             registers,
             memory,
             display,
             keyboard,
             rng,
+            random_source: None,
+            quirks,
+            vblank: false,
+            exited: false,
+            rpl_flags: [0; 16],
+            audio_pattern: None,
+            pitch: DEFAULT_PITCH,
+            memory_access: None,
+            observers: Vec::new(),
+            decode_cache,
+            blocks: None,
+        })
+    }
+
+    /// Executes the instruction at the program counter.
+    pub fn step(&mut self) -> Result<StepOutcome, ExecutionError> {
+        if self.exited {
+            return Ok(StepOutcome::Exited);
         }
-    }
 
-    pub fn step(&mut self) {
-        self.registers.delay = self.registers.delay.saturating_sub(1);
-        self.registers.sound = self.registers.sound.saturating_sub(1);
+        self.memory_access = None;
 
-        let pc = self.registers.pc as usize;
-
-        let cur: u16 = u16::from_be_bytes(self.memory.0[pc..pc + 2].try_into().unwrap());
+        let pc = self.registers.pc;
+        let start = pc as usize;
+        if start + 2 > self.memory.0.len() {
+            return Err(ExecutionError::MemoryOutOfRange { pc, address: start + 1 });
+        }
+        let end = (start + 4).min(self.memory.0.len());
+
+        let instruction = match self.decode_cache.as_ref().and_then(|cache| cache.get(start)) {
+            Some(instruction) => instruction,
+            None => {
+                let instruction = Instruction::decode_bytes(&self.memory.0[start..end])
+                    .map_err(|e| ExecutionError::InvalidOpcode { pc, opcode: e.opcode })?;
+                if let Some(cache) = &mut self.decode_cache {
+                    cache.insert(start, instruction);
+                }
+                instruction
+            }
+        };
 
-        let _first_byte = self.memory.0[pc];
-        let second_byte = self.memory.0[pc + 1];
+        if self.observers.is_empty() {
+            return self.execute(instruction);
+        }
 
-        let first_nibble = ((cur & 0xF000) >> 12) as u8;
-        let second_nibble = ((cur & 0x0F00) >> 8) as u8;
-        let third_nibble = ((cur & 0x0F0) >> 4) as u8;
-        let fourth_nibble = (cur & 0x000F) as u8;
+        let before = self.registers.clone();
+        let outcome = self.execute(instruction)?;
+        if outcome != StepOutcome::Waiting {
+            for observer in &mut self.observers {
+                observer.executed(pc, instruction, &before, &self.registers);
+            }
+        }
 
-        let bottom_tribble = cur & 0x0FFF;
+        Ok(outcome)
+    }
 
-        // println!("A: {:#01X}, B: {:#01X}, C: {:#01X}, D: {:#01X}", first_nibble, second_nibble, third_nibble, fourth_nibble);
+    fn execute(&mut self, instruction: Instruction) -> Result<StepOutcome, ExecutionError> {
+        use Instruction::*;
 
-        if cur == 0x00E0 {
-            self.handle_clear();
-        } else if cur == 0x00EE {
-            self.handle_ret();
-        } else {
-            match first_nibble {
-                0x0 | 0x1 => {
-                    self.handle_jump(bottom_tribble);
-                    return;
-                }
-                0x2 => {
-                    self.handle_call(bottom_tribble);
-                    return;
-                }
-                0x3 => self.handle_skip_if_equal_immediate(second_nibble as usize, second_byte),
-                0x4 => self.handle_skip_if_not_equal_immediate(second_nibble as usize, second_byte),
-                0x5 if fourth_nibble == 0 => {
-                    self.handle_skip_if_equal_register(second_nibble as usize, third_nibble as usize)
-                }
-                0x6 => self.handle_load_register_immediate(second_nibble as usize, second_byte),
-                0x7 => self.handle_add_register_immediate(second_nibble as usize, second_byte),
-                0x8 if fourth_nibble == 0 => {
-                    self.handle_load_register_register(second_nibble as usize, third_nibble as usize)
-                }
-                0x8 if fourth_nibble == 1 => {
-                    self.handle_or_register_register(second_nibble as usize, third_nibble as usize)
-                }
-                0x8 if fourth_nibble == 2 => {
-                    self.handle_and_register_register(second_nibble as usize, third_nibble as usize)
-                }
-                0x8 if fourth_nibble == 3 => {
-                    self.handle_xor_register_register(second_nibble as usize, third_nibble as usize)
-                }
-                0x8 if fourth_nibble == 4 => {
-                    self.handle_add_register_register(second_nibble as usize, third_nibble as usize)
-                }
-                0x8 if fourth_nibble == 5 => {
-                    self.handle_sub_register_register(second_nibble as usize, third_nibble as usize)
-                }
-                0x8 if fourth_nibble == 6 => {
-                    self.handle_shift_right_register_one(second_nibble as usize, third_nibble as usize)
-                }
-                0x8 if fourth_nibble == 7 => {
-                    self.handle_sub_register_register_negated(second_nibble as usize, third_nibble as usize)
-                }
-                0x8 if fourth_nibble == 0xE => {
-                    self.handle_shift_left_register_one(second_nibble as usize, third_nibble as usize)
-                }
-                0x9 if fourth_nibble == 0 => {
-                    self.handle_skip_if_not_equal_register(second_nibble as usize, third_nibble as usize)
-                }
-                0xA => self.handle_load_immediate_into_i(bottom_tribble),
-                0xB => {
-                    self.handle_jump_relative(bottom_tribble);
-                    return;
+        match instruction {
+            Clear => self.handle_clear(),
+            Ret => self.handle_ret()?,
+            Sys(Addr(n)) | Jump(Addr(n)) => {
+                self.handle_jump(n);
+                return Ok(StepOutcome::Executed);
+            }
+            Call(Addr(n)) => {
+                self.handle_call(n)?;
+                return Ok(StepOutcome::Executed);
+            }
+            SkipIfEqualImmediate(x, Byte(k)) => self.handle_skip_if_equal_immediate(x.index(), k),
+            SkipIfNotEqualImmediate(x, Byte(k)) => self.handle_skip_if_not_equal_immediate(x.index(), k),
+            SkipIfEqualRegister(x, y) => self.handle_skip_if_equal_register(x.index(), y.index()),
+            LoadRegisterImmediate(x, Byte(k)) => self.handle_load_register_immediate(x.index(), k),
+            AddRegisterImmediate(x, Byte(k)) => self.handle_add_register_immediate(x.index(), k),
+            LoadRegisterRegister(x, y) => self.handle_load_register_register(x.index(), y.index()),
+            OrRegisterRegister(x, y) => self.handle_or_register_register(x.index(), y.index()),
+            AndRegisterRegister(x, y) => self.handle_and_register_register(x.index(), y.index()),
+            XorRegisterRegister(x, y) => self.handle_xor_register_register(x.index(), y.index()),
+            AddRegisterRegister(x, y) => self.handle_add_register_register(x.index(), y.index()),
+            SubRegisterRegister(x, y) => self.handle_sub_register_register(x.index(), y.index()),
+            ShiftRightRegisterOne(x, y) => self.handle_shift_right_register_one(x.index(), y.index()),
+            SubRegisterRegisterNegated(x, y) => self.handle_sub_register_register_negated(x.index(), y.index()),
+            ShiftLeftRegisterOne(x, y) => self.handle_shift_left_register_one(x.index(), y.index()),
+            SkipIfNotEqualRegister(x, y) => self.handle_skip_if_not_equal_register(x.index(), y.index()),
+            LoadImmediateIntoI(Addr(n)) => self.handle_load_immediate_into_i(n),
+            JumpRelative(Addr(n)) => {
+                self.handle_jump_relative(n);
+                return Ok(StepOutcome::Executed);
+            }
+            Random(x, Byte(k)) => self.handle_random(x.index(), k),
+            DrawSprite(x, y, Nibble(n)) => {
+                if self.quirks.display_wait && !self.vblank {
+                    return Ok(StepOutcome::Waiting);
                 }
-                0xC => self.handle_random(second_nibble as usize, second_byte),
-                0xD => self.handle_draw_sprite(second_nibble, third_nibble, fourth_nibble),
-                0xE if second_byte == 0x9E => self.handle_skip_if_key_pressed(second_nibble as usize),
-                0xE if second_byte == 0xA1 => self.handle_skip_if_key_not_pressed(second_nibble as usize),
-                0xF if second_byte == 0x07 => self.handle_store_delay_timer_register(second_nibble as usize),
-                0xF if second_byte == 0x0A => {
-                    self.handle_wait_for_keypress(second_nibble as usize);
-                    return;
+                self.handle_draw_sprite(x.0, y.0, n)?;
+                self.vblank = false;
+            }
+            SkipIfKeyPressed(x) => self.handle_skip_if_key_pressed(x.index()),
+            SkipIfKeyNotPressed(x) => self.handle_skip_if_key_not_pressed(x.index()),
+            StoreDelayTimerRegister(x) => self.handle_store_delay_timer_register(x.index()),
+            WaitForKeypress(x) => {
+                if !self.handle_wait_for_keypress(x.index()) {
+                    return Ok(StepOutcome::Waiting);
                 }
-                0xF if second_byte == 0x15 => self.handle_load_delay_timer_register(second_nibble as usize),
-                0xF if second_byte == 0x18 => self.handle_load_sound_timer_register(second_nibble as usize),
-                0xF if second_byte == 0x29 => self.handle_load_digit_sprite_location(second_nibble as usize),
-                0xF if second_byte == 0x1E => self.handle_add_i_register(second_nibble as usize),
-                0xF if second_byte == 0x33 => self.handle_load_bcd(second_nibble as usize),
-                0xF if second_byte == 0x55 => self.handle_store_registers_in_memory(second_nibble as usize),
-                0xF if second_byte == 0x65 => self.handle_load_registers_from_memory(second_nibble as usize),
-                _ => eprintln!("Unknown instruction: {:#02x}", cur),
             }
+            LoadDelayTimerRegister(x) => self.handle_load_delay_timer_register(x.index()),
+            LoadSoundTimerRegister(x) => self.handle_load_sound_timer_register(x.index()),
+            AddIRegister(x) => self.handle_add_i_register(x.index()),
+            LoadDigitSpriteLocation(x) => self.handle_load_digit_sprite_location(x.index()),
+            LoadBcd(x) => self.handle_load_bcd(x.index())?,
+            StoreRegistersInMemory(x) => self.handle_store_registers_in_memory(x.index())?,
+            LoadRegistersFromMemory(x) => self.handle_load_registers_from_memory(x.index())?,
+            ScrollDown(Nibble(n)) => self.handle_scroll_down(n),
+            ScrollRight => self.handle_scroll_right(),
+            ScrollLeft => self.handle_scroll_left(),
+            Exit => {
+                self.handle_exit();
+                return Ok(StepOutcome::Exited);
+            }
+            LowResolution => self.handle_low_resolution(),
+            HighResolution => self.handle_high_resolution(),
+            LoadBigDigitSpriteLocation(x) => self.handle_load_big_digit_sprite_location(x.index()),
+            StoreFlags(x) => self.handle_store_flags(x.index()),
+            LoadFlags(x) => self.handle_load_flags(x.index()),
+            StoreRegisterRange(x, y) => self.handle_store_register_range(x.index(), y.index())?,
+            LoadRegisterRange(x, y) => self.handle_load_register_range(x.index(), y.index())?,
+            LoadLongI(Addr(n)) => self.handle_load_long_i(n),
+            SelectPlanes(Nibble(n)) => self.handle_select_planes(n),
+            LoadAudioPattern => self.handle_load_audio_pattern()?,
+            LoadPitch(x) => self.handle_load_pitch(x.index()),
         }
 
-        self.registers.pc += 2;
+        self.registers.pc = self.registers.pc.wrapping_add(instruction.size());
+        Ok(StepOutcome::Executed)
+    }
+
+    /// Reads the memory at `address..address + len`, if it does not reach past the end of memory.
+    fn memory(&mut self, address: usize, len: usize) -> Result<&[u8], ExecutionError> {
+        let pc = self.registers.pc;
+        self.memory_access = Some(MemoryAccess {
+            address,
+            len,
+            write: false,
+        });
+        self.memory
+            .0
+            .get(address..address + len)
+            .ok_or_else(|| ExecutionError::MemoryOutOfRange {
+                pc,
+                address: (address + len).saturating_sub(1),
+            })
+    }
+
+    /// The mutable memory at `address..address + len`, see [`Interpreter::memory`].
+    fn memory_mut(&mut self, address: usize, len: usize) -> Result<&mut [u8], ExecutionError> {
+        let pc = self.registers.pc;
+        self.memory_access = Some(MemoryAccess {
+            address,
+            len,
+            write: true,
+        });
+        if let Some(cache) = &mut self.decode_cache {
+            cache.invalidate(address, len);
+        }
+        if let Some(blocks) = &mut self.blocks {
+            blocks.invalidate(address, len);
+        }
+        self.memory
+            .0
+            .get_mut(address..address + len)
+            .ok_or_else(|| ExecutionError::MemoryOutOfRange {
+                pc,
+                address: (address + len).saturating_sub(1),
+            })
+    }
+
+    fn stack_error(&self, error: StackError) -> ExecutionError {
+        let pc = self.registers.pc;
+        match error {
+            StackError::Overflow => ExecutionError::StackOverflow { pc },
+            StackError::Underflow => ExecutionError::StackUnderflow { pc },
+        }
     }
 
     fn handle_clear(&mut self) {
@@ -146,8 +417,8 @@ impl Interpreter {
     /// Return from a subroutine.
     ///
     /// The interpreter sets the program counter to the address at the top of the stack, then subtracts 1 from the stack pointer.
-    fn handle_ret(&mut self) {
-        self.registers.pop();
+    fn handle_ret(&mut self) -> Result<(), ExecutionError> {
+        self.registers.pop().map_err(|e| self.stack_error(e))
     }
 
     /// 1nnn - JP addr
@@ -162,8 +433,8 @@ impl Interpreter {
     /// Call subroutine at nnn.
     ///
     /// The interpreter increments the stack pointer, then puts the current PC on the top of the stack. The PC is then set to nnn.
-    fn handle_call(&mut self, n: u16) {
-        self.registers.push(n);
+    fn handle_call(&mut self, n: u16) -> Result<(), ExecutionError> {
+        self.registers.push(n).map_err(|e| self.stack_error(e))
     }
 
     /// 3xkk - SE Vx, byte
@@ -172,7 +443,7 @@ impl Interpreter {
     /// The interpreter compares register Vx to kk, and if they are equal, increments the program counter by 2.
     fn handle_skip_if_equal_immediate(&mut self, x: usize, k: u8) {
         if self.registers.vx[x] == k {
-            self.registers.pc += 2;
+            self.skip_next_instruction();
         }
     }
 
@@ -182,7 +453,7 @@ impl Interpreter {
     /// The interpreter compares register Vx to kk, and if they are not equal, increments the program counter by 2.
     fn handle_skip_if_not_equal_immediate(&mut self, x: usize, k: u8) {
         if self.registers.vx[x] != k {
-            self.registers.pc += 2;
+            self.skip_next_instruction();
         }
     }
 
@@ -192,10 +463,19 @@ impl Interpreter {
     /// The interpreter compares register Vx to register Vy, and if they are equal, increments the program counter by 2.
     fn handle_skip_if_equal_register(&mut self, x: usize, y: usize) {
         if self.registers.vx[x] == self.registers.vx[y] {
-            self.registers.pc += 2;
+            self.skip_next_instruction();
         }
     }
 
+    /// Moves the program counter past the instruction after the current one, which takes 4 bytes
+    /// instead of 2 if it is `F000 nnnn`.
+    fn skip_next_instruction(&mut self) {
+        let next = self.registers.pc as usize + 2;
+        let is_long = self.memory.0.get(next..next + 2) == Some(&Instruction::LONG_PREFIX.to_be_bytes()[..]);
+
+        self.registers.pc = self.registers.pc.wrapping_add(if is_long { 4 } else { 2 });
+    }
+
     /// 6xkk - LD Vx, byte
     /// Set Vx = kk.
     ///
@@ -227,6 +507,10 @@ impl Interpreter {
     /// Performs a bitwise OR on the values of Vx and Vy, then stores the result in Vx.
     fn handle_or_register_register(&mut self, x: usize, y: usize) {
         self.registers.vx[x] |= self.registers.vx[y];
+
+        if self.quirks.vf_reset {
+            self.registers.vx[0xF] = 0;
+        }
     }
 
     /// 8xy2 - AND Vx, Vy
@@ -235,6 +519,10 @@ impl Interpreter {
     /// Performs a bitwise AND on the values of Vx and Vy, then stores the result in Vx.
     fn handle_and_register_register(&mut self, x: usize, y: usize) {
         self.registers.vx[x] &= self.registers.vx[y];
+
+        if self.quirks.vf_reset {
+            self.registers.vx[0xF] = 0;
+        }
     }
 
     /// 8xy3 - XOR Vx, Vy
@@ -243,6 +531,10 @@ impl Interpreter {
     /// Performs a bitwise XOR on the values of Vx and Vy, then stores the result in Vx.
     fn handle_xor_register_register(&mut self, x: usize, y: usize) {
         self.registers.vx[x] ^= self.registers.vx[y];
+
+        if self.quirks.vf_reset {
+            self.registers.vx[0xF] = 0;
+        }
     }
 
     /// 8xy4 - ADD Vx, Vy
@@ -287,8 +579,13 @@ impl Interpreter {
     /// Set Vx = Vx SHR 1.
     ///
     /// If the least-significant bit of Vx is 1, then VF is set to 1, otherwise 0. Then Vx is divided by 2.
-    fn handle_shift_right_register_one(&mut self, x: usize, _y: usize) {
-        let a = self.registers.vx[x];
+    /// With `Quirks::shift_uses_vy` Vy is shifted instead and the result stored in Vx.
+    fn handle_shift_right_register_one(&mut self, x: usize, y: usize) {
+        let a = if self.quirks.shift_uses_vy {
+            self.registers.vx[y]
+        } else {
+            self.registers.vx[x]
+        };
 
         let underflow = a & 1 == 1;
         let result = a >> 1;
@@ -325,8 +622,13 @@ impl Interpreter {
     /// Set Vx = Vx SHL 1.
     ///
     /// If the most-significant bit of Vx is 1, then VF is set to 1, otherwise to 0. Then Vx is multiplied by 2.
-    fn handle_shift_left_register_one(&mut self, x: usize, _y: usize) {
-        let a = self.registers.vx[x];
+    /// With `Quirks::shift_uses_vy` Vy is shifted instead and the result stored in Vx.
+    fn handle_shift_left_register_one(&mut self, x: usize, y: usize) {
+        let a = if self.quirks.shift_uses_vy {
+            self.registers.vx[y]
+        } else {
+            self.registers.vx[x]
+        };
 
         let overflow = a & 0b1000_0000 > 1;
         let result = a << 1;
@@ -346,7 +648,7 @@ impl Interpreter {
     /// The values of Vx and Vy are compared, and if they are not equal, the program counter is increased by 2.
     fn handle_skip_if_not_equal_register(&mut self, x: usize, y: usize) {
         if self.registers.vx[x] != self.registers.vx[y] {
-            self.registers.pc += 2;
+            self.skip_next_instruction();
         }
     }
 
@@ -361,9 +663,11 @@ impl Interpreter {
     /// Bnnn - JP V0, addr
     /// Jump to location nnn + V0.
     ///
-    /// The program counter is set to nnn plus the value of V0.
+    /// The program counter is set to nnn plus the value of V0. With `Quirks::jump_uses_vx` the
+    /// highest nibble of nnn selects the register instead of V0.
     fn handle_jump_relative(&mut self, n: u16) {
-        self.registers.pc = n.wrapping_add(self.registers.vx[0].into());
+        let x = if self.quirks.jump_uses_vx { (n >> 8) as usize } else { 0 };
+        self.registers.pc = n.wrapping_add(self.registers.vx[x].into());
     }
 
     /// Cxkk - RND Vx, byte
@@ -371,7 +675,10 @@ impl Interpreter {
     ///
     /// The interpreter generates a random number from 0 to 255, which is then ANDed with the value kk.
     fn handle_random(&mut self, x: usize, k: u8) {
-        let v = self.rng.gen_range(0..=255);
+        let v = match &mut self.random_source {
+            Some(source) => source.next_byte(),
+            None => self.rng.next_byte(),
+        };
         self.registers.vx[x] = v & k;
     }
 
@@ -384,38 +691,81 @@ impl Interpreter {
     /// to 0. If the sprite is positioned so part of it is outside the coordinates of the display, it
     /// wraps around to the opposite side of the screen. See instruction 8xy3 for more information on
     /// XOR, and section 2.4, Display, for more information on the Chip-8 screen and sprites.
-    fn handle_draw_sprite(&mut self, x: u8, y: u8, n: u8) {
-        let mut was_cleared = false;
-
-        let mut row: usize = self.registers.vx[y as usize].into();
-
-        for offset in 0..n {
-            let idx = self.registers.i as usize + offset as usize;
-            let sprite = &self.memory.0[idx];
-
-            let mut mask = 0b1000_0000;
-
-            let mut col: usize = self.registers.vx[x as usize].into();
+    ///
+    /// With `Quirks::clip_sprites` only the starting position wraps around, the parts of the sprite
+    /// that are outside of the display are not drawn.
+    ///
+    /// SUPER-CHIP: If n is 0, a 16x16 sprite is drawn from 32 bytes, two bytes per row. In high
+    /// resolution mode VF is set to the number of rows that collided or were clipped at the bottom.
+    ///
+    /// XO-CHIP: The sprite is drawn to every selected plane, each plane reads the next sprite
+    /// from memory, so with both planes selected the data for the second plane follows the first.
+    /// With no plane selected nothing is drawn or read and VF is set to 0.
+    fn handle_draw_sprite(&mut self, x: u8, y: u8, n: u8) -> Result<(), ExecutionError> {
+        let selected_planes = self.display.selected_planes();
+        if selected_planes == 0 {
+            self.registers.vx[0xF] = 0;
+            return Ok(());
+        }
 
-            for _ in 0..8 {
-                let value = (sprite & mask) > 0;
-                if self.display.xor_pixel(col, row, value) {
-                    was_cleared = true;
+        let (rows, cols) = if n == 0 { (16, 16) } else { (n as usize, 8) };
+        let bytes_per_row = cols / 8;
+
+        let width = self.display.width();
+        let height = self.display.height();
+        let x0 = self.registers.vx[x as usize] as usize % width;
+        let y0 = self.registers.vx[y as usize] as usize % height;
+
+        let mut collided = vec![false; rows];
+        let mut clipped_rows = 0;
+
+        let planes: Vec<usize> = (0..PLANES)
+            .filter(|plane| selected_planes & (1 << plane) != 0)
+            .collect();
+        let sprite_size = rows * bytes_per_row;
+        let sprites = self
+            .memory(self.registers.i as usize, planes.len() * sprite_size)?
+            .to_vec();
+
+        for (sprite_number, plane) in planes.into_iter().enumerate() {
+            let start = sprite_number * sprite_size;
+
+            for (offset, collided) in collided.iter_mut().enumerate() {
+                let row = y0 + offset;
+                if self.quirks.clip_sprites && row >= height {
+                    clipped_rows = rows - offset;
+                    break;
                 }
 
-                mask >>= 1;
-
-                col += 1;
+                let idx = start + offset * bytes_per_row;
+                let sprite = if bytes_per_row == 2 {
+                    u16::from_be_bytes([sprites[idx], sprites[idx + 1]])
+                } else {
+                    u16::from(sprites[idx]) << 8
+                };
+
+                for bit in 0..cols {
+                    let col = x0 + bit;
+                    if self.quirks.clip_sprites && col >= width {
+                        break;
+                    }
+
+                    let value = (sprite & (0x8000 >> bit)) > 0;
+                    if self.display.xor_plane_pixel(plane, col, row, value) {
+                        *collided = true;
+                    }
+                }
             }
-
-            row += 1;
         }
 
-        if was_cleared {
-            self.registers.vx[0xF] = 1;
+        let collided_rows = collided.iter().filter(|collided| **collided).count();
+        self.registers.vx[0xF] = if self.display.is_high_resolution() {
+            (collided_rows + clipped_rows) as u8
         } else {
-            self.registers.vx[0xF] = 0;
-        }
+            (collided_rows > 0).into()
+        };
+
+        Ok(())
     }
 
     /// Ex9E - SKP Vx
@@ -426,7 +776,7 @@ impl Interpreter {
         let keycode = self.registers.vx[x];
 
         if self.keyboard.is_pressed(keycode) {
-            self.registers.pc += 2;
+            self.skip_next_instruction();
         }
     }
 
@@ -438,7 +788,7 @@ impl Interpreter {
         let keycode = self.registers.vx[x];
 
         if !self.keyboard.is_pressed(keycode) {
-            self.registers.pc += 2;
+            self.skip_next_instruction();
         }
     }
 
@@ -454,10 +804,14 @@ impl Interpreter {
     /// Wait for a key press, store the value of the key in Vx.
     ///
     /// All execution stops until a key is pressed, then the value of that key is stored in Vx.
-    fn handle_wait_for_keypress(&mut self, x: usize) {
-        if let Some(keycode) = self.keyboard.wait_for_keypress() {
-            self.registers.vx[x] = keycode;
-            self.registers.pc += 2;
+    /// Returns whether a key was pressed.
+    fn handle_wait_for_keypress(&mut self, x: usize) -> bool {
+        match self.keyboard.wait_for_keypress() {
+            Some(keycode) => {
+                self.registers.vx[x] = keycode;
+                true
+            }
+            None => false,
         }
     }
 
@@ -498,35 +852,351 @@ impl Interpreter {
     /// Store BCD representation of Vx in memory locations I, I+1, and I+2.
     ///
     /// The interpreter takes the decimal value of Vx, and places the hundreds digit in memory at location in I, the tens digit at location I+1, and the ones digit at location I+2.
-    fn handle_load_bcd(&mut self, x: usize) {
-        let i = self.registers.i as usize;
+    fn handle_load_bcd(&mut self, x: usize) -> Result<(), ExecutionError> {
         let vx = self.registers.vx[x];
 
-        self.memory.0[i + 0] = vx / 100;
-        self.memory.0[i + 1] = (vx % 100) / 10;
-        self.memory.0[i + 2] = vx % 10;
+        let digits = self.memory_mut(self.registers.i as usize, 3)?;
+        digits[0] = vx / 100;
+        digits[1] = (vx % 100) / 10;
+        digits[2] = vx % 10;
+
+        Ok(())
     }
 
     /// Fx55 - LD [I], Vx
     /// Store registers V0 through Vx in memory starting at location I.
     ///
     /// The interpreter copies the values of registers V0 through Vx into memory, starting at the address in I.
-    fn handle_store_registers_in_memory(&mut self, x: usize) {
-        let i = self.registers.i as usize;
-        for offset in 0..=x {
-            self.memory.0[i + offset] = self.registers.vx[offset];
+    /// With `Quirks::load_store_increments_i` I is incremented by x + 1 afterwards.
+    fn handle_store_registers_in_memory(&mut self, x: usize) -> Result<(), ExecutionError> {
+        let values = self.registers.vx;
+        self.memory_mut(self.registers.i as usize, x + 1)?
+            .copy_from_slice(&values[..=x]);
+
+        if self.quirks.load_store_increments_i {
+            self.registers.i = self.registers.i.wrapping_add(x as u16 + 1);
         }
+
+        Ok(())
     }
 
     /// Fx65 - LD Vx, [I]
     /// Read registers V0 through Vx from memory starting at location I.
     ///
     /// The interpreter reads values from memory starting at location I into registers V0 through Vx.
-    fn handle_load_registers_from_memory(&mut self, x: usize) {
-        let i = self.registers.i as usize;
-        for offset in 0..=x {
-            self.registers.vx[offset] = self.memory.0[i + offset];
+    /// With `Quirks::load_store_increments_i` I is incremented by x + 1 afterwards.
+    fn handle_load_registers_from_memory(&mut self, x: usize) -> Result<(), ExecutionError> {
+        let values = self.memory(self.registers.i as usize, x + 1)?.to_vec();
+        self.registers.vx[..=x].copy_from_slice(&values);
+
+        if self.quirks.load_store_increments_i {
+            self.registers.i = self.registers.i.wrapping_add(x as u16 + 1);
+        }
+
+        Ok(())
+    }
+
+    /// 00Cn - SCD nibble
+    /// Scroll display n lines down.
+    ///
+    /// The rows that are scrolled in at the top are blank, the bottom rows are discarded.
+    fn handle_scroll_down(&mut self, n: u8) {
+        self.display.scroll_down(n.into());
+    }
+
+    /// 00FB - SCR
+    /// Scroll display 4 pixels right.
+    fn handle_scroll_right(&mut self) {
+        self.display.scroll_right(4);
+    }
+
+    /// 00FC - SCL
+    /// Scroll display 4 pixels left.
+    fn handle_scroll_left(&mut self) {
+        self.display.scroll_left(4);
+    }
+
+    /// 00FD - EXIT
+    /// Exit the interpreter.
+    ///
+    /// The program counter stays on this instruction and no further instructions are executed.
+    fn handle_exit(&mut self) {
+        self.exited = true;
+    }
+
+    /// 00FE - LOW
+    /// Disable extended screen mode.
+    ///
+    /// Switches back to the 64x32 display, which is cleared.
+    fn handle_low_resolution(&mut self) {
+        self.display.set_high_resolution(false);
+    }
+
+    /// 00FF - HIGH
+    /// Enable extended screen mode for full-screen graphics.
+    ///
+    /// Switches to the 128x64 display, which is cleared.
+    fn handle_high_resolution(&mut self) {
+        self.display.set_high_resolution(true);
+    }
+
+    /// Fx30 - LD HF, Vx
+    /// Set I = location of the 8x10 sprite for digit Vx.
+    ///
+    /// The value of I is set to the location of the large hexadecimal sprite corresponding to the value of Vx.
+    fn handle_load_big_digit_sprite_location(&mut self, x: usize) {
+        self.registers.i = BIG_FONT_START as u16 + (self.registers.vx[x] as u16 & 0xF) * 10;
+    }
+
+    /// Fx75 - LD R, Vx
+    /// Store V0 through Vx in the RPL user flags.
+    ///
+    /// The HP-48 has 8 RPL user flags, XO-CHIP extends them to 16 so every register can be saved.
+    fn handle_store_flags(&mut self, x: usize) {
+        self.rpl_flags[..=x].copy_from_slice(&self.registers.vx[..=x]);
+    }
+
+    /// Fx85 - LD Vx, R
+    /// Read V0 through Vx from the RPL user flags.
+    fn handle_load_flags(&mut self, x: usize) {
+        self.registers.vx[..=x].copy_from_slice(&self.rpl_flags[..=x]);
+    }
+
+    /// 5xy2 - LD [I], Vx-Vy
+    /// Store registers Vx through Vy in memory starting at location I.
+    ///
+    /// The registers are stored in the order given, x may be larger than y. I is not changed.
+    fn handle_store_register_range(&mut self, x: usize, y: usize) -> Result<(), ExecutionError> {
+        let registers = register_range(x, y);
+        let values = self.registers.vx;
+
+        let memory = self.memory_mut(self.registers.i as usize, registers.len())?;
+        for (offset, register) in registers.into_iter().enumerate() {
+            memory[offset] = values[register];
+        }
+
+        Ok(())
+    }
+
+    /// 5xy3 - LD Vx-Vy, [I]
+    /// Read registers Vx through Vy from memory starting at location I.
+    ///
+    /// The registers are loaded in the order given, x may be larger than y. I is not changed.
+    fn handle_load_register_range(&mut self, x: usize, y: usize) -> Result<(), ExecutionError> {
+        let registers = register_range(x, y);
+
+        let values = self.memory(self.registers.i as usize, registers.len())?.to_vec();
+        for (offset, register) in registers.into_iter().enumerate() {
+            self.registers.vx[register] = values[offset];
+        }
+
+        Ok(())
+    }
+
+    /// F000 nnnn - LD I, long addr
+    /// Set I = nnnn.
+    ///
+    /// The address is the 16-bit word following the instruction, which can reach all of XO-CHIP's 64 KiB.
+    fn handle_load_long_i(&mut self, n: u16) {
+        self.registers.i = n;
+    }
+
+    /// Fn01 - PLANE nibble
+    /// Select the bitplanes n.
+    ///
+    /// Drawing, clearing and scrolling only affect the selected planes, 0 selects none of them.
+    fn handle_select_planes(&mut self, n: u8) {
+        self.display.select_planes(n);
+    }
+
+    /// F002 - LD AUDIO, [I]
+    /// Load the 16 bytes starting at I into the audio pattern buffer.
+    ///
+    /// While the sound timer is active the 128 bits of the pattern are played in a loop.
+    fn handle_load_audio_pattern(&mut self) -> Result<(), ExecutionError> {
+        let mut pattern = [0; 16];
+        pattern.copy_from_slice(self.memory(self.registers.i as usize, 16)?);
+        self.audio_pattern = Some(pattern);
+
+        Ok(())
+    }
+
+    /// Fx3A - LD PITCH, Vx
+    /// Set the playback pitch = Vx.
+    ///
+    /// The pattern is played at 4000 * 2^((Vx - 64) / 48) bits per second.
+    fn handle_load_pitch(&mut self, x: usize) {
+        self.pitch = self.registers.vx[x];
+    }
+
+    /// Captures the complete machine, including the position in the random number stream, so that
+    /// [`Interpreter::load_state`] continues exactly where the state was saved.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new();
+
+        self.registers.write_state(&mut writer);
+        self.memory.write_state(&mut writer);
+        self.display.write_state(&mut writer);
+        self.keyboard.write_state(&mut writer);
+
+        writer.bytes(&self.rng.get_seed());
+        writer.u64(self.rng.get_stream());
+        writer.u128(self.rng.get_word_pos());
+
+        let quirks = self.quirks;
+        for quirk in [
+            quirks.shift_uses_vy,
+            quirks.load_store_increments_i,
+            quirks.jump_uses_vx,
+            quirks.vf_reset,
+            quirks.clip_sprites,
+            quirks.display_wait,
+        ] {
+            writer.bool(quirk);
+        }
+
+        writer.bool(self.vblank);
+        writer.bool(self.exited);
+        writer.bytes(&self.rpl_flags);
+        writer.bool(self.audio_pattern.is_some());
+        writer.bytes(&self.audio_pattern.unwrap_or_default());
+        writer.u8(self.pitch);
+
+        writer.finish()
+    }
+
+    /// Restores a state created by [`Interpreter::save_state`]. The interpreter is left
+    /// unchanged if the state cannot be restored.
+    pub fn load_state(&mut self, bytes: &[u8]) -> Result<(), StateError> {
+        let mut reader = StateReader::new(bytes)?;
+
+        let registers = Registers::read_state(&mut reader)?;
+        let memory = Memory::read_state(&mut reader)?;
+        let display = Display::read_state(&mut reader)?;
+        let keyboard = Keyboard::read_state(&mut reader)?;
+
+        let mut rng = ChaCha8Rng::from_seed(reader.array()?);
+        rng.set_stream(reader.u64()?);
+        rng.set_word_pos(reader.u128()?);
+
+        let quirks = Quirks {
+            shift_uses_vy: reader.bool()?,
+            load_store_increments_i: reader.bool()?,
+            jump_uses_vx: reader.bool()?,
+            vf_reset: reader.bool()?,
+            clip_sprites: reader.bool()?,
+            display_wait: reader.bool()?,
+        };
+
+        let vblank = reader.bool()?;
+        let exited = reader.bool()?;
+        let rpl_flags = reader.array()?;
+        let has_audio_pattern = reader.bool()?;
+        let audio_pattern: [u8; 16] = reader.array()?;
+        let pitch = reader.u8()?;
+        reader.finish()?;
+
+        let decode_cache = self.decode_cache.is_some().then(|| DecodeCache::new(memory.0.len()));
+        let blocks = self.blocks.is_some().then(|| Blocks::new(memory.0.len()));
+        *self = Interpreter {
+            registers,
+            memory,
+            display,
+            keyboard,
+            rng,
+            random_source: self.random_source.take(),
+            quirks,
+            vblank,
+            exited,
+            rpl_flags,
+            audio_pattern: has_audio_pattern.then_some(audio_pattern),
+            pitch,
+            memory_access: None,
+            observers: std::mem::take(&mut self.observers),
+            decode_cache,
+            blocks,
+        };
+
+        Ok(())
+    }
+
+    /// Turns the cache of decoded instructions on or off, it is on by default. Without it every
+    /// instruction is decoded again each time it is executed, which is slower but uses less memory.
+    pub fn set_decode_cache(&mut self, enabled: bool) {
+        self.decode_cache = enabled.then(|| DecodeCache::new(self.memory.0.len()));
+    }
+
+    /// Selects how [`Interpreter::run_frame`] executes instructions, the interpreter by default.
+    /// Both engines leave the interpreter in the same state, [`Interpreter::step`] always
+    /// interprets a single instruction. Observers are only supported by the interpreter, the
+    /// recompiler falls back to it while any are added.
+    pub fn set_engine(&mut self, engine: Engine) {
+        if engine != self.engine() {
+            self.blocks = (engine == Engine::Recompiler).then(|| Blocks::new(self.memory.0.len()));
+        }
+    }
+
+    pub fn engine(&self) -> Engine {
+        match self.blocks {
+            Some(_) => Engine::Recompiler,
+            None => Engine::Interpreter,
+        }
+    }
+
+    /// Reports every instruction executed from now on to `observer`.
+    pub fn add_observer(&mut self, observer: Box<dyn Observer>) {
+        self.observers.push(observer);
+    }
+
+    /// Restarts the built-in generator with `seed`, by default [`DEFAULT_SEED`] is used.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = ChaCha8Rng::seed_from_u64(seed);
+    }
+
+    /// Takes the random bytes for Cxkk from `source` instead of the built-in generator. Save
+    /// states only capture the built-in generator, an injected source keeps its position
+    /// when a state is loaded.
+    pub fn set_random_source(&mut self, source: Box<dyn RandomSource>) {
+        self.random_source = Some(source);
+    }
+
+    /// Decrements the delay and sound timers, needs to be called 60 times per second
+    /// independent of how many instructions are executed.
+    pub fn tick_timers(&mut self) {
+        self.registers.delay = self.registers.delay.saturating_sub(1);
+        self.registers.sound = self.registers.sound.saturating_sub(1);
+    }
+
+    /// Emulates one 60 Hz frame: signals the vertical blank, executes up to `cycles_per_frame`
+    /// instructions and ticks the timers. Returns early if the program exits or an instruction fails.
+    pub fn run_frame(&mut self, cycles_per_frame: u32) -> Result<(), ExecutionError> {
+        self.vblank();
+
+        let mut remaining = cycles_per_frame;
+        while remaining > 0 {
+            let (cycles, outcome) = if self.blocks.is_some() && self.observers.is_empty() {
+                self.run_block(remaining)?
+            } else {
+                (1, self.step()?)
+            };
+            if outcome == StepOutcome::Exited {
+                break;
+            }
+            remaining -= cycles;
         }
+
+        self.tick_timers();
+        Ok(())
+    }
+
+    /// Signals a vertical blank, i.e. the start of a new 60 Hz frame. Only needed with
+    /// `Quirks::display_wait`, where sprites are not drawn until the next vertical blank.
+    pub fn vblank(&mut self) {
+        self.vblank = true;
+    }
+
+    pub fn quirks(&self) -> &Quirks {
+        &self.quirks
     }
 
     pub fn display(&self) -> &Display {
@@ -544,11 +1214,87 @@ impl Interpreter {
     pub fn sound_timer_active(&self) -> bool {
         self.registers.sound > 0
     }
+
+    /// The XO-CHIP audio pattern, `None` until the program loaded one.
+    pub fn audio_pattern(&self) -> Option<&[u8; 16]> {
+        self.audio_pattern.as_ref()
+    }
+
+    pub fn pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    /// The number of pattern bits played per second at the current pitch.
+    pub fn playback_rate(&self) -> f32 {
+        4000.0 * 2f32.powf((self.pitch as f32 - 64.0) / 48.0)
+    }
+
+    pub fn rpl_flags(&self) -> &[u8; 16] {
+        &self.rpl_flags
+    }
+
+    /// Restores previously persisted RPL user flags, missing flags are set to 0.
+    pub fn load_rpl_flags(&mut self, flags: &[u8]) {
+        let len = flags.len().min(self.rpl_flags.len());
+        self.rpl_flags = [0; 16];
+        self.rpl_flags[..len].copy_from_slice(&flags[..len]);
+    }
+
+    /// Whether the program stopped itself with 00FD - EXIT.
+    pub fn has_exited(&self) -> bool {
+        self.exited
+    }
+
+    /// The memory read or written by the last executed instruction, e.g. by Fx55 or Dxyn.
+    /// Instruction fetches do not count.
+    pub fn last_memory_access(&self) -> Option<MemoryAccess> {
+        self.memory_access
+    }
+
+    pub fn registers(&self) -> &Registers {
+        &self.registers
+    }
+
+    pub(crate) fn registers_mut(&mut self) -> &mut Registers {
+        &mut self.registers
+    }
+
+    /// All of memory, including the interpreter area below the rom.
+    pub(crate) fn ram(&self) -> &[u8] {
+        &self.memory.0
+    }
+
+    /// All of memory for writing, which forgets the instructions decoded so far.
+    pub(crate) fn ram_mut(&mut self) -> &mut [u8] {
+        if let Some(cache) = &mut self.decode_cache {
+            cache.clear();
+        }
+        if let Some(blocks) = &mut self.blocks {
+            blocks.clear();
+        }
+        &mut self.memory.0
+    }
+}
+
+/// The registers from x to y inclusive, counting down if x is larger than y.
+fn register_range(x: usize, y: usize) -> Vec<usize> {
+    if x <= y {
+        (x..=y).collect()
+    } else {
+        (y..=x).rev().collect()
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::Interpreter;
+    use super::{ExecutionError, Interpreter, MemoryAccess, Observer, Registers, StepOutcome};
+    use crate::instruction::Instruction;
+    use crate::memory::BIG_FONT_START;
+    use crate::quirks::{Platform, Quirks};
+    use crate::random::ScriptedRandom;
+    use crate::state::StateError;
+    use std::cell::RefCell;
+    use std::rc::Rc;
     use test_case::test_case;
 
     #[test]
@@ -559,7 +1305,7 @@ mod tests {
         let rom: &[u8] = &[0x17, 0x89];
         let mut interpreter = Interpreter::with_rom(rom);
 
-        interpreter.step();
+        interpreter.step().unwrap();
 
         assert_eq!(interpreter.registers.pc, 0x789);
     }
@@ -569,7 +1315,7 @@ mod tests {
         let rom: &[u8] = &[0x21, 0x23];
         let mut interpreter = Interpreter::with_rom(rom);
 
-        interpreter.step();
+        interpreter.step().unwrap();
         assert_eq!(interpreter.registers.sp, 1);
         assert_eq!(interpreter.registers.pc, 0x123);
     }
@@ -579,11 +1325,11 @@ mod tests {
         let rom: &[u8] = &[0x22, 0x06, 0x00, 0xE0, 0x00, 0xE0, 0x00, 0xEE];
         let mut interpreter = Interpreter::with_rom(rom);
 
-        interpreter.step();
+        interpreter.step().unwrap();
         assert_eq!(interpreter.registers.sp, 1);
         assert_eq!(interpreter.registers.pc, 0x206);
 
-        interpreter.step();
+        interpreter.step().unwrap();
         assert_eq!(interpreter.registers.sp, 0);
         assert_eq!(interpreter.registers.pc, 0x202);
     }
@@ -595,7 +1341,7 @@ mod tests {
         let mut interpreter = Interpreter::with_rom(rom);
         interpreter.registers.vx[x as usize] = vx;
 
-        interpreter.step();
+        interpreter.step().unwrap();
 
         assert_eq!(interpreter.registers.pc, pc);
     }
@@ -607,7 +1353,7 @@ mod tests {
         let mut interpreter = Interpreter::with_rom(rom);
         interpreter.registers.vx[x as usize] = vx;
 
-        interpreter.step();
+        interpreter.step().unwrap();
 
         assert_eq!(interpreter.registers.pc, pc);
     }
@@ -620,7 +1366,7 @@ mod tests {
         interpreter.registers.vx[x as usize] = vx;
         interpreter.registers.vx[y as usize] = vy;
 
-        interpreter.step();
+        interpreter.step().unwrap();
 
         assert_eq!(interpreter.registers.pc, pc);
     }
@@ -630,7 +1376,7 @@ mod tests {
         let rom: &[u8] = &[0x61, 0x23];
         let mut interpreter = Interpreter::with_rom(rom);
 
-        interpreter.step();
+        interpreter.step().unwrap();
 
         assert_eq!(interpreter.registers.vx[1], 0x23);
     }
@@ -640,8 +1386,8 @@ mod tests {
         let rom: &[u8] = &[0x73, 0x21, 0x73, 0x10];
         let mut interpreter = Interpreter::with_rom(rom);
 
-        interpreter.step();
-        interpreter.step();
+        interpreter.step().unwrap();
+        interpreter.step().unwrap();
 
         assert_eq!(interpreter.registers.vx[3], 0x31);
     }
@@ -653,7 +1399,7 @@ mod tests {
 
         interpreter.registers.vx[0xC] = 0x23;
 
-        interpreter.step();
+        interpreter.step().unwrap();
 
         assert_eq!(interpreter.registers.vx[0xA], 0x23);
     }
@@ -666,7 +1412,7 @@ mod tests {
         interpreter.registers.vx[0xB] = 0x23;
         interpreter.registers.vx[0xD] = 0x42;
 
-        interpreter.step();
+        interpreter.step().unwrap();
 
         assert_eq!(interpreter.registers.vx[0xB], 0x63);
     }
@@ -679,7 +1425,7 @@ mod tests {
         interpreter.registers.vx[0xE] = 0x23;
         interpreter.registers.vx[0x1] = 0x42;
 
-        interpreter.step();
+        interpreter.step().unwrap();
 
         assert_eq!(interpreter.registers.vx[0xE], 0x2);
     }
@@ -692,7 +1438,7 @@ mod tests {
         interpreter.registers.vx[0x9] = 0x15;
         interpreter.registers.vx[0x7] = 0x37;
 
-        interpreter.step();
+        interpreter.step().unwrap();
 
         assert_eq!(interpreter.registers.vx[0x9], 0x22);
     }
@@ -707,7 +1453,7 @@ mod tests {
         interpreter.registers.vx[x as usize] = vx;
         interpreter.registers.vx[y as usize] = vy;
 
-        interpreter.step();
+        interpreter.step().unwrap();
 
         assert_eq!(interpreter.registers.vx[x as usize], result, "Result wrong");
         assert_eq!(interpreter.registers.vx[0xF], carry, "Carry wrong");
@@ -723,7 +1469,7 @@ mod tests {
         interpreter.registers.vx[x as usize] = vx;
         interpreter.registers.vx[y as usize] = vy;
 
-        interpreter.step();
+        interpreter.step().unwrap();
 
         assert_eq!(interpreter.registers.vx[x as usize], result, "Result wrong");
         assert_eq!(interpreter.registers.vx[0xF], underflow, "Underflow wrong");
@@ -738,7 +1484,7 @@ mod tests {
         let mut interpreter = Interpreter::with_rom(rom);
         interpreter.registers.vx[x as usize] = vx;
 
-        interpreter.step();
+        interpreter.step().unwrap();
 
         assert_eq!(interpreter.registers.vx[x as usize], result, "Result wrong");
         assert_eq!(interpreter.registers.vx[0xF], underflow, "Underflow wrong");
@@ -754,7 +1500,7 @@ mod tests {
         interpreter.registers.vx[x as usize] = vx;
         interpreter.registers.vx[y as usize] = vy;
 
-        interpreter.step();
+        interpreter.step().unwrap();
 
         assert_eq!(interpreter.registers.vx[x as usize], result, "Result wrong");
         assert_eq!(interpreter.registers.vx[0xF], underflow, "Underflow wrong");
@@ -769,7 +1515,7 @@ mod tests {
         let mut interpreter = Interpreter::with_rom(rom);
         interpreter.registers.vx[x as usize] = vx;
 
-        interpreter.step();
+        interpreter.step().unwrap();
 
         assert_eq!(interpreter.registers.vx[x as usize], result, "Result wrong");
         assert_eq!(interpreter.registers.vx[0xF], overflow, "Overflow wrong");
@@ -783,7 +1529,7 @@ mod tests {
         interpreter.registers.vx[x as usize] = vx;
         interpreter.registers.vx[y as usize] = vy;
 
-        interpreter.step();
+        interpreter.step().unwrap();
 
         assert_eq!(interpreter.registers.pc, pc);
     }
@@ -793,7 +1539,7 @@ mod tests {
         let rom: &[u8] = &[0xA6, 0x78];
         let mut interpreter = Interpreter::with_rom(rom);
 
-        interpreter.step();
+        interpreter.step().unwrap();
 
         assert_eq!(interpreter.registers.i, 0x678);
     }
@@ -804,7 +1550,7 @@ mod tests {
         let mut interpreter = Interpreter::with_rom(rom);
         interpreter.registers.vx[0] = 0x13;
 
-        interpreter.step();
+        interpreter.step().unwrap();
 
         assert_eq!(interpreter.registers.pc, 0x678 + 0x13);
     }
@@ -814,11 +1560,40 @@ mod tests {
         let rom: &[u8] = &[0xC1, 0xFF];
         let mut interpreter = Interpreter::with_rom(rom);
 
-        interpreter.step();
+        interpreter.step().unwrap();
 
         assert_ne!(interpreter.registers.vx[1], 0);
     }
 
+    #[test]
+    fn test_handle_random_with_scripted_source() {
+        let rom: &[u8] = &[0xC1, 0xFF, 0xC2, 0x0F, 0xC3, 0xFF];
+        let mut interpreter = Interpreter::with_rom(rom);
+        interpreter.set_random_source(Box::new(ScriptedRandom::new(vec![0x42, 0xAB])));
+
+        for _ in 0..3 {
+            interpreter.step().unwrap();
+        }
+
+        assert_eq!(interpreter.registers.vx[1..4], [0x42, 0x0B, 0x42]);
+    }
+
+    #[test]
+    fn test_set_seed() {
+        let rom: &[u8] = &[0xC1, 0xFF, 0xC2, 0xFF, 0xC3, 0xFF, 0xC4, 0xFF];
+        let run = |seed: u64| {
+            let mut interpreter = Interpreter::with_rom(rom);
+            interpreter.set_seed(seed);
+            for _ in 0..4 {
+                interpreter.step().unwrap();
+            }
+            interpreter.registers.vx
+        };
+
+        assert_eq!(run(1), run(1));
+        assert_ne!(run(1), run(2));
+    }
+
     #[test_case(0x3, 0x5, Some(0x5), 0x204; "SKP Vx: wanted key is pressed")]
     #[test_case(0xE, 0x1, None,  0x202; "SKP Vx: no key pressed")]
     #[test_case(0x7, 0xB, Some(0xE),  0x202; "SKP Vx: different pressed")]
@@ -832,7 +1607,7 @@ mod tests {
 
         interpreter.registers.vx[x as usize] = vx;
 
-        interpreter.step();
+        interpreter.step().unwrap();
 
         assert_eq!(interpreter.registers.pc, pc);
     }
@@ -850,7 +1625,7 @@ mod tests {
 
         interpreter.registers.vx[x as usize] = vx;
 
-        interpreter.step();
+        interpreter.step().unwrap();
 
         assert_eq!(interpreter.registers.pc, pc);
     }
@@ -868,7 +1643,7 @@ mod tests {
         }
 
         // Wait for keypress
-        interpreter.step();
+        interpreter.step().unwrap();
 
         assert_eq!(interpreter.registers.pc, 0x200);
         assert_eq!(interpreter.registers.vx[x as usize], 0);
@@ -879,7 +1654,7 @@ mod tests {
             interpreter.keyboard_mut().release_key(keycode);
         }
 
-        interpreter.step();
+        interpreter.step().unwrap();
 
         assert_eq!(interpreter.registers.pc, pc);
         assert_eq!(interpreter.registers.vx[x as usize], vx);
@@ -891,7 +1666,7 @@ mod tests {
         let mut interpreter = Interpreter::with_rom(rom);
         interpreter.registers.vx[0xA] = 23;
 
-        interpreter.step();
+        interpreter.step().unwrap();
 
         assert_eq!(interpreter.registers.vx[0xA], 23);
     }
@@ -905,7 +1680,7 @@ mod tests {
         interpreter.registers.i = i;
         interpreter.registers.vx[x as usize] = vx;
 
-        interpreter.step();
+        interpreter.step().unwrap();
 
         assert_eq!(interpreter.registers.i, result);
     }
@@ -917,7 +1692,7 @@ mod tests {
 
         interpreter.registers.vx[0x7] = 0xA;
 
-        interpreter.step();
+        interpreter.step().unwrap();
 
         assert_eq!(interpreter.registers.i, 0xA * 5);
     }
@@ -926,12 +1701,13 @@ mod tests {
     #[test_case(0x5 , 109, 1, 0, 9; "BCD: x0z")]
     #[test_case(0x3 , 42, 0, 4, 2; "BCD: yz")]
     #[test_case(0xA , 7, 0, 0, 7; "BCD: z")]
+    #[allow(clippy::identity_op)]
     fn test_handle_bcd(x: u8, vx: u8, hundreds: u8, tens: u8, ones: u8) {
         let rom: &[u8] = &[0xF0 | x, 0x33];
         let mut interpreter = Interpreter::with_rom(rom);
         interpreter.registers.vx[x as usize] = vx;
 
-        interpreter.step();
+        interpreter.step().unwrap();
 
         assert_eq!(
             interpreter.memory.0[interpreter.registers.i as usize + 0],
@@ -943,6 +1719,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::needless_range_loop, clippy::manual_memcpy)]
     fn test_handle_store_registers_in_memory() {
         let values: Vec<u8> = vec![116, 58, 224, 135, 225, 142, 236, 47, 66, 29, 230, 171, 127, 21, 11, 147];
 
@@ -955,7 +1732,7 @@ mod tests {
                 interpreter.registers.vx[i] = values[i];
             }
 
-            interpreter.step();
+            interpreter.step().unwrap();
 
             for i in 0..=x as usize {
                 assert_eq!(interpreter.memory.0[interpreter.registers.i as usize + i], values[i]);
@@ -968,6 +1745,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::needless_range_loop, clippy::manual_memcpy)]
     fn test_handle_load_registers_from_memory() {
         let values: Vec<u8> = vec![116, 58, 224, 135, 225, 142, 236, 47, 66, 29, 230, 171, 127, 21, 11, 147];
 
@@ -980,7 +1758,7 @@ mod tests {
                 interpreter.memory.0[interpreter.registers.i as usize + i] = values[i];
             }
 
-            interpreter.step();
+            interpreter.step().unwrap();
 
             for i in 0..=x as usize {
                 assert_eq!(interpreter.registers.vx[i], values[i]);
@@ -997,8 +1775,642 @@ mod tests {
         let rom: &[u8] = &[0xA6, 0x78];
         let mut interpreter = Interpreter::with_rom(rom);
 
-        interpreter.step();
+        interpreter.step().unwrap();
 
         assert_eq!(interpreter.registers.i, 0x678);
     }
+
+    #[test_case(0x1, 0x2, 0x6, 0b101, 0b10, 1; "SHR: vx, vy")]
+    #[test_case(0x1, 0x2, 0xE, 0b1000_0001, 0b10, 1; "SHL: vx, vy")]
+    fn test_quirk_shift_uses_vy(x: u8, y: u8, n: u8, vy: u8, result: u8, flag: u8) {
+        let rom: &[u8] = &[0x80 | x, (y << 4) | n];
+        let quirks = Quirks {
+            shift_uses_vy: true,
+            ..Default::default()
+        };
+        let mut interpreter = Interpreter::with_quirks(rom, quirks);
+        interpreter.registers.vx[x as usize] = 0xFF;
+        interpreter.registers.vx[y as usize] = vy;
+
+        interpreter.step().unwrap();
+
+        assert_eq!(interpreter.registers.vx[x as usize], result, "Result wrong");
+        assert_eq!(interpreter.registers.vx[0xF], flag, "Flag wrong");
+    }
+
+    #[test_case(0x55; "LD [I], Vx")]
+    #[test_case(0x65; "LD Vx, [I]")]
+    fn test_quirk_load_store_increments_i(kk: u8) {
+        let rom: &[u8] = &[0xF3, kk];
+        let quirks = Quirks {
+            load_store_increments_i: true,
+            ..Default::default()
+        };
+        let mut interpreter = Interpreter::with_quirks(rom, quirks);
+        interpreter.registers.i = 0x400;
+
+        interpreter.step().unwrap();
+
+        assert_eq!(interpreter.registers.i, 0x404);
+    }
+
+    #[test]
+    fn test_quirk_jump_uses_vx() {
+        let rom: &[u8] = &[0xB6, 0x78];
+        let quirks = Quirks {
+            jump_uses_vx: true,
+            ..Default::default()
+        };
+        let mut interpreter = Interpreter::with_quirks(rom, quirks);
+        interpreter.registers.vx[0] = 0x13;
+        interpreter.registers.vx[6] = 0x2;
+
+        interpreter.step().unwrap();
+
+        assert_eq!(interpreter.registers.pc, 0x678 + 0x2);
+    }
+
+    #[test_case(0x1; "OR")]
+    #[test_case(0x2; "AND")]
+    #[test_case(0x3; "XOR")]
+    fn test_quirk_vf_reset(n: u8) {
+        let rom: &[u8] = &[0x81, 0x20 | n];
+        let quirks = Quirks {
+            vf_reset: true,
+            ..Default::default()
+        };
+        let mut interpreter = Interpreter::with_quirks(rom, quirks);
+        interpreter.registers.vx[0xF] = 0x42;
+
+        interpreter.step().unwrap();
+
+        assert_eq!(interpreter.registers.vx[0xF], 0);
+    }
+
+    #[test_case(false, true; "wrap")]
+    #[test_case(true, false; "clip")]
+    fn test_quirk_clip_sprites(clip_sprites: bool, wrapped: bool) {
+        // Draws an 8x2 sprite at (60, 31), half of it is outside of the display.
+        let rom: &[u8] = &[0xD0, 0x12];
+        let quirks = Quirks {
+            clip_sprites,
+            ..Default::default()
+        };
+        let mut interpreter = Interpreter::with_quirks(rom, quirks);
+        interpreter.registers.vx[0] = 60;
+        interpreter.registers.vx[1] = 31;
+        interpreter.registers.i = 0x400;
+        interpreter.memory.0[0x400] = 0xFF;
+        interpreter.memory.0[0x401] = 0xFF;
+
+        interpreter.step().unwrap();
+
+        assert!(interpreter.display().pixel(63, 31));
+        assert_eq!(interpreter.display().pixel(0, 31), wrapped);
+        assert_eq!(interpreter.display().pixel(60, 0), wrapped);
+        assert_eq!(interpreter.registers.vx[0xF], 0);
+    }
+
+    #[test_case(Platform::CosmacVip; "vip")]
+    #[test_case(Platform::Chip48; "chip48")]
+    #[test_case(Platform::SuperChip; "schip")]
+    fn test_clipped_sprite_without_collision(platform: Platform) {
+        // LD I, 0x000; DRW V0, V1, 5 draws the 0 of the font at (0, 30)
+        let rom: &[u8] = &[0xA0, 0x00, 0xD0, 0x15];
+        let mut interpreter = Interpreter::with_quirks(rom, platform.quirks());
+        interpreter.registers.vx[1] = 30;
+
+        interpreter.step().unwrap();
+        interpreter.vblank();
+        interpreter.step().unwrap();
+
+        assert!(interpreter.display().pixel(0, 31));
+        assert_eq!(interpreter.registers.vx[0xF], 0);
+    }
+
+    #[test]
+    fn test_quirk_display_wait() {
+        let rom: &[u8] = &[0xD0, 0x01, 0xD0, 0x01];
+        let quirks = Quirks {
+            display_wait: true,
+            ..Default::default()
+        };
+        let mut interpreter = Interpreter::with_quirks(rom, quirks);
+
+        interpreter.step().unwrap();
+        assert_eq!(interpreter.registers.pc, 0x200);
+
+        interpreter.vblank();
+        interpreter.step().unwrap();
+        assert_eq!(interpreter.registers.pc, 0x202);
+
+        interpreter.step().unwrap();
+        assert_eq!(interpreter.registers.pc, 0x202);
+    }
+
+    #[test]
+    fn test_handle_high_low_resolution() {
+        let rom: &[u8] = &[0x00, 0xFF, 0x00, 0xFE];
+        let mut interpreter = Interpreter::with_rom(rom);
+
+        interpreter.step().unwrap();
+        assert_eq!(interpreter.display().width(), 128);
+        assert_eq!(interpreter.display().height(), 64);
+
+        interpreter.step().unwrap();
+        assert_eq!(interpreter.display().width(), 64);
+        assert_eq!(interpreter.display().height(), 32);
+    }
+
+    #[test]
+    fn test_handle_draw_sprite_high_resolution() {
+        let rom: &[u8] = &[0x00, 0xFF, 0xD0, 0x11];
+        let mut interpreter = Interpreter::with_rom(rom);
+        interpreter.registers.vx[0] = 120;
+        interpreter.registers.vx[1] = 63;
+        interpreter.registers.i = 0x400;
+        interpreter.memory.0[0x400] = 0b1000_0001;
+
+        interpreter.step().unwrap();
+        interpreter.step().unwrap();
+
+        assert!(interpreter.display().pixel(120, 63));
+        assert!(interpreter.display().pixel(127, 63));
+        assert!(!interpreter.display().pixel(0, 63));
+    }
+
+    #[test]
+    fn test_handle_scroll() {
+        let rom: &[u8] = &[0xD0, 0x11, 0x00, 0xC3, 0x00, 0xFB, 0x00, 0xFC, 0x00, 0xFC];
+        let mut interpreter = Interpreter::with_rom(rom);
+        interpreter.registers.vx[0] = 8;
+        interpreter.registers.i = 0x400;
+        interpreter.memory.0[0x400] = 0b1000_0000;
+
+        interpreter.step().unwrap();
+        assert!(interpreter.display().pixel(8, 0));
+
+        interpreter.step().unwrap();
+        assert!(interpreter.display().pixel(8, 3));
+
+        interpreter.step().unwrap();
+        assert!(interpreter.display().pixel(12, 3));
+
+        interpreter.step().unwrap();
+        interpreter.step().unwrap();
+        assert!(interpreter.display().pixel(4, 3));
+    }
+
+    #[test]
+    fn test_handle_exit() {
+        let rom: &[u8] = &[0x00, 0xFD, 0x60, 0x01];
+        let mut interpreter = Interpreter::with_rom(rom);
+
+        interpreter.step().unwrap();
+        interpreter.step().unwrap();
+
+        assert!(interpreter.has_exited());
+        assert_eq!(interpreter.registers.pc, 0x200);
+        assert_eq!(interpreter.registers.vx[0], 0);
+    }
+
+    #[test_case(false, 1; "low resolution")]
+    #[test_case(true, 2; "high resolution")]
+    fn test_handle_draw_big_sprite(hires: bool, collisions: u8) {
+        let rom: &[u8] = &[0xD0, 0x10, 0xD0, 0x10];
+        let mut interpreter = Interpreter::with_rom(rom);
+        interpreter.display.set_high_resolution(hires);
+        interpreter.registers.vx[0] = 4;
+        interpreter.registers.vx[1] = 2;
+        interpreter.registers.i = 0x400;
+        interpreter.memory.0[0x400] = 0x80;
+        interpreter.memory.0[0x401] = 0x01;
+        interpreter.memory.0[0x41E] = 0xFF;
+
+        interpreter.step().unwrap();
+
+        assert!(interpreter.display().pixel(4, 2));
+        assert!(interpreter.display().pixel(19, 2));
+        assert!(!interpreter.display().pixel(20, 2));
+        assert!(interpreter.display().pixel(4, 17));
+        assert!(!interpreter.display().pixel(12, 17));
+        assert_eq!(interpreter.registers.vx[0xF], 0);
+
+        interpreter.step().unwrap();
+
+        assert_eq!(interpreter.registers.vx[0xF], collisions);
+    }
+
+    #[test]
+    fn test_handle_load_big_digit_sprite_location() {
+        let rom: &[u8] = &[0xF7, 0x30];
+        let mut interpreter = Interpreter::with_rom(rom);
+        interpreter.registers.vx[0x7] = 0x9;
+
+        interpreter.step().unwrap();
+
+        let i = interpreter.registers.i as usize;
+        assert_eq!(i, BIG_FONT_START + 9 * 10);
+        assert_eq!(interpreter.memory.0[i..i + 4], [0xFF, 0xFF, 0xC3, 0xC3]);
+    }
+
+    #[test]
+    fn test_handle_store_load_flags() {
+        let rom: &[u8] = &[0xF2, 0x75, 0x60, 0x00, 0xF1, 0x85];
+        let mut interpreter = Interpreter::with_rom(rom);
+        interpreter.registers.vx[..3].copy_from_slice(&[7, 8, 9]);
+
+        interpreter.step().unwrap();
+        assert_eq!(interpreter.rpl_flags()[..4], [7, 8, 9, 0]);
+
+        interpreter.step().unwrap();
+        interpreter.registers.vx[1] = 0;
+        interpreter.step().unwrap();
+        assert_eq!(interpreter.registers.vx[..3], [7, 8, 9]);
+    }
+
+    #[test]
+    fn test_load_rpl_flags() {
+        let mut interpreter = Interpreter::with_rom(&[]);
+
+        interpreter.load_rpl_flags(&[1, 2, 3]);
+
+        assert_eq!(interpreter.rpl_flags()[..4], [1, 2, 3, 0]);
+    }
+
+    #[test]
+    fn test_handle_load_long_i() {
+        // LD I, long 0xFFF0; LD [I], V0-V2 at the very end of XO-CHIP memory.
+        let rom: &[u8] = &[0xF0, 0x00, 0xFF, 0xF0, 0x50, 0x22];
+        let mut interpreter = Interpreter::new(rom, Quirks::default(), 0x10000);
+        interpreter.registers.vx[..3].copy_from_slice(&[1, 2, 3]);
+
+        interpreter.step().unwrap();
+        assert_eq!(interpreter.registers.i, 0xFFF0);
+        assert_eq!(interpreter.registers.pc, 0x204);
+
+        interpreter.step().unwrap();
+        assert_eq!(interpreter.memory.0[0xFFF0..0xFFF3], [1, 2, 3]);
+    }
+
+    #[test_case(0x3, 0x206; "SE skips all 4 bytes of the long load")]
+    #[test_case(0x4, 0x202; "SE does not skip")]
+    fn test_skip_over_long_i(vx: u8, pc: u16) {
+        // SE V0, 0x03; LD I, long 0x1234
+        let rom: &[u8] = &[0x30, 0x03, 0xF0, 0x00, 0x12, 0x34];
+        let mut interpreter = Interpreter::new(rom, Quirks::default(), 0x10000);
+        interpreter.registers.vx[0] = vx;
+
+        interpreter.step().unwrap();
+
+        assert_eq!(interpreter.registers.pc, pc);
+    }
+
+    #[test_case(0x5132, [0, 1, 2, 3, 0]; "ascending")]
+    #[test_case(0x5312, [0, 3, 2, 1, 0]; "descending")]
+    fn test_handle_store_register_range(opcode: u16, memory: [u8; 5]) {
+        let rom = opcode.to_be_bytes();
+        let mut interpreter = Interpreter::with_rom(&rom);
+        interpreter.registers.vx[..5].copy_from_slice(&[9, 1, 2, 3, 9]);
+        interpreter.registers.i = 0x400;
+
+        interpreter.step().unwrap();
+
+        assert_eq!(interpreter.memory.0[0x3FF..0x404], memory);
+        assert_eq!(interpreter.registers.i, 0x400);
+    }
+
+    #[test]
+    fn test_handle_load_register_range() {
+        // LD VA-V8, [I]
+        let rom: &[u8] = &[0x5A, 0x83];
+        let mut interpreter = Interpreter::with_rom(rom);
+        interpreter.registers.i = 0x400;
+        interpreter.memory.0[0x400..0x403].copy_from_slice(&[1, 2, 3]);
+
+        interpreter.step().unwrap();
+
+        assert_eq!(interpreter.registers.vx[0x8..0xB], [3, 2, 1]);
+        assert_eq!(interpreter.registers.i, 0x400);
+    }
+
+    #[test]
+    fn test_draw_sprite_on_both_planes() {
+        // PLANE 3; DRW V0, V0, 1
+        let rom: &[u8] = &[0xF3, 0x01, 0xD0, 0x01];
+        let mut interpreter = Interpreter::with_rom(rom);
+        interpreter.registers.i = 0x400;
+        interpreter.memory.0[0x400] = 0b1100_0000;
+        interpreter.memory.0[0x401] = 0b1010_0000;
+
+        interpreter.step().unwrap();
+        interpreter.step().unwrap();
+
+        let colors: Vec<u8> = (0..3).map(|x| interpreter.display().color_index(x, 0)).collect();
+        assert_eq!(colors, vec![3, 1, 2]);
+        assert_eq!(interpreter.registers.vx[0xF], 0);
+    }
+
+    #[test]
+    fn test_draw_sprite_without_planes() {
+        // PLANE 0; LD I, 0x000; DRW V0, V0, 5
+        let rom: &[u8] = &[0xF0, 0x01, 0xA0, 0x00, 0xD0, 0x05];
+        let mut interpreter = Interpreter::with_rom(rom);
+        interpreter.registers.vx[0xF] = 1;
+
+        for _ in 0..3 {
+            assert_eq!(interpreter.step(), Ok(StepOutcome::Executed));
+        }
+
+        assert!((0..4).all(|x| interpreter.display().color_index(x, 0) == 0));
+        assert_eq!(interpreter.registers.vx[0xF], 0);
+        assert_eq!(interpreter.last_memory_access(), None);
+    }
+
+    #[test]
+    fn test_clear_selected_plane() {
+        // PLANE 2; CLS
+        let rom: &[u8] = &[0xF2, 0x01, 0x00, 0xE0];
+        let mut interpreter = Interpreter::with_rom(rom);
+        interpreter.display.xor_plane_pixel(0, 0, 0, true);
+        interpreter.display.xor_plane_pixel(1, 0, 0, true);
+
+        interpreter.step().unwrap();
+        interpreter.step().unwrap();
+
+        assert_eq!(interpreter.display().color_index(0, 0), 1);
+    }
+
+    #[test]
+    fn test_handle_load_audio_pattern_and_pitch() {
+        // LD AUDIO, [I]; LD PITCH, V1
+        let rom: &[u8] = &[0xF0, 0x02, 0xF1, 0x3A];
+        let mut interpreter = Interpreter::with_rom(rom);
+        interpreter.registers.i = 0x400;
+        interpreter.memory.0[0x400..0x410].copy_from_slice(&[0xF0; 16]);
+        interpreter.registers.vx[1] = 112;
+
+        assert_eq!(interpreter.audio_pattern(), None);
+        assert_eq!(interpreter.playback_rate(), 4000.0);
+
+        interpreter.step().unwrap();
+        interpreter.step().unwrap();
+
+        assert_eq!(interpreter.audio_pattern(), Some(&[0xF0; 16]));
+        assert_eq!(interpreter.pitch(), 112);
+        assert_eq!(interpreter.playback_rate(), 8000.0);
+    }
+
+    #[test]
+    fn test_step_does_not_tick_timers() {
+        // LD DT, V0; JP 0x202
+        let rom: &[u8] = &[0xF0, 0x15, 0x12, 0x02];
+        let mut interpreter = Interpreter::with_rom(rom);
+        interpreter.registers.vx[0] = 2;
+
+        for _ in 0..100 {
+            interpreter.step().unwrap();
+        }
+        assert_eq!(interpreter.registers.delay, 2);
+
+        interpreter.tick_timers();
+        assert_eq!(interpreter.registers.delay, 1);
+    }
+
+    #[test]
+    fn test_run_frame() {
+        // LD ST, V0; ADD V1, 1; JP 0x202
+        let rom: &[u8] = &[0xF0, 0x18, 0x71, 0x01, 0x12, 0x02];
+        let mut interpreter = Interpreter::with_rom(rom);
+        interpreter.registers.vx[0] = 2;
+
+        interpreter.run_frame(11).unwrap();
+
+        assert_eq!(interpreter.registers.vx[1], 5);
+        assert_eq!(interpreter.registers.sound, 1);
+        assert!(interpreter.sound_timer_active());
+
+        interpreter.run_frame(11).unwrap();
+
+        assert!(!interpreter.sound_timer_active());
+    }
+
+    #[test]
+    fn test_run_frame_with_display_wait() {
+        // DRW V0, V0, 1; JP 0x200
+        let rom: &[u8] = &[0xD0, 0x01, 0x12, 0x00];
+        let quirks = Quirks {
+            display_wait: true,
+            ..Quirks::default()
+        };
+        let mut interpreter = Interpreter::with_quirks(rom, quirks);
+
+        interpreter.run_frame(10).unwrap();
+
+        assert!(interpreter.display().pixel(0, 0), "one sprite is drawn per frame");
+        assert_eq!(interpreter.registers.pc, 0x200);
+    }
+
+    #[test]
+    fn test_stack_underflow() {
+        let rom: &[u8] = &[0x00, 0xEE];
+        let mut interpreter = Interpreter::with_rom(rom);
+
+        assert_eq!(interpreter.step(), Err(ExecutionError::StackUnderflow { pc: 0x200 }));
+        assert_eq!(interpreter.registers.pc, 0x200);
+    }
+
+    #[test]
+    fn test_stack_overflow() {
+        let rom: &[u8] = &[0x22, 0x00];
+        let mut interpreter = Interpreter::with_rom(rom);
+
+        for _ in 0..16 {
+            assert_eq!(interpreter.step(), Ok(StepOutcome::Executed));
+        }
+
+        assert_eq!(interpreter.step(), Err(ExecutionError::StackOverflow { pc: 0x200 }));
+    }
+
+    #[test]
+    fn test_invalid_opcode() {
+        let rom: &[u8] = &[0x60, 0x01, 0xFF, 0xFF];
+        let mut interpreter = Interpreter::with_rom(rom);
+
+        interpreter.step().unwrap();
+
+        assert_eq!(
+            interpreter.step(),
+            Err(ExecutionError::InvalidOpcode {
+                pc: 0x202,
+                opcode: 0xFFFF
+            })
+        );
+        assert_eq!(interpreter.registers.pc, 0x202);
+    }
+
+    #[test_case(&[0xF0, 0x33], 0xFFE, 0x1000; "LD B, Vx")]
+    #[test_case(&[0xFF, 0x55], 0xFF8, 0x1007; "LD [I], Vx")]
+    #[test_case(&[0xD0, 0x05], 0xFFF, 0x1003; "DRW Vx, Vy, nibble")]
+    fn test_memory_out_of_range(rom: &[u8], i: u16, address: usize) {
+        let mut interpreter = Interpreter::with_rom(rom);
+        interpreter.registers.i = i;
+
+        assert_eq!(
+            interpreter.step(),
+            Err(ExecutionError::MemoryOutOfRange { pc: 0x200, address })
+        );
+    }
+
+    #[test]
+    fn test_empty_memory_access() {
+        let mut interpreter = Interpreter::with_rom(&[]);
+
+        assert_eq!(interpreter.memory(0, 0), Ok(&[][..]));
+        assert_eq!(interpreter.memory_mut(0, 0).map(|memory| memory.len()), Ok(0));
+        assert_eq!(interpreter.memory(0x1000, 0), Ok(&[][..]));
+    }
+
+    #[test]
+    fn test_pc_out_of_range() {
+        let mut interpreter = Interpreter::with_rom(&[]);
+        interpreter.registers.pc = 0xFFF;
+
+        assert_eq!(
+            interpreter.step(),
+            Err(ExecutionError::MemoryOutOfRange {
+                pc: 0xFFF,
+                address: 0x1000
+            })
+        );
+    }
+
+    #[test]
+    fn test_rom_too_large() {
+        let rom = vec![0; 3585];
+
+        let result = Interpreter::try_new(&rom, Quirks::default(), 0x1000);
+
+        assert_eq!(
+            result.err(),
+            Some(ExecutionError::RomTooLarge { size: 3585, max: 3584 })
+        );
+    }
+
+    #[test]
+    fn test_step_outcomes() {
+        // LD V0, K; EXIT
+        let rom: &[u8] = &[0xF0, 0x0A, 0x00, 0xFD];
+        let mut interpreter = Interpreter::with_rom(rom);
+
+        assert_eq!(interpreter.step(), Ok(StepOutcome::Waiting));
+
+        interpreter.keyboard_mut().press_key(0x5);
+        interpreter.keyboard_mut().release_key(0x5);
+        assert_eq!(interpreter.step(), Ok(StepOutcome::Executed));
+
+        assert_eq!(interpreter.step(), Ok(StepOutcome::Exited));
+        assert_eq!(interpreter.step(), Ok(StepOutcome::Exited));
+    }
+
+    #[test]
+    fn test_save_and_load_state_replays_identically() {
+        // RND V0, 0xFF; LD F, V0; DRW V1, V2, 5; ADD V1, 3; LD V3, K; JP 0x200
+        let rom: &[u8] = &[0xC0, 0xFF, 0xF0, 0x29, 0xD1, 0x25, 0x71, 0x03, 0xF3, 0x0A, 0x12, 0x00];
+        let mut interpreter = Interpreter::with_rom(rom);
+        interpreter.run_frame(7).unwrap();
+        interpreter.keyboard_mut().press_key(0x4);
+
+        let state = interpreter.save_state();
+
+        let run = |interpreter: &mut Interpreter| {
+            for frame in 0..20 {
+                if frame % 3 == 0 {
+                    interpreter.keyboard_mut().release_key(0x4);
+                    interpreter.keyboard_mut().press_key(0x4);
+                }
+                interpreter.run_frame(9).unwrap();
+            }
+            interpreter.save_state()
+        };
+        let expected = run(&mut interpreter);
+
+        let mut restored = Interpreter::with_rom(&[]);
+        restored.load_state(&state).unwrap();
+        assert_eq!(restored.save_state(), state);
+        assert_eq!(run(&mut restored), expected);
+    }
+
+    #[test]
+    fn test_load_invalid_state() {
+        let mut interpreter = Interpreter::with_rom(&[0x60, 0x01]);
+        let mut state = interpreter.save_state();
+        interpreter.step().unwrap();
+        let before = interpreter.save_state();
+
+        assert_eq!(
+            interpreter.load_state(&state[..state.len() - 1]),
+            Err(StateError::Truncated)
+        );
+        state[0] = b'X';
+        assert_eq!(interpreter.load_state(&state), Err(StateError::BadMagic));
+
+        assert_eq!(interpreter.save_state(), before);
+    }
+
+    #[test_case(&[0xF2, 0x55] => Some((0x300, 3, true)); "LD [I], Vx")]
+    #[test_case(&[0xF2, 0x65] => Some((0x300, 3, false)); "LD Vx, [I]")]
+    #[test_case(&[0xF2, 0x33] => Some((0x300, 3, true)); "LD B, Vx")]
+    #[test_case(&[0xD0, 0x14] => Some((0x300, 4, false)); "DRW Vx, Vy, n")]
+    #[test_case(&[0x72, 0x01] => None; "ADD Vx, byte")]
+    fn test_last_memory_access(rom: &[u8]) -> Option<(usize, usize, bool)> {
+        let mut interpreter = Interpreter::with_rom(rom);
+        interpreter.registers.i = 0x300;
+
+        interpreter.step().unwrap();
+
+        interpreter
+            .last_memory_access()
+            .map(|MemoryAccess { address, len, write }| (address, len, write))
+    }
+
+    #[test_case(true; "cached")]
+    #[test_case(false; "uncached")]
+    fn test_self_modifying_code(decode_cache: bool) {
+        // ADD V3, 1; LD V0, 0x72; LD V1, 0x10; LD I, 0x200; LD [I], V1; JP 0x200
+        let rom = [0x73, 0x01, 0x60, 0x72, 0x61, 0x10, 0xA2, 0x00, 0xF1, 0x55, 0x12, 0x00];
+        let mut interpreter = Interpreter::with_rom(&rom);
+        interpreter.set_decode_cache(decode_cache);
+
+        for _ in 0..7 {
+            interpreter.step().unwrap();
+        }
+
+        // The second time 0x200 is `ADD V2, 0x10`.
+        assert_eq!(interpreter.registers.vx[2..4], [0x10, 0x01]);
+    }
+
+    /// Records the address and the following program counter of every executed instruction.
+    struct Recorder(Rc<RefCell<Vec<(u16, u16)>>>);
+
+    impl Observer for Recorder {
+        fn executed(&mut self, pc: u16, _: Instruction, _: &Registers, after: &Registers) {
+            self.0.borrow_mut().push((pc, after.pc));
+        }
+    }
+
+    #[test]
+    fn test_observer() {
+        // LD V0, 1; LD V1, K
+        let mut interpreter = Interpreter::with_rom(&[0x60, 0x01, 0xF1, 0x0A]);
+        let executed = Rc::new(RefCell::new(Vec::new()));
+        interpreter.add_observer(Box::new(Recorder(executed.clone())));
+
+        assert_eq!(interpreter.step(), Ok(StepOutcome::Executed));
+        assert_eq!(interpreter.step(), Ok(StepOutcome::Waiting));
+        interpreter.registers.pc = 0xFFF;
+        assert!(interpreter.step().is_err());
+
+        assert_eq!(*executed.borrow(), vec![(0x200, 0x202)]);
+    }
 }
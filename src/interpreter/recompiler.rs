@@ -0,0 +1,377 @@
+use std::rc::Rc;
+
+use super::{ExecutionError, Interpreter, StepOutcome};
+use crate::instruction::{Addr, Byte, Instruction};
+
+/// The most instructions compiled into a single block.
+const MAX_BLOCK_LEN: usize = 64;
+
+/// Executes one instruction, leaving the interpreter exactly as [`Interpreter::step`] would.
+type Op = Box<dyn Fn(&mut Interpreter) -> Result<StepOutcome, ExecutionError>>;
+
+/// Straight-line instructions, the last one jumps, calls, returns, skips or waits for a key, or
+/// the next one could not be decoded.
+pub(super) struct Block {
+    /// The address after the last instruction.
+    end: usize,
+    ops: Vec<Op>,
+}
+
+/// The block starting at every address of memory, compiled when it is first executed. Writes to
+/// memory have to be passed to [`Blocks::invalidate`].
+pub(super) struct Blocks {
+    blocks: Vec<Option<Rc<Block>>>,
+    /// The most bytes any of the blocks spans.
+    max_size: usize,
+    /// Changes whenever blocks are invalidated, so that a running block notices that it wrote
+    /// over its own instructions.
+    generation: u64,
+}
+
+impl Blocks {
+    pub fn new(memory_size: usize) -> Self {
+        Blocks {
+            blocks: vec![None; memory_size],
+            max_size: 0,
+            generation: 0,
+        }
+    }
+
+    /// The block starting at `address`, compiled from `memory` if it is not yet. `None` if
+    /// there is no valid instruction at `address`.
+    fn get(&mut self, memory: &[u8], address: usize) -> Option<Rc<Block>> {
+        let entry = self.blocks.get_mut(address)?;
+        if let Some(block) = entry {
+            return Some(block.clone());
+        }
+
+        let block = Rc::new(compile_block(memory, address)?);
+        *entry = Some(block.clone());
+        self.max_size = self.max_size.max(block.end - address);
+        Some(block)
+    }
+
+    /// Forgets the blocks that overlap `address..address + len`, including those starting before
+    /// it.
+    pub fn invalidate(&mut self, address: usize, len: usize) {
+        let start = address.saturating_sub(self.max_size).min(self.blocks.len());
+        let end = (address + len).min(self.blocks.len());
+
+        for entry in &mut self.blocks[start..end] {
+            if entry.as_ref().is_some_and(|block| block.end > address) {
+                *entry = None;
+                self.generation += 1;
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.blocks.iter_mut().for_each(|entry| *entry = None);
+        self.max_size = 0;
+        self.generation += 1;
+    }
+}
+
+fn compile_block(memory: &[u8], start: usize) -> Option<Block> {
+    let mut ops = Vec::new();
+    let mut address = start;
+
+    while ops.len() < MAX_BLOCK_LEN && address + 2 <= memory.len() {
+        let end = (address + 4).min(memory.len());
+        let instruction = match Instruction::decode_bytes(&memory[address..end]) {
+            Ok(instruction) => instruction,
+            Err(_) => break,
+        };
+
+        ops.push(compile(instruction));
+        address += instruction.size() as usize;
+
+        if ends_block(instruction) {
+            break;
+        }
+    }
+
+    if ops.is_empty() {
+        return None;
+    }
+
+    Some(Block { end: address, ops })
+}
+
+/// Whether execution may not continue with the instruction after `instruction`.
+fn ends_block(instruction: Instruction) -> bool {
+    use Instruction::*;
+
+    matches!(
+        instruction,
+        Sys(_)
+            | Jump(_)
+            | JumpRelative(_)
+            | Call(_)
+            | Ret
+            | SkipIfEqualImmediate(..)
+            | SkipIfNotEqualImmediate(..)
+            | SkipIfEqualRegister(..)
+            | SkipIfNotEqualRegister(..)
+            | SkipIfKeyPressed(_)
+            | SkipIfKeyNotPressed(_)
+            | WaitForKeypress(_)
+            | Exit
+    )
+}
+
+/// A 2-byte instruction that always continues with the next one.
+macro_rules! straight {
+    (|$interpreter:ident| $body:expr) => {
+        Box::new(move |$interpreter: &mut Interpreter| {
+            $body;
+            $interpreter.registers.pc = $interpreter.registers.pc.wrapping_add(2);
+            Ok(StepOutcome::Executed)
+        })
+    };
+}
+
+/// Binds the operands of the most frequent instructions into a closure that calls their handler
+/// directly, all others go through [`Interpreter::execute`].
+fn compile(instruction: Instruction) -> Op {
+    use Instruction::*;
+
+    match instruction {
+        Clear => straight!(|i| i.handle_clear()),
+        LoadRegisterImmediate(x, Byte(k)) => {
+            let x = x.index();
+            straight!(|i| i.handle_load_register_immediate(x, k))
+        }
+        AddRegisterImmediate(x, Byte(k)) => {
+            let x = x.index();
+            straight!(|i| i.handle_add_register_immediate(x, k))
+        }
+        LoadRegisterRegister(x, y) => {
+            let (x, y) = (x.index(), y.index());
+            straight!(|i| i.handle_load_register_register(x, y))
+        }
+        OrRegisterRegister(x, y) => {
+            let (x, y) = (x.index(), y.index());
+            straight!(|i| i.handle_or_register_register(x, y))
+        }
+        AndRegisterRegister(x, y) => {
+            let (x, y) = (x.index(), y.index());
+            straight!(|i| i.handle_and_register_register(x, y))
+        }
+        XorRegisterRegister(x, y) => {
+            let (x, y) = (x.index(), y.index());
+            straight!(|i| i.handle_xor_register_register(x, y))
+        }
+        AddRegisterRegister(x, y) => {
+            let (x, y) = (x.index(), y.index());
+            straight!(|i| i.handle_add_register_register(x, y))
+        }
+        SubRegisterRegister(x, y) => {
+            let (x, y) = (x.index(), y.index());
+            straight!(|i| i.handle_sub_register_register(x, y))
+        }
+        ShiftRightRegisterOne(x, y) => {
+            let (x, y) = (x.index(), y.index());
+            straight!(|i| i.handle_shift_right_register_one(x, y))
+        }
+        SubRegisterRegisterNegated(x, y) => {
+            let (x, y) = (x.index(), y.index());
+            straight!(|i| i.handle_sub_register_register_negated(x, y))
+        }
+        ShiftLeftRegisterOne(x, y) => {
+            let (x, y) = (x.index(), y.index());
+            straight!(|i| i.handle_shift_left_register_one(x, y))
+        }
+        LoadImmediateIntoI(Addr(n)) => straight!(|i| i.handle_load_immediate_into_i(n)),
+        Random(x, Byte(k)) => {
+            let x = x.index();
+            straight!(|i| i.handle_random(x, k))
+        }
+        StoreDelayTimerRegister(x) => {
+            let x = x.index();
+            straight!(|i| i.handle_store_delay_timer_register(x))
+        }
+        LoadDelayTimerRegister(x) => {
+            let x = x.index();
+            straight!(|i| i.handle_load_delay_timer_register(x))
+        }
+        LoadSoundTimerRegister(x) => {
+            let x = x.index();
+            straight!(|i| i.handle_load_sound_timer_register(x))
+        }
+        AddIRegister(x) => {
+            let x = x.index();
+            straight!(|i| i.handle_add_i_register(x))
+        }
+        LoadDigitSpriteLocation(x) => {
+            let x = x.index();
+            straight!(|i| i.handle_load_digit_sprite_location(x))
+        }
+        _ => Box::new(move |i: &mut Interpreter| i.execute(instruction)),
+    }
+}
+
+impl Interpreter {
+    /// Runs the block at the program counter, but at most `max_cycles` of its instructions.
+    /// Returns how many instructions were executed and the outcome of the last one. Falls back to
+    /// [`Interpreter::step`] if the recompiler is off or there is no valid instruction.
+    pub(super) fn run_block(&mut self, max_cycles: u32) -> Result<(u32, StepOutcome), ExecutionError> {
+        if self.exited {
+            return Ok((0, StepOutcome::Exited));
+        }
+
+        let pc = self.registers.pc as usize;
+        let (block, generation) = match &mut self.blocks {
+            Some(blocks) => match blocks.get(&self.memory.0, pc) {
+                Some(block) => (block, blocks.generation),
+                None => return self.step().map(|outcome| (1, outcome)),
+            },
+            None => return self.step().map(|outcome| (1, outcome)),
+        };
+
+        let mut cycles = 0;
+        for op in block.ops.iter().take(max_cycles as usize) {
+            self.memory_access = None;
+            let outcome = op(self)?;
+            cycles += 1;
+
+            let invalidated = self
+                .blocks
+                .as_ref()
+                .is_some_and(|blocks| blocks.generation != generation);
+            if outcome != StepOutcome::Executed || invalidated {
+                return Ok((cycles, outcome));
+            }
+        }
+
+        Ok((cycles, StepOutcome::Executed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler;
+    use crate::interpreter::Engine;
+    use crate::quirks::{Platform, Quirks};
+    use quickcheck_macros::quickcheck;
+    use test_case::test_case;
+
+    /// Runs `rom` block by block with the recompiler and instruction by instruction with the
+    /// interpreter, and asserts that both are in the same state after every block.
+    fn assert_lockstep(rom: &[u8], quirks: Quirks, blocks: usize) {
+        let mut reference = Interpreter::with_quirks(rom, quirks);
+        let mut recompiled = Interpreter::with_quirks(rom, quirks);
+        recompiled.set_engine(Engine::Recompiler);
+
+        for block in 0..blocks {
+            if block % 8 == 0 {
+                for interpreter in [&mut reference, &mut recompiled] {
+                    interpreter.vblank();
+                    interpreter.tick_timers();
+                }
+            }
+            // Presses a key for a while, for the programs waiting for one.
+            if block % 50 == 25 || block % 50 == 30 {
+                for interpreter in [&mut reference, &mut recompiled] {
+                    if block % 50 == 25 {
+                        interpreter.keyboard_mut().press_key(0x5);
+                    } else {
+                        interpreter.keyboard_mut().release_key(0x5);
+                    }
+                }
+            }
+
+            let pc = recompiled.registers.pc;
+            match recompiled.run_block(MAX_BLOCK_LEN as u32) {
+                Ok((0, _)) => {
+                    assert_eq!(reference.step(), Ok(StepOutcome::Exited));
+                    break;
+                }
+                Ok((cycles, outcome)) => {
+                    let outcomes: Vec<StepOutcome> = (0..cycles).map(|_| reference.step().unwrap()).collect();
+                    assert_eq!(outcomes.last(), Some(&outcome), "block at {:#05X}", pc);
+                }
+                Err(error) => {
+                    let reference_error = (0..MAX_BLOCK_LEN).find_map(|_| reference.step().err());
+                    assert_eq!(reference_error, Some(error), "block at {:#05X}", pc);
+                    assert!(reference.save_state() == recompiled.save_state());
+                    return;
+                }
+            }
+
+            assert!(
+                reference.save_state() == recompiled.save_state(),
+                "different states after block {} at {:#05X}",
+                block,
+                pc
+            );
+        }
+    }
+
+    #[test_case(include_str!("../../programs/arithmetic.asm"); "arithmetic")]
+    #[test_case(include_str!("../../programs/sprites.asm"); "sprites")]
+    #[test_case(include_str!("../../programs/subroutines.asm"); "subroutines")]
+    #[test_case(include_str!("../../programs/bcd.asm"); "bcd")]
+    #[test_case("
+        loop:
+            LD V0, K
+            LD DT, V0
+            LD ST, V0
+            SKP V0
+            JP loop
+            LD V1, DT
+            ADD V2, V1
+            JP loop
+        "; "keys and timers")]
+    #[test_case("
+            LD V0, 0x71
+            LD V1, 0x05
+        loop:
+            LD I, patch
+            LD [I], V1
+        patch:
+            ADD V2, 1
+            ADD V3, 1
+            ADD V1, 1
+            JP loop
+        "; "rewrites the running block")]
+    #[test_case("
+        start:
+            CALL start
+        "; "stack overflow")]
+    fn test_lockstep(source: &str) {
+        let rom = assembler::assemble(source).unwrap();
+
+        for quirks in [Quirks::default(), Platform::CosmacVip.quirks()] {
+            assert_lockstep(&rom, quirks, 500);
+        }
+    }
+
+    #[quickcheck]
+    fn test_lockstep_random(rom: Vec<u8>) {
+        assert_lockstep(&rom, Quirks::default(), 100);
+    }
+
+    #[test]
+    fn test_blocks() {
+        // LD V0, 1; ADD V0, 2; SE V0, 3; CLS; JP 0x200
+        let memory = [0x60, 0x01, 0x70, 0x02, 0x30, 0x03, 0x00, 0xE0, 0x12, 0x00];
+        let mut blocks = Blocks::new(memory.len());
+
+        let block = blocks.get(&memory, 0).unwrap();
+        assert_eq!((block.end, block.ops.len()), (6, 3));
+        let block = blocks.get(&memory, 6).unwrap();
+        assert_eq!((block.end, block.ops.len()), (10, 2));
+        assert!(Blocks::new(2).get(&[0xFF, 0xFF], 0).is_none());
+
+        blocks.invalidate(6, 1);
+        assert!(blocks.blocks[0].is_some() && blocks.blocks[6].is_none());
+        assert_eq!(blocks.generation, 1);
+        blocks.invalidate(0x100, 2);
+        assert_eq!(blocks.generation, 1);
+        blocks.invalidate(4, 2);
+        assert!(blocks.blocks[0].is_none());
+        assert_eq!(blocks.generation, 2);
+    }
+}
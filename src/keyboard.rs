@@ -1,3 +1,5 @@
+use crate::state::{StateError, StateReader, StateWriter};
+
 #[derive(PartialEq, Debug)]
 enum WaitingState {
     Waiting,
@@ -34,6 +36,40 @@ impl Keyboard {
         }
     }
 
+    pub(crate) fn write_state(&self, writer: &mut StateWriter) {
+        for pressed in self.pressed_keys {
+            writer.bool(pressed);
+        }
+
+        match self.waiting_state {
+            WaitingState::None => writer.u8(0),
+            WaitingState::Waiting => writer.u8(1),
+            WaitingState::Pressed { key } => {
+                writer.u8(2);
+                writer.u8(key);
+            }
+        }
+    }
+
+    pub(crate) fn read_state(reader: &mut StateReader) -> Result<Self, StateError> {
+        let mut keyboard = Keyboard::new();
+        for pressed in &mut keyboard.pressed_keys {
+            *pressed = reader.bool()?;
+        }
+
+        keyboard.waiting_state = match reader.u8()? {
+            0 => WaitingState::None,
+            1 => WaitingState::Waiting,
+            2 => match reader.u8()? {
+                key @ 0..=0xF => WaitingState::Pressed { key },
+                _ => return Err(StateError::Invalid("pressed key")),
+            },
+            _ => return Err(StateError::Invalid("keyboard waiting state")),
+        };
+
+        Ok(keyboard)
+    }
+
     pub fn wait_for_keypress(&mut self) -> Option<u8> {
         if self.waiting_state == WaitingState::None {
             self.waiting_state = WaitingState::Waiting;
@@ -47,10 +83,15 @@ impl Keyboard {
     }
 }
 
+impl Default for Keyboard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use claim;
 
     #[test]
     fn test_is_pressed() {
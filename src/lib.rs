@@ -1,9 +1,25 @@
+pub mod assembler;
+pub mod control_flow;
+pub mod database;
+pub mod debugger;
+mod decode_cache;
+pub mod disassembler;
 pub mod display;
+pub mod gdb;
+pub mod headless;
+pub mod instruction;
 pub mod interpreter;
 pub mod keyboard;
 mod memory;
+pub mod movie;
+pub mod profiler;
+pub mod quirks;
+pub mod random;
 mod registers;
+pub mod rewind;
 pub mod sound;
+pub mod state;
+pub mod trace;
 
 pub type Error = anyhow::Error;
 pub type Result<T> = anyhow::Result<T>;
@@ -1,34 +1,505 @@
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::error::Error;
-use std::path::PathBuf;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::rc::Rc;
+use std::str::FromStr;
 
-use chip8::sound::SquareWave;
-use clap::Parser;
+use chip8::sound::PatternWave;
+use clap::{Args, Parser, Subcommand};
 
-use chip8::interpreter::Interpreter;
+use chip8::control_flow::ControlFlowGraph;
+use chip8::database::{Database, Entry};
+use chip8::debugger::Debugger;
+use chip8::gdb::GdbServer;
+use chip8::headless::{self, KeyScript};
+use chip8::interpreter::{Engine, Interpreter, MEMORY_SIZE};
+use chip8::movie::Movie;
+use chip8::profiler::Profiler;
+use chip8::quirks::{Platform, Quirks};
+use chip8::random::DEFAULT_SEED;
+use chip8::rewind::Rewind;
+use chip8::trace::{AddressRange, TraceFormat, TraceLog};
+use chip8::{assembler, disassembler};
 
 use sdl2::audio::AudioSpecDesired;
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
 use sdl2::pixels::Color;
 use sdl2::rect::Point;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+
+/// The colors of the four combinations of the two XO-CHIP bitplanes, monochrome programs only
+/// use the first two.
+const PALETTE: [Color; 4] = [
+    Color::RGB(0, 0, 0),
+    Color::RGB(255, 255, 255),
+    Color::RGB(170, 170, 170),
+    Color::RGB(85, 85, 85),
+];
+
+/// The number of instructions per frame if neither `--ipf` nor the rom database sets it.
+const DEFAULT_IPF: u32 = 10;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, arg_required_else_help = true)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    run: Option<RunArgs>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run a rom, the same as passing the options without a subcommand
+    Run(Box<RunArgs>),
+    /// Print an address-annotated disassembly of a rom
+    Disasm {
+        /// The path of the rom to disassemble
+        #[arg(value_name = "FILE")]
+        rom_path: PathBuf,
+    },
+    /// Print the control-flow graph of a rom in the Graphviz DOT format
+    Cfg {
+        /// The path of the rom to analyze
+        #[arg(value_name = "FILE")]
+        rom_path: PathBuf,
+    },
+    /// Assemble a source file into a rom
+    Asm {
+        /// The path of the source to assemble
+        #[arg(value_name = "FILE")]
+        source_path: PathBuf,
+        /// Where to write the rom, defaults to the source path with a `.ch8` extension
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+}
+
+// clap leaves the group of an `Args` with flattened fields empty, name the required rom path so
+// that `Option<RunArgs>` is present whenever it is given.
+#[derive(Args)]
+#[group(args = ["rom_path"])]
+struct RunArgs {
     /// The path of the rom to load
     #[arg(short, long, value_name = "FILE")]
     rom_path: PathBuf,
+
+    /// The number of instructions executed per 60 Hz frame [default: from the rom database or 10]
+    #[arg(long, value_name = "N")]
+    ipf: Option<u32>,
+
+    /// Interpret instructions one at a time or compile straight-line blocks of them into closures
+    /// [possible values: interpreter, recompiler]
+    #[arg(long, value_name = "ENGINE", default_value_t = Engine::Interpreter)]
+    engine: Engine,
+
+    /// Seed the random number generator with a number or `random`, runs are reproducible without it
+    #[arg(long, value_name = "SEED")]
+    seed: Option<Seed>,
+
+    /// Look roms up in this database before the bundled one, in the format of the `programs.json`
+    /// of the community chip-8-database
+    #[arg(long, value_name = "FILE")]
+    database: Option<PathBuf>,
+
+    /// Keep the SUPER-CHIP RPL user flags (e.g. high scores) in a `.rpl` file next to the rom
+    #[arg(long)]
+    persist_flags: bool,
+
+    /// Start paused in an interactive debugger on the terminal, F8 pauses the window again
+    #[arg(long)]
+    debug: bool,
+
+    /// Wait for a GDB remote protocol client on this local port instead of opening a window
+    #[arg(long, value_name = "PORT", conflicts_with_all = ["debug", "headless", "record", "play"])]
+    gdb: Option<u16>,
+
+    #[command(flatten)]
+    quirks: QuirkArgs,
+
+    #[command(flatten)]
+    headless: HeadlessArgs,
+
+    #[command(flatten)]
+    trace: TraceArgs,
+
+    #[command(flatten)]
+    profile: ProfileArgs,
+
+    #[command(flatten)]
+    movie: MovieArgs,
+}
+
+#[derive(Args)]
+#[command(next_help_heading = "Headless")]
+struct HeadlessArgs {
+    /// Run without a window and print the final display as text
+    #[arg(long)]
+    headless: bool,
+
+    /// The number of frames to run for
+    #[arg(long, value_name = "N", default_value_t = 600)]
+    frames: u32,
+
+    /// Keys to press and release, e.g. `30:+5,35:-5` presses 5 in frame 30 and releases it in frame 35
+    #[arg(long, value_name = "SCRIPT", requires = "headless")]
+    keys: Option<KeyScript>,
+
+    /// Also write the final display as a PBM image
+    #[arg(long, value_name = "FILE", requires = "headless")]
+    pbm: Option<PathBuf>,
+}
+
+#[derive(Args)]
+#[command(next_help_heading = "Tracing")]
+struct TraceArgs {
+    /// Log every executed instruction to a file, or to stderr with `-`
+    #[arg(long, value_name = "FILE")]
+    trace: Option<PathBuf>,
+
+    /// The format of the log [possible values: human, machine]
+    #[arg(long, value_name = "FORMAT", default_value_t = TraceFormat::Human)]
+    trace_format: TraceFormat,
+
+    /// Only log the instructions in an address range, e.g. `0x200-0x2FF`
+    #[arg(long, value_name = "RANGE", requires = "trace")]
+    trace_range: Option<AddressRange>,
+}
+
+#[derive(Args)]
+#[command(next_help_heading = "Profiling")]
+struct ProfileArgs {
+    /// Write the executions per address, instruction and subroutine and the coverage of the rom to a file
+    #[arg(long, value_name = "FILE")]
+    profile: Option<PathBuf>,
+
+    /// Write the disassembly of the rom with the number of executions of every instruction to a file
+    #[arg(long, value_name = "FILE")]
+    profile_listing: Option<PathBuf>,
+}
+
+#[derive(Args)]
+#[command(next_help_heading = "Movies")]
+struct MovieArgs {
+    /// Record the keys pressed in every frame to a movie file that replays the run
+    #[arg(long, value_name = "FILE")]
+    record: Option<PathBuf>,
+
+    /// Replay the keys and seed of a movie, headless runs stop where it ends
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["record", "keys", "seed"])]
+    play: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy)]
+enum Seed {
+    Fixed(u64),
+    Random,
+}
+
+impl FromStr for Seed {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("random") {
+            return Ok(Seed::Random);
+        }
+
+        s.parse()
+            .map(Seed::Fixed)
+            .map_err(|_| format!("expected a number or `random`, got `{}`", s))
+    }
+}
+
+#[derive(Args)]
+#[command(next_help_heading = "Quirks")]
+struct QuirkArgs {
+    /// Emulate the quirks of a platform [default: from the rom database] [possible values: vip, chip48, schip, xochip]
+    #[arg(short, long)]
+    platform: Option<Platform>,
+
+    /// 8xy6 and 8xyE shift Vy instead of Vx
+    #[arg(long, value_name = "BOOL")]
+    shift_uses_vy: Option<bool>,
+
+    /// Fx55 and Fx65 increment I
+    #[arg(long, value_name = "BOOL")]
+    load_store_increments_i: Option<bool>,
+
+    /// Bnnn jumps to nnn + Vx instead of nnn + V0
+    #[arg(long, value_name = "BOOL")]
+    jump_uses_vx: Option<bool>,
+
+    /// 8xy1, 8xy2 and 8xy3 reset VF
+    #[arg(long, value_name = "BOOL")]
+    vf_reset: Option<bool>,
+
+    /// Clip sprites at the screen edges instead of wrapping them
+    #[arg(long, value_name = "BOOL")]
+    clip_sprites: Option<bool>,
+
+    /// Dxyn waits for the next frame before drawing
+    #[arg(long, value_name = "BOOL")]
+    display_wait: Option<bool>,
+}
+
+impl QuirkArgs {
+    /// The quirks of the selected platform, or else those the rom database has for the rom,
+    /// with the individual overrides applied.
+    fn quirks(&self, database: Option<(Platform, Quirks)>) -> Quirks {
+        let mut quirks = match (self.platform, database) {
+            (Some(platform), _) => platform.quirks(),
+            (None, Some((_, quirks))) => quirks,
+            (None, None) => Quirks::default(),
+        };
+
+        let overrides = [
+            (self.shift_uses_vy, &mut quirks.shift_uses_vy),
+            (self.load_store_increments_i, &mut quirks.load_store_increments_i),
+            (self.jump_uses_vx, &mut quirks.jump_uses_vx),
+            (self.vf_reset, &mut quirks.vf_reset),
+            (self.clip_sprites, &mut quirks.clip_sprites),
+            (self.display_wait, &mut quirks.display_wait),
+        ];
+        for (value, quirk) in overrides {
+            if let Some(value) = value {
+                *quirk = value;
+            }
+        }
+
+        quirks
+    }
 }
 
-fn main() -> std::result::Result<(), Box<dyn Error>> {
+fn main() -> ExitCode {
     let cli = Cli::parse();
 
+    let result = match (cli.command, cli.run) {
+        (Some(Command::Disasm { rom_path }), _) => disasm(&rom_path),
+        (Some(Command::Cfg { rom_path }), _) => cfg(&rom_path),
+        (Some(Command::Asm { source_path, output }), _) => {
+            let output = output.unwrap_or_else(|| source_path.with_extension("ch8"));
+            asm(&source_path, &output)
+        }
+        (Some(Command::Run(run)), _) => run_rom(&run),
+        (None, Some(run)) => run_rom(&run),
+        (None, None) => unreachable!("clap requires either a subcommand or the run arguments"),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn disasm(rom_path: &Path) -> std::result::Result<(), Box<dyn Error>> {
+    let bytes = std::fs::read(rom_path)?;
+
+    print!("{}", disassembler::disassemble(&bytes));
+
+    Ok(())
+}
+
+fn cfg(rom_path: &Path) -> std::result::Result<(), Box<dyn Error>> {
+    let bytes = std::fs::read(rom_path)?;
+
+    print!("{}", ControlFlowGraph::new(&bytes).to_dot());
+
+    Ok(())
+}
+
+fn asm(source_path: &Path, output: &Path) -> std::result::Result<(), Box<dyn Error>> {
+    let bytes = assembler::assemble_file(source_path)?;
+
+    std::fs::write(output, bytes)?;
+
+    Ok(())
+}
+
+/// Loads the rom with the quirks and memory selected on the command line, falling back to the
+/// platform of `game` in the rom database.
+fn create_interpreter(
+    cli: &RunArgs,
+    bytes: &[u8],
+    game: Option<Entry>,
+    seed: u64,
+) -> std::result::Result<Interpreter, Box<dyn Error>> {
+    let database = game.and_then(|game| game.rom.quirks());
+    let quirks = cli.quirks.quirks(database);
+    let platform = cli.quirks.platform.or(database.map(|(platform, _)| platform));
+    let memory_size = platform.map_or(MEMORY_SIZE, Platform::memory_size);
+    let mut interpreter = Interpreter::try_new(bytes, quirks, memory_size)?;
+    interpreter.set_seed(seed);
+    interpreter.set_engine(cli.engine);
+
+    if let Some(path) = &cli.trace.trace {
+        let writer: Box<dyn Write> = if path == Path::new("-") {
+            Box::new(io::stderr())
+        } else {
+            Box::new(BufWriter::new(File::create(path)?))
+        };
+        let trace = TraceLog::new(writer, cli.trace.trace_format, cli.trace.trace_range);
+        interpreter.add_observer(Box::new(trace));
+    }
+
+    Ok(interpreter)
+}
+
+fn run_rom(cli: &RunArgs) -> std::result::Result<(), Box<dyn Error>> {
     let bytes = std::fs::read(&cli.rom_path)?;
 
-    let fps = 500;
+    let mut databases = Vec::new();
+    if let Some(path) = &cli.database {
+        databases.push(Database::from_json(&std::fs::read_to_string(path)?)?);
+    }
+    databases.push(Database::bundled());
+    let game = databases.iter().find_map(|database| database.lookup(&bytes));
+
+    let playback = match &cli.movie.play {
+        Some(path) => Some(std::fs::read_to_string(path)?.parse::<Movie>()?),
+        None => None,
+    };
+    if playback.as_ref().is_some_and(|movie| !movie.is_of(&bytes)) {
+        return Err("The movie was recorded with a different rom".into());
+    }
 
+    let seed = match (&playback, cli.seed) {
+        (Some(movie), _) => movie.seed,
+        (None, Some(Seed::Fixed(seed))) => seed,
+        (None, Some(Seed::Random)) => {
+            let seed = rand::random();
+            // Logged so that a run can be reproduced with `--seed`.
+            eprintln!("Seed: {}", seed);
+            seed
+        }
+        (None, None) => DEFAULT_SEED,
+    };
+    let mut recording = cli.movie.record.as_ref().map(|_| Movie::new(&bytes, seed));
+
+    let mut interpreter = create_interpreter(cli, &bytes, game, seed)?;
+    let ipf = cli
+        .ipf
+        .or(game.and_then(|game| game.rom.tickrate))
+        .unwrap_or(DEFAULT_IPF);
+
+    let profiler = (cli.profile.profile.is_some() || cli.profile.profile_listing.is_some())
+        .then(|| Rc::new(RefCell::new(Profiler::new())));
+    if let Some(profiler) = &profiler {
+        interpreter.add_observer(Box::new(profiler.clone()));
+    }
+
+    let result = if let Some(port) = cli.gdb {
+        run_gdb(&mut interpreter, ipf, port)
+    } else if cli.headless.headless {
+        run_headless(cli, &mut interpreter, ipf, playback.as_ref(), recording.as_mut())
+    } else {
+        run_window(cli, &mut interpreter, ipf, game, playback.as_ref(), recording.as_mut())
+    };
+
+    // A movie of a run that failed is what reproduces the failure.
+    if let (Some(path), Some(movie)) = (&cli.movie.record, recording) {
+        std::fs::write(path, movie.to_string())?;
+    }
+
+    // Like the display of headless runs, the profile is written even if an instruction failed.
+    if let Some(profiler) = profiler {
+        let profiler = profiler.borrow();
+        if let Some(path) = &cli.profile.profile {
+            std::fs::write(path, profiler.report(&bytes))?;
+        }
+        if let Some(path) = &cli.profile.profile_listing {
+            std::fs::write(path, profiler.annotated_listing(&bytes))?;
+        }
+    }
+
+    result
+}
+
+fn run_gdb(interpreter: &mut Interpreter, ipf: u32, port: u16) -> std::result::Result<(), Box<dyn Error>> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    eprintln!("Waiting for gdb on {}", listener.local_addr()?);
+    let (stream, _) = listener.accept()?;
+
+    GdbServer::new(ipf).serve(interpreter, stream)?;
+    Ok(())
+}
+
+fn run_headless(
+    cli: &RunArgs,
+    interpreter: &mut Interpreter,
+    ipf: u32,
+    playback: Option<&Movie>,
+    recording: Option<&mut Movie>,
+) -> std::result::Result<(), Box<dyn Error>> {
+    let (script, frames) = match playback {
+        Some(movie) => (movie.script.clone(), movie.frames),
+        None => (cli.headless.keys.clone().unwrap_or_default(), cli.headless.frames),
+    };
+
+    // Counts the frames that started, including the one an instruction failed in.
+    let mut started = 0;
+    let mut debugger = cli.debug.then(Debugger::new);
+    let result = headless::run_with(interpreter, frames, &script, |interpreter| {
+        started += 1;
+        match &mut debugger {
+            Some(debugger) => debug_frame(debugger, interpreter, ipf),
+            None => Ok(interpreter.run_frame(ipf).map(|()| true)?),
+        }
+    });
+
+    if let Some(movie) = recording {
+        for event in script.events.iter().filter(|event| event.frame < started) {
+            movie.record(event.frame, event.key, event.pressed);
+        }
+        movie.frames = started;
+    }
+
+    // The display is written even if an instruction failed, it helps to find out why.
+    print!("{}", headless::to_ascii(interpreter.display()));
+    if let Some(path) = &cli.headless.pbm {
+        std::fs::write(path, headless::to_pbm(interpreter.display()))?;
+    }
+
+    result
+}
+
+/// Runs a frame under the debugger, prompting on the terminal whenever it pauses. Returns
+/// `Ok(false)` once the user quits.
+fn debug_frame(
+    debugger: &mut Debugger,
+    interpreter: &mut Interpreter,
+    cycles_per_frame: u32,
+) -> std::result::Result<bool, Box<dyn Error>> {
+    loop {
+        if debugger.is_paused() && !debugger.repl(interpreter, &mut io::stdin().lock(), &mut io::stdout())? {
+            return Ok(false);
+        }
+
+        // Failing instructions pause the debugger, whose prompt reports them.
+        if let Ok(true) = debugger.run_frame(interpreter, cycles_per_frame) {
+            return Ok(true);
+        }
+    }
+}
+
+fn run_window(
+    cli: &RunArgs,
+    interpreter: &mut Interpreter,
+    ipf: u32,
+    game: Option<Entry>,
+    playback: Option<&Movie>,
+    mut recording: Option<&mut Movie>,
+) -> std::result::Result<(), Box<dyn Error>> {
     let keymap: HashMap<Keycode, u8> = HashMap::from([
         (Keycode::Num1, 0x1),
         (Keycode::Num2, 0x2),
@@ -49,20 +520,69 @@ fn main() -> std::result::Result<(), Box<dyn Error>> {
         (Keycode::V, 0xF),
     ]);
 
-    let mut interpreter = Interpreter::with_rom(&bytes);
+    let flags_path = cli.persist_flags.then(|| cli.rom_path.with_extension("rpl"));
+    if let Some(path) = &flags_path {
+        if path.exists() {
+            interpreter.load_rpl_flags(&std::fs::read(path)?);
+        }
+    }
+    let mut saved_flags = *interpreter.rpl_flags();
+
+    // F5 saves the machine state next to the rom, F9 restores it.
+    let state_path = cli.rom_path.with_extension("state");
+
+    // Holding backspace plays the last 30 seconds backwards.
+    let mut rewind = Rewind::new(30 * 60);
+    rewind.push(interpreter);
+    let mut rewinding = false;
+
+    // Movies count the frames from the start, going back in time would break them.
+    let movie = playback.is_some() || recording.is_some();
+    let mut frame = 0;
+
+    let mut debugger = cli.debug.then(Debugger::new);
 
+    // The rom database can replace the colors and knows which keys the game uses.
+    let mut palette = PALETTE;
+    if let Some(game) = game {
+        let colors = game.rom.colors.as_ref().and_then(|colors| colors.pixel_rgb());
+        for (color, [r, g, b]) in palette.iter_mut().zip(colors.unwrap_or_default()) {
+            *color = Color::RGB(r, g, b);
+        }
+
+        if !game.rom.keys.is_empty() {
+            let hints: Vec<String> = game
+                .rom
+                .keys
+                .iter()
+                .map(|(action, key)| {
+                    let keycode = keymap
+                        .iter()
+                        .find(|(_, k)| *k == key)
+                        .map(|(keycode, _)| keycode.name());
+                    format!("{} {}", action, keycode.unwrap_or_else(|| format!("{:X}", key)))
+                })
+                .collect();
+            eprintln!("Keys: {}", hints.join(", "));
+        }
+    }
+
+    // The window keeps its size, high resolution mode draws smaller pixels.
     let scale = 32;
 
-    let width = interpreter.display().width() as u32;
-    let height = interpreter.display().height() as u32;
+    let window_width = interpreter.display().width() as u32 * scale;
+    let window_height = interpreter.display().height() as u32 * scale;
 
     let sdl_context = sdl2::init()?;
     let video_subsystem = sdl_context.video()?;
 
-    let title =format!("chip-8-rs: {:?}", &cli.rom_path.file_stem().unwrap_or_default());
+    let title = match game {
+        Some(game) => format!("chip-8-rs: {}", game.program.title),
+        None => format!("chip-8-rs: {:?}", &cli.rom_path.file_stem().unwrap_or_default()),
+    };
 
     let window = video_subsystem
-        .window(&title, width * scale, height * scale)
+        .window(&title, window_width, window_height)
         .position_centered()
         .build()?;
 
@@ -79,23 +599,27 @@ fn main() -> std::result::Result<(), Box<dyn Error>> {
         samples: None,     // default sample size
     };
 
-    let device = audio_subsystem.open_playback(None, &desired_spec, |spec| {
-        // initialize the audio callback
-        SquareWave {
-            phase_inc: 440.0 / spec.freq as f32,
-            phase: 0.0,
-            volume: 0.05,
-        }
-    })?;
+    let mut device = audio_subsystem.open_playback(None, &desired_spec, |spec| PatternWave::new(spec.freq, 0.05))?;
+    let mut audio = (None, interpreter.pitch());
+
+    let frame_duration = Duration::from_secs(1) / 60;
+    let mut next_frame = Instant::now();
 
     loop {
+        let current_audio = (interpreter.audio_pattern().copied(), interpreter.pitch());
+        if current_audio != audio {
+            audio = current_audio;
+            device.lock().set_pattern(audio.0, interpreter.playback_rate());
+        }
+
         if interpreter.sound_timer_active() {
             device.resume();
         } else {
             device.pause();
         }
 
-        // Input
+        // Input, the keys of a movie replace those of the user until it ends.
+        let replaying = playback.is_some_and(|movie| frame < movie.frames);
         for event in event_pump.poll_iter() {
             match event {
                 Event::Quit { .. }
@@ -104,43 +628,128 @@ fn main() -> std::result::Result<(), Box<dyn Error>> {
                     ..
                 } => return Ok(()),
                 Event::KeyDown {
-                    keycode: Some(keycode), ..
-                } if keymap.contains_key(&keycode) => {
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } if !movie => rewinding = true,
+                Event::KeyUp {
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } => rewinding = false,
+                Event::KeyDown {
+                    keycode: Some(Keycode::F5),
+                    repeat: false,
+                    ..
+                } => std::fs::write(&state_path, interpreter.save_state())?,
+                Event::KeyDown {
+                    keycode: Some(Keycode::F8),
+                    repeat: false,
+                    ..
+                } => {
+                    if let Some(debugger) = &mut debugger {
+                        debugger.pause();
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F9),
+                    repeat: false,
+                    ..
+                } if !movie => match std::fs::read(&state_path) {
+                    Ok(state) => {
+                        if let Err(e) = interpreter.load_state(&state) {
+                            eprintln!("Error: {}", e);
+                        }
+                    }
+                    Err(e) => eprintln!("Error: {}: {}", state_path.display(), e),
+                },
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    repeat,
+                    ..
+                } if keymap.contains_key(&keycode) && !replaying => {
                     let key = keymap.get(&keycode).expect("Already checked contains");
                     interpreter.keyboard_mut().press_key(*key);
+                    if let (Some(movie), false) = (&mut recording, repeat) {
+                        movie.record(frame, *key, true);
+                    }
                 }
                 Event::KeyUp {
                     keycode: Some(keycode), ..
-                } if keymap.contains_key(&keycode) => {
+                } if keymap.contains_key(&keycode) && !replaying => {
                     let key = keymap.get(&keycode).expect("Already checked contains");
                     interpreter.keyboard_mut().release_key(*key);
+                    if let Some(movie) = &mut recording {
+                        movie.record(frame, *key, false);
+                    }
                 }
                 _ => {}
             }
         }
 
         // Update
-        interpreter.step();
+        if rewinding {
+            rewind.rewind(interpreter, 1);
+        } else {
+            if let Some(movie) = playback {
+                movie.script.apply(frame, interpreter.keyboard_mut());
+            }
+            if let Some(movie) = &mut recording {
+                movie.frames = frame + 1;
+            }
+
+            match &mut debugger {
+                Some(debugger) => {
+                    if !debug_frame(debugger, interpreter, ipf)? {
+                        return Ok(());
+                    }
+                }
+                None => interpreter.run_frame(ipf)?,
+            }
+            rewind.push(interpreter);
+            frame += 1;
+        }
+
+        if let Some(path) = &flags_path {
+            if *interpreter.rpl_flags() != saved_flags {
+                saved_flags = *interpreter.rpl_flags();
+                std::fs::write(path, saved_flags)?;
+            }
+        }
+
+        if interpreter.has_exited() {
+            return Ok(());
+        }
 
         // Draw
-        canvas.set_draw_color(Color::RGB(0, 0, 0));
-        canvas.set_scale(scale as f32, scale as f32)?;
+        canvas.set_draw_color(palette[0]);
+        let width = interpreter.display().width() as u32;
+        let pixel_scale = (window_width / width) as f32;
+        canvas.set_scale(pixel_scale, pixel_scale)?;
         canvas.clear();
 
-        canvas.set_draw_color(Color::RGB(255, 255, 255));
-
-        for (idx, pixel) in interpreter.display().pixels().iter().enumerate() {
+        let mut points: [Vec<Point>; 4] = Default::default();
+        for (idx, color) in interpreter.display().color_indices().iter().enumerate() {
             let idx = idx as u32;
 
             let x = idx % width;
             let y = idx / width;
 
-            if *pixel {
-                canvas.draw_point(Point::new(x as i32, y as i32))?;
-            }
+            points[*color as usize].push(Point::new(x as i32, y as i32));
+        }
+
+        for (color, points) in palette.iter().zip(&points).skip(1) {
+            canvas.set_draw_color(*color);
+            canvas.draw_points(points.as_slice())?;
         }
 
         canvas.present();
-        ::std::thread::sleep(Duration::new(0, 1_000_000_000u32 / fps));
+
+        next_frame += frame_duration;
+        let now = Instant::now();
+        if next_frame > now {
+            ::std::thread::sleep(next_frame - now);
+        } else {
+            // Running behind, e.g. after the window was moved, do not try to catch up.
+            next_frame = now;
+        }
     }
 }
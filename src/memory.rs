@@ -1,7 +1,12 @@
+use crate::state::{StateError, StateReader, StateWriter};
+
 pub const START_ROM: usize = 0x200;
-const ROM_SIZE: usize = 4096 - START_ROM;
+/// The RAM of the classic platforms.
+pub const MEMORY_SIZE: usize = 0x1000;
+/// The RAM of XO-CHIP, which `F000 nnnn` can address completely.
+pub const XO_CHIP_MEMORY_SIZE: usize = 0x10000;
 
-const FONT_DATA: &'static [u8] = &[
+const FONT_DATA: &[u8] = &[
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
     0x20, 0x60, 0x20, 0x20, 0x70, // 1
     0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
@@ -20,18 +25,66 @@ const FONT_DATA: &'static [u8] = &[
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
 
+/// The SUPER-CHIP font with 8x10 sprites, stored directly after `FONT_DATA`.
+pub(crate) const BIG_FONT_START: usize = FONT_DATA.len();
+
+const BIG_FONT_DATA: &[u8] = &[
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
 #[derive(Debug)]
-pub(crate) struct Memory(pub [u8; 4096]);
+pub(crate) struct Memory(pub Vec<u8>);
 
 impl Memory {
-    pub fn new() -> Self {
-        Memory([0; 4096])
+    pub fn with_size(size: usize) -> Self {
+        assert!(size > START_ROM, "memory of {} bytes cannot hold a rom", size);
+        Memory(vec![0; size])
+    }
+
+    pub fn write_state(&self, writer: &mut StateWriter) {
+        writer.vec(&self.0);
+    }
+
+    pub fn read_state(reader: &mut StateReader) -> Result<Self, StateError> {
+        let bytes = reader.vec()?;
+        if bytes.len() <= START_ROM || bytes.len() > XO_CHIP_MEMORY_SIZE {
+            return Err(StateError::Invalid("memory size"));
+        }
+
+        Ok(Memory(bytes))
+    }
+
+    /// The number of bytes available for a rom, from `START_ROM` to the end of memory.
+    pub fn max_rom_size(&self) -> usize {
+        self.0.len() - START_ROM
     }
 
     pub fn load_rom(&mut self, bytes: &[u8]) {
         self.0[0..FONT_DATA.len()].copy_from_slice(FONT_DATA);
+        self.0[BIG_FONT_START..BIG_FONT_START + BIG_FONT_DATA.len()].copy_from_slice(BIG_FONT_DATA);
 
         let rom_size = bytes.len();
+        assert!(
+            rom_size <= self.max_rom_size(),
+            "ROM of {} bytes does not fit into memory",
+            rom_size
+        );
+
         self.0[START_ROM..START_ROM + rom_size].copy_from_slice(bytes);
     }
 }
@@ -61,9 +114,40 @@ mod tests {
     fn test_load_rom(rom: RomFixture) {
         let num_bytes = rom.bytes.len();
 
-        let mut memory = Memory::new();
+        let mut memory = Memory::with_size(MEMORY_SIZE);
         memory.load_rom(&rom.bytes);
 
         assert_eq!(memory.0[START_ROM..START_ROM + num_bytes], rom.bytes);
     }
+
+    #[test]
+    fn test_load_rom_into_xo_chip_memory() {
+        let rom = vec![0xAB; XO_CHIP_MEMORY_SIZE - START_ROM];
+
+        let mut memory = Memory::with_size(XO_CHIP_MEMORY_SIZE);
+        memory.load_rom(&rom);
+
+        assert_eq!(memory.0.len(), 0x10000);
+        assert_eq!(memory.0[0xFFFF], 0xAB);
+    }
+
+    #[test]
+    #[should_panic(expected = "does not fit into memory")]
+    fn test_load_rom_too_large() {
+        let mut memory = Memory::with_size(MEMORY_SIZE);
+        memory.load_rom(&[0; MEMORY_SIZE - START_ROM + 1]);
+    }
+
+    #[test]
+    fn test_load_fonts() {
+        let mut memory = Memory::with_size(MEMORY_SIZE);
+        memory.load_rom(&[]);
+
+        assert_eq!(memory.0[0..FONT_DATA.len()], *FONT_DATA);
+        assert_eq!(
+            memory.0[BIG_FONT_START..BIG_FONT_START + BIG_FONT_DATA.len()],
+            *BIG_FONT_DATA
+        );
+        assert!(BIG_FONT_START + BIG_FONT_DATA.len() <= START_ROM);
+    }
 }
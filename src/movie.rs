@@ -0,0 +1,210 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{
+    database,
+    headless::{KeyEvent, KeyScript},
+};
+
+/// The first line of every movie.
+const MAGIC: &str = "chip8 movie";
+/// The version of the format, the number after [`MAGIC`].
+const VERSION: u32 = 1;
+
+/// Returned when a movie cannot be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MovieError {
+    /// The text does not start with [`MAGIC`], it is not a movie.
+    NotAMovie,
+    /// The movie was written by a different version of the format.
+    UnsupportedVersion(String),
+    /// A header line is missing.
+    Missing(&'static str),
+    /// The line with the 1-based number cannot be parsed.
+    InvalidLine(usize, String),
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MovieError::NotAMovie => write!(f, "Not a movie"),
+            MovieError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported movie version {}, expected {}", version, VERSION)
+            }
+            MovieError::Missing(header) => write!(f, "Movie is missing the {} header", header),
+            MovieError::InvalidLine(number, line) => write!(f, "Invalid movie line {}: `{}`", number, line),
+        }
+    }
+}
+
+impl std::error::Error for MovieError {}
+
+/// The key presses and releases of a run and the frames they happened in, which replay the run
+/// exactly when the same rom is run with the same seed, quirks and instructions per frame.
+///
+/// Movies are text, a header followed by one event per line in the format of [`KeyScript`]:
+///
+/// ```text
+/// chip8 movie 1
+/// rom a9993e364706816aba3e25717850c26c9cd0d89d
+/// seed 9122022
+/// frames 600
+/// 30:+5
+/// 35:-5
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    /// The SHA-1 hash of the rom, see [`database::sha1`].
+    pub rom_hash: String,
+    pub seed: u64,
+    /// The number of frames that were run.
+    pub frames: u32,
+    pub script: KeyScript,
+}
+
+impl Movie {
+    /// An empty movie of `rom` run with `seed`.
+    pub fn new(rom: &[u8], seed: u64) -> Self {
+        Movie {
+            rom_hash: database::sha1(rom),
+            seed,
+            frames: 0,
+            script: KeyScript::default(),
+        }
+    }
+
+    /// Records that `key` was pressed or released before `frame` ran. The number of frames that
+    /// were run is up to the caller to keep.
+    pub fn record(&mut self, frame: u32, key: u8, pressed: bool) {
+        self.script.events.push(KeyEvent { frame, key, pressed });
+    }
+
+    /// Whether the movie was recorded with `rom`.
+    pub fn is_of(&self, rom: &[u8]) -> bool {
+        self.rom_hash.eq_ignore_ascii_case(&database::sha1(rom))
+    }
+}
+
+impl fmt::Display for Movie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} {}", MAGIC, VERSION)?;
+        writeln!(f, "rom {}", self.rom_hash)?;
+        writeln!(f, "seed {}", self.seed)?;
+        writeln!(f, "frames {}", self.frames)?;
+        for event in &self.script.events {
+            writeln!(f, "{}", event)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for Movie {
+    type Err = MovieError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.lines().enumerate().map(|(index, line)| (index + 1, line.trim()));
+
+        let version = match lines.next() {
+            Some((_, line)) => line.strip_prefix(MAGIC).ok_or(MovieError::NotAMovie)?.trim(),
+            None => return Err(MovieError::NotAMovie),
+        };
+        if version != VERSION.to_string() {
+            return Err(MovieError::UnsupportedVersion(version.to_string()));
+        }
+
+        let (mut rom_hash, mut seed, mut frames) = (None, None, None);
+        let mut script = KeyScript::default();
+
+        for (number, line) in lines.filter(|(_, line)| !line.is_empty()) {
+            let invalid = || MovieError::InvalidLine(number, line.to_string());
+
+            match line.split_once(' ') {
+                Some(("rom", hash)) => rom_hash = Some(hash.trim().to_string()),
+                Some(("seed", value)) => seed = Some(value.trim().parse().map_err(|_| invalid())?),
+                Some(("frames", value)) => frames = Some(value.trim().parse().map_err(|_| invalid())?),
+                _ => {
+                    let events: KeyScript = line.parse().map_err(|_| invalid())?;
+                    script.events.extend(events.events);
+                }
+            }
+        }
+
+        Ok(Movie {
+            rom_hash: rom_hash.ok_or(MovieError::Missing("rom"))?,
+            seed: seed.ok_or(MovieError::Missing("seed"))?,
+            frames: frames.ok_or(MovieError::Missing("frames"))?,
+            script,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headless;
+    use crate::interpreter::Interpreter;
+    use test_case::test_case;
+
+    const ROM: &[u8] = &[0x00, 0xE0];
+
+    fn movie() -> Movie {
+        let mut movie = Movie::new(ROM, 42);
+        movie.record(30, 0x5, true);
+        movie.record(35, 0xA, false);
+        movie.frames = 40;
+        movie
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let movie = movie();
+
+        let text = movie.to_string();
+
+        assert_eq!(
+            text,
+            format!(
+                "chip8 movie 1\nrom {}\nseed 42\nframes 40\n30:+5\n35:-A\n",
+                database::sha1(ROM)
+            )
+        );
+        assert_eq!(text.parse(), Ok(movie));
+    }
+
+    #[test]
+    fn test_is_of() {
+        assert!(movie().is_of(ROM));
+        assert!(!movie().is_of(&[0x00, 0xEE]));
+    }
+
+    #[test_case("" => MovieError::NotAMovie)]
+    #[test_case("chip8 state 1" => MovieError::NotAMovie)]
+    #[test_case("chip8 movie 2" => MovieError::UnsupportedVersion(String::from("2")))]
+    #[test_case("chip8 movie 1\nrom 00\nseed 1" => MovieError::Missing("frames"))]
+    #[test_case("chip8 movie 1\nrom 00\nseed x\nframes 1" => MovieError::InvalidLine(3, String::from("seed x")))]
+    #[test_case("chip8 movie 1\nrom 00\n\n10:5" => MovieError::InvalidLine(4, String::from("10:5")))]
+    fn test_parse_error(text: &str) -> MovieError {
+        text.parse::<Movie>().unwrap_err()
+    }
+
+    #[test]
+    fn test_replay() {
+        // LD V0, K; LD V1, K; EXIT
+        let rom: &[u8] = &[0xF0, 0x0A, 0xF1, 0x0A, 0x00, 0xFD];
+        let mut movie = Movie::new(rom, 1);
+        movie.record(2, 0x3, true);
+        movie.record(3, 0x3, false);
+        movie.record(5, 0x7, true);
+        movie.record(6, 0x7, false);
+        movie.frames = 7;
+        let movie: Movie = movie.to_string().parse().unwrap();
+
+        let mut interpreter = Interpreter::with_rom(rom);
+        interpreter.set_seed(movie.seed);
+        headless::run(&mut interpreter, movie.frames, 10, &movie.script).unwrap();
+
+        assert!(interpreter.has_exited());
+        assert_eq!(interpreter.registers().vx[..2], [0x3, 0x7]);
+    }
+}
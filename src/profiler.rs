@@ -0,0 +1,380 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::{
+    disassembler::{self, Item},
+    instruction::Instruction,
+    interpreter::{Observer, Registers},
+    memory::START_ROM,
+};
+
+/// The number of addresses listed as the hottest in [`Profiler::report`].
+const HOTTEST_ADDRESSES: usize = 10;
+
+/// How often a subroutine was called and how many instructions it executed, including those
+/// of the subroutines it called itself and its final `RET`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SubroutineProfile {
+    pub calls: u64,
+    pub cycles: u64,
+}
+
+/// How much of a ROM was executed, see [`Profiler::coverage`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Coverage {
+    /// The bytes of the ROM that were part of an executed instruction.
+    pub executed_bytes: usize,
+    pub rom_bytes: usize,
+    /// The instructions found by the disassembler that were executed.
+    pub executed_instructions: usize,
+    pub instructions: usize,
+}
+
+/// Counts the executions of every address and kind of instruction and the cycles spent in
+/// every subroutine, see [`Interpreter::add_observer`]. Every instruction is one cycle.
+///
+/// Subroutines are tracked by pairing every `CALL` with the next `RET`, calls that have not
+/// returned yet are not counted.
+///
+/// [`Interpreter::add_observer`]: crate::interpreter::Interpreter::add_observer
+#[derive(Debug, Clone, Default)]
+pub struct Profiler {
+    total: u64,
+    /// The last instruction executed at every address and how often the address was executed.
+    executions: BTreeMap<u16, (Instruction, u64)>,
+    kinds: BTreeMap<&'static str, u64>,
+    subroutines: BTreeMap<u16, SubroutineProfile>,
+    /// The subroutines that have not returned yet and the total when they were called.
+    call_stack: Vec<(u16, u64)>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Profiler::default()
+    }
+
+    /// The number of instructions executed.
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// How often the instruction at `address` was executed.
+    pub fn executions(&self, address: u16) -> u64 {
+        self.executions.get(&address).map_or(0, |(_, count)| *count)
+    }
+
+    /// How often every kind of instruction was executed, keyed by [`Instruction::pattern`].
+    pub fn kinds(&self) -> &BTreeMap<&'static str, u64> {
+        &self.kinds
+    }
+
+    /// The profile of every subroutine that returned at least once, keyed by its address.
+    pub fn subroutines(&self) -> &BTreeMap<u16, SubroutineProfile> {
+        &self.subroutines
+    }
+
+    /// How much of `rom`, loaded at `START_ROM`, was executed.
+    pub fn coverage(&self, rom: &[u8]) -> Coverage {
+        let listing = disassembler::disassemble(rom);
+        let instructions: Vec<u16> = listing
+            .lines
+            .iter()
+            .filter(|line| matches!(line.item, Item::Instruction(_)))
+            .map(|line| line.address)
+            .collect();
+
+        Coverage {
+            executed_bytes: self.executed_bytes(rom).iter().filter(|executed| **executed).count(),
+            rom_bytes: rom.len(),
+            executed_instructions: instructions
+                .iter()
+                .filter(|address| self.executions.contains_key(address))
+                .count(),
+            instructions: instructions.len(),
+        }
+    }
+
+    /// A text report of the coverage of `rom`, the hottest addresses, the executions of every
+    /// kind of instruction, the subroutines and the code that was never executed.
+    pub fn report(&self, rom: &[u8]) -> String {
+        let mut report = String::new();
+        let coverage = self.coverage(rom);
+
+        writeln!(report, "Instructions executed: {}", self.total).unwrap();
+        writeln!(
+            report,
+            "Coverage: {} of {} bytes ({}), {} of {} instructions ({})",
+            coverage.executed_bytes,
+            coverage.rom_bytes,
+            percent(coverage.executed_bytes as u64, coverage.rom_bytes as u64),
+            coverage.executed_instructions,
+            coverage.instructions,
+            percent(coverage.executed_instructions as u64, coverage.instructions as u64)
+        )
+        .unwrap();
+
+        let mut hottest: Vec<(&u16, &(Instruction, u64))> = self.executions.iter().collect();
+        hottest.sort_by(|(_, (_, a)), (_, (_, b))| b.cmp(a));
+        writeln!(report, "\nHottest addresses:").unwrap();
+        for (address, (instruction, count)) in hottest.into_iter().take(HOTTEST_ADDRESSES) {
+            let line = format!("{:#05X}: {}", address, instruction);
+            writeln!(report, "  {:<28}{:>10}  {}", line, count, percent(*count, self.total)).unwrap();
+        }
+
+        writeln!(report, "\nInstruction kinds:").unwrap();
+        let mut kinds: Vec<(&&str, &u64)> = self.kinds.iter().collect();
+        kinds.sort_by(|(_, a), (_, b)| b.cmp(a));
+        for (pattern, count) in kinds {
+            writeln!(
+                report,
+                "  {:<28}{:>10}  {}",
+                pattern,
+                count,
+                percent(*count, self.total)
+            )
+            .unwrap();
+        }
+
+        writeln!(report, "\nSubroutines:").unwrap();
+        writeln!(
+            report,
+            "  {:<8}{:>10}{:>12}{:>12}",
+            "address", "calls", "cycles", "per call"
+        )
+        .unwrap();
+        for (address, profile) in &self.subroutines {
+            writeln!(
+                report,
+                "  {:<#8X}{:>10}{:>12}{:>12}",
+                address,
+                profile.calls,
+                profile.cycles,
+                profile.cycles / profile.calls.max(1)
+            )
+            .unwrap();
+        }
+
+        writeln!(report, "\nNever executed code:").unwrap();
+        for (start, end) in self.unexecuted_code(rom) {
+            if start == end {
+                writeln!(report, "  {:#05X}", start).unwrap();
+            } else {
+                writeln!(report, "  {:#05X}-{:#05X}", start, end).unwrap();
+            }
+        }
+
+        report
+    }
+
+    /// The disassembly of `rom` with the number of executions in front of every line, `-` for
+    /// instructions that were never executed.
+    pub fn annotated_listing(&self, rom: &[u8]) -> String {
+        let mut listing = String::new();
+
+        for line in disassembler::disassemble(rom).lines {
+            let count = match &line.item {
+                Item::Instruction(_) => match self.executions(line.address) {
+                    0 => String::from("-"),
+                    count => count.to_string(),
+                },
+                // Data can still be executed by computed jumps the disassembler cannot follow.
+                Item::Data(bytes) => {
+                    let end = line.address.saturating_add(bytes.len() as u16);
+                    match self
+                        .executions
+                        .range(line.address..end)
+                        .map(|(_, (_, count))| count)
+                        .sum::<u64>()
+                    {
+                        0 => String::new(),
+                        count => count.to_string(),
+                    }
+                }
+            };
+            writeln!(listing, "{:>10}  {}", count, line).unwrap();
+        }
+
+        listing
+    }
+
+    /// Whether every byte of `rom` was part of an executed instruction.
+    fn executed_bytes(&self, rom: &[u8]) -> Vec<bool> {
+        let mut executed = vec![false; rom.len()];
+
+        for (address, (instruction, _)) in &self.executions {
+            let start = (*address as usize).saturating_sub(START_ROM);
+            let end = (*address as usize + instruction.size() as usize).saturating_sub(START_ROM);
+            for byte in executed.iter_mut().take(end).skip(start) {
+                *byte = true;
+            }
+        }
+
+        executed
+    }
+
+    /// The ranges of consecutive instructions found by the disassembler that were never
+    /// executed, from the address of the first to the last byte.
+    fn unexecuted_code(&self, rom: &[u8]) -> Vec<(u16, u16)> {
+        let mut ranges: Vec<(u16, u16)> = Vec::new();
+        let mut previous_executed = true;
+
+        for line in disassembler::disassemble(rom).lines {
+            let instruction = match line.item {
+                Item::Instruction(instruction) => instruction,
+                Item::Data(_) => {
+                    previous_executed = true;
+                    continue;
+                }
+            };
+
+            let executed = self.executions.contains_key(&line.address);
+            let end = line.address + instruction.size() - 1;
+            match ranges.last_mut() {
+                Some(range) if !executed && !previous_executed => range.1 = end,
+                _ if !executed => ranges.push((line.address, end)),
+                _ => {}
+            }
+            previous_executed = executed;
+        }
+
+        ranges
+    }
+}
+
+impl Observer for Profiler {
+    fn executed(&mut self, pc: u16, instruction: Instruction, _: &Registers, after: &Registers) {
+        self.total += 1;
+        let execution = self.executions.entry(pc).or_insert((instruction, 0));
+        *execution = (instruction, execution.1 + 1);
+        *self.kinds.entry(instruction.pattern()).or_default() += 1;
+
+        match instruction {
+            Instruction::Call(_) => self.call_stack.push((after.pc, self.total)),
+            Instruction::Ret => {
+                if let Some((address, start)) = self.call_stack.pop() {
+                    let profile = self.subroutines.entry(address).or_default();
+                    profile.calls += 1;
+                    profile.cycles += self.total - start;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn percent(count: u64, total: u64) -> String {
+    if total == 0 {
+        String::from("-")
+    } else {
+        format!("{:.1}%", count as f64 * 100.0 / total as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::Addr;
+    use crate::interpreter::Interpreter;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // 0x200: CALL 0x208; CALL 0x208; EXIT; db 0xAB, 0xCD
+    // 0x208: ADD V0, 1; SE V0, 3; RET; LD V1, 2; RET
+    const ROM: &[u8] = &[
+        0x22, 0x08, 0x22, 0x08, 0x00, 0xFD, 0xAB, 0xCD, 0x70, 0x01, 0x30, 0x03, 0x00, 0xEE, 0x61, 0x02, 0x00, 0xEE,
+    ];
+
+    fn profile() -> Profiler {
+        let mut interpreter = Interpreter::with_rom(ROM);
+        let profiler = Rc::new(RefCell::new(Profiler::new()));
+        interpreter.add_observer(Box::new(profiler.clone()));
+
+        interpreter.run_frame(100).unwrap();
+        assert!(interpreter.has_exited());
+
+        let profiler = profiler.borrow().clone();
+        profiler
+    }
+
+    #[test]
+    fn test_counts() {
+        let profiler = profile();
+
+        assert_eq!(profiler.total(), 9);
+        assert_eq!(profiler.executions(0x200), 1);
+        assert_eq!(profiler.executions(0x208), 2);
+        assert_eq!(profiler.executions(0x20E), 0);
+        assert_eq!(profiler.kinds()["CALL addr"], 2);
+        assert_eq!(profiler.kinds()["RET"], 2);
+        assert_eq!(profiler.kinds()["EXIT"], 1);
+        assert_eq!(
+            profiler.subroutines().get(&0x208),
+            Some(&SubroutineProfile { calls: 2, cycles: 6 })
+        );
+    }
+
+    #[test]
+    fn test_nested_calls() {
+        let mut profiler = Profiler::new();
+        let at = |pc| Registers {
+            pc,
+            ..Default::default()
+        };
+
+        profiler.executed(0x200, Instruction::Call(Addr(0x300)), &at(0x200), &at(0x300));
+        profiler.executed(0x300, Instruction::Call(Addr(0x400)), &at(0x300), &at(0x400));
+        profiler.executed(0x400, Instruction::Clear, &at(0x400), &at(0x402));
+        profiler.executed(0x402, Instruction::Ret, &at(0x402), &at(0x302));
+        profiler.executed(0x302, Instruction::Ret, &at(0x302), &at(0x202));
+        profiler.executed(0x202, Instruction::Ret, &at(0x202), &at(0x200));
+
+        assert_eq!(
+            profiler.subroutines()[&0x400],
+            SubroutineProfile { calls: 1, cycles: 2 }
+        );
+        assert_eq!(
+            profiler.subroutines()[&0x300],
+            SubroutineProfile { calls: 1, cycles: 4 }
+        );
+        assert_eq!(profiler.subroutines().len(), 2);
+    }
+
+    #[test]
+    fn test_coverage() {
+        assert_eq!(
+            profile().coverage(ROM),
+            Coverage {
+                executed_bytes: 12,
+                rom_bytes: 18,
+                executed_instructions: 6,
+                instructions: 8,
+            }
+        );
+    }
+
+    #[test]
+    fn test_report() {
+        let report = profile().report(ROM);
+
+        assert!(report.starts_with(
+            "Instructions executed: 9\n\
+             Coverage: 12 of 18 bytes (66.7%), 6 of 8 instructions (75.0%)\n"
+        ));
+        assert!(report.contains("  0x208: ADD V0, 0x01                  2  22.2%\n"));
+        assert!(report.contains("  CALL addr                            2  22.2%\n"));
+        assert!(report.contains("  0x208            2           6           3\n"));
+        assert!(report.ends_with("Never executed code:\n  0x20E-0x211\n"));
+    }
+
+    #[test]
+    fn test_annotated_listing() {
+        let listing = profile().annotated_listing(ROM);
+        let lines: Vec<&str> = listing.lines().collect();
+
+        assert_eq!(lines.len(), 9);
+        assert_eq!(lines[0], "         1  0x200: 2208  CALL 0x208");
+        assert_eq!(lines[3], "            0x206:       db 0xAB, 0xCD");
+        assert_eq!(lines[4], "         2  0x208: 7001  ADD V0, 0x01");
+        assert_eq!(lines[7], "         -  0x20E: 6102  LD V1, 0x02");
+    }
+}
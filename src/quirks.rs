@@ -0,0 +1,137 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::memory::{MEMORY_SIZE, XO_CHIP_MEMORY_SIZE};
+
+/// Behaviors in which Chip-8 implementations disagree. Every ROM was written against one
+/// implementation, so it only runs correctly when the same choices are made here.
+///
+/// The default keeps the behavior this interpreter always had, which does not match any
+/// single platform; use [`Platform::quirks`] to emulate a specific one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Quirks {
+    /// 8xy6 and 8xyE shift Vy and store the result in Vx, instead of shifting Vx in place.
+    pub shift_uses_vy: bool,
+    /// Fx55 and Fx65 leave I pointing to the address after the last register stored or loaded.
+    pub load_store_increments_i: bool,
+    /// Bnnn jumps to nnn plus Vx, where x is the highest nibble of nnn, instead of nnn plus V0.
+    pub jump_uses_vx: bool,
+    /// 8xy1, 8xy2 and 8xy3 reset VF to 0.
+    pub vf_reset: bool,
+    /// Sprites are clipped at the edges of the screen instead of wrapping around.
+    pub clip_sprites: bool,
+    /// Dxyn waits for the next vertical blank before drawing, limiting draws to 60 per second.
+    pub display_wait: bool,
+}
+
+/// The platforms whose quirks can be emulated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Platform {
+    /// The original interpreter on the RCA COSMAC VIP.
+    CosmacVip,
+    /// CHIP-48 on the HP-48 calculators.
+    Chip48,
+    /// SUPER-CHIP 1.1 on the HP-48 calculators.
+    SuperChip,
+    /// XO-CHIP as implemented by Octo.
+    XoChip,
+}
+
+impl Platform {
+    pub const ALL: [Platform; 4] = [
+        Platform::CosmacVip,
+        Platform::Chip48,
+        Platform::SuperChip,
+        Platform::XoChip,
+    ];
+
+    pub fn quirks(self) -> Quirks {
+        match self {
+            Platform::CosmacVip => Quirks {
+                shift_uses_vy: true,
+                load_store_increments_i: true,
+                jump_uses_vx: false,
+                vf_reset: true,
+                clip_sprites: true,
+                display_wait: true,
+            },
+            Platform::Chip48 | Platform::SuperChip => Quirks {
+                shift_uses_vy: false,
+                load_store_increments_i: false,
+                jump_uses_vx: true,
+                vf_reset: false,
+                clip_sprites: true,
+                display_wait: false,
+            },
+            Platform::XoChip => Quirks {
+                shift_uses_vy: true,
+                load_store_increments_i: true,
+                jump_uses_vx: false,
+                vf_reset: false,
+                clip_sprites: false,
+                display_wait: false,
+            },
+        }
+    }
+
+    /// The number of bytes of RAM, XO-CHIP extends it to 64 KiB.
+    pub fn memory_size(self) -> usize {
+        match self {
+            Platform::XoChip => XO_CHIP_MEMORY_SIZE,
+            _ => MEMORY_SIZE,
+        }
+    }
+
+    /// The name used to select the platform on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            Platform::CosmacVip => "vip",
+            Platform::Chip48 => "chip48",
+            Platform::SuperChip => "schip",
+            Platform::XoChip => "xochip",
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Platform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Platform::ALL
+            .iter()
+            .copied()
+            .find(|platform| platform.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                let names: Vec<&str> = Platform::ALL.iter().map(|platform| platform.name()).collect();
+                format!("unknown platform `{}`, expected one of {}", s, names.join(", "))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_platform() {
+        for platform in Platform::ALL {
+            assert_eq!(platform.name().parse::<Platform>(), Ok(platform));
+        }
+
+        assert_eq!("XOCHIP".parse::<Platform>(), Ok(Platform::XoChip));
+        assert!("gameboy".parse::<Platform>().is_err());
+    }
+
+    #[test]
+    fn test_default_is_not_a_platform() {
+        for platform in Platform::ALL {
+            assert_ne!(platform.quirks(), Quirks::default());
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use rand::Rng;
+use rand_chacha::ChaCha8Rng;
+
+/// The seed of the built-in generator unless another one is chosen, so runs are reproducible.
+pub const DEFAULT_SEED: u64 = 9122022;
+
+/// Where Cxkk - RND Vx, byte gets its random bytes from.
+pub trait RandomSource {
+    fn next_byte(&mut self) -> u8;
+}
+
+impl RandomSource for ChaCha8Rng {
+    fn next_byte(&mut self) -> u8 {
+        self.gen_range(0..=255)
+    }
+}
+
+/// Returns the given bytes in order and starts over after the last one, to script exactly
+/// what a program sees.
+#[derive(Debug, Clone)]
+pub struct ScriptedRandom {
+    bytes: Vec<u8>,
+    position: usize,
+}
+
+impl ScriptedRandom {
+    /// # Panics
+    ///
+    /// If `bytes` is empty.
+    pub fn new(bytes: Vec<u8>) -> Self {
+        assert!(!bytes.is_empty(), "a scripted random source needs at least one byte");
+        ScriptedRandom { bytes, position: 0 }
+    }
+}
+
+impl RandomSource for ScriptedRandom {
+    fn next_byte(&mut self) -> u8 {
+        let byte = self.bytes[self.position];
+        self.position = (self.position + 1) % self.bytes.len();
+        byte
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_scripted_random_repeats() {
+        let mut source = ScriptedRandom::new(vec![1, 2, 3]);
+
+        let bytes: Vec<u8> = (0..5).map(|_| source.next_byte()).collect();
+
+        assert_eq!(bytes, vec![1, 2, 3, 1, 2]);
+    }
+
+    #[test]
+    fn test_seeded_generator_is_reproducible() {
+        let mut a = ChaCha8Rng::seed_from_u64(DEFAULT_SEED);
+        let mut b = ChaCha8Rng::seed_from_u64(DEFAULT_SEED);
+
+        for _ in 0..100 {
+            assert_eq!(a.next_byte(), b.next_byte());
+        }
+    }
+}
@@ -1,5 +1,14 @@
-#[derive(Debug, Default)]
-pub(crate) struct Registers {
+use crate::state::{StateError, StateReader, StateWriter};
+
+/// Returned when a subroutine call or return would leave the bounds of the stack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum StackError {
+    Overflow,
+    Underflow,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Registers {
     /// Chip-8 has 16 general purpose 8-bit registers, usually referred to as Vx, where x is a hexadecimal digit (0 through F).
     /// The VF register should not be used by any program, as it is used as a flag by some instructions.
     pub vx: [u8; 16],
@@ -9,7 +18,7 @@ pub(crate) struct Registers {
     pub i: u16,
     /// The program counter (PC) should be 16-bit, and is used to store the currently executing address.
     pub pc: u16,
-    /// The stack pointer (SP) can be 8-bit, it is used to point to the topmost level of the stack.
+    /// The stack pointer (SP) can be 8-bit, it is the number of addresses on the stack.
     pub sp: u8,
 
     pub delay: u8,
@@ -21,43 +30,93 @@ pub(crate) struct Registers {
 }
 
 impl Registers {
-    /// The interpreter increments the stack pointer, then puts the current PC on the top of the stack. The PC is then set to nnn.
-    pub fn push(&mut self, n: u16) {
+    /// Puts the current PC on the top of the stack and increments the stack pointer. The PC is then set to nnn.
+    pub(crate) fn push(&mut self, n: u16) -> Result<(), StackError> {
+        let slot = self.stack.get_mut(self.sp as usize).ok_or(StackError::Overflow)?;
+        *slot = self.pc;
         self.sp += 1;
-        self.stack[self.sp as usize] = self.pc;
         self.pc = n;
+        Ok(())
+    }
+
+    pub(crate) fn write_state(&self, writer: &mut StateWriter) {
+        writer.bytes(&self.vx);
+        writer.u16(self.i);
+        writer.u16(self.pc);
+        writer.u8(self.sp);
+        writer.u8(self.delay);
+        writer.u8(self.sound);
+        for address in self.stack {
+            writer.u16(address);
+        }
     }
 
-    /// The interpreter sets the program counter to the address at the top of the stack, then subtracts 1 from the stack pointer.
-    pub fn pop(&mut self) {
+    pub(crate) fn read_state(reader: &mut StateReader) -> Result<Self, StateError> {
+        let mut registers = Registers {
+            vx: reader.array()?,
+            i: reader.u16()?,
+            pc: reader.u16()?,
+            sp: reader.u8()?,
+            delay: reader.u8()?,
+            sound: reader.u8()?,
+            stack: [0; 16],
+        };
+        for address in &mut registers.stack {
+            *address = reader.u16()?;
+        }
+
+        if registers.sp as usize > registers.stack.len() {
+            return Err(StateError::Invalid("stack pointer is larger than the stack"));
+        }
+
+        Ok(registers)
+    }
+
+    /// Subtracts 1 from the stack pointer, then sets the program counter to the address at the top of the stack.
+    pub(crate) fn pop(&mut self) -> Result<(), StackError> {
+        self.sp = self.sp.checked_sub(1).ok_or(StackError::Underflow)?;
         self.pc = self.stack[self.sp as usize];
-        self.sp -= 1;
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::Registers;
+    use super::{Registers, StackError};
 
     #[test]
+    #[allow(clippy::field_reassign_with_default)]
     fn test_push_pop() {
         let mut registers = Registers::default();
         registers.pc = 0x42;
 
-        registers.push(0x23);
+        registers.push(0x23).unwrap();
         assert_eq!(registers.sp, 1);
         assert_eq!(registers.pc, 0x23);
 
-        registers.push(0x77);
+        registers.push(0x77).unwrap();
         assert_eq!(registers.sp, 2);
         assert_eq!(registers.pc, 0x77);
 
-        registers.pop();
+        registers.pop().unwrap();
         assert_eq!(registers.sp, 1);
         assert_eq!(registers.pc, 0x23);
 
-        registers.pop();
+        registers.pop().unwrap();
         assert_eq!(registers.sp, 0);
         assert_eq!(registers.pc, 0x42);
     }
+
+    #[test]
+    fn test_stack_bounds() {
+        let mut registers = Registers::default();
+
+        assert_eq!(registers.pop(), Err(StackError::Underflow));
+
+        for n in 0..16 {
+            registers.push(n).unwrap();
+        }
+        assert_eq!(registers.push(0x200), Err(StackError::Overflow));
+        assert_eq!((registers.sp, registers.pc), (16, 15));
+    }
 }
@@ -0,0 +1,177 @@
+use std::collections::VecDeque;
+use std::convert::TryInto;
+
+use crate::interpreter::Interpreter;
+
+/// A ring buffer of the last `capacity` states of an interpreter, to step gameplay backwards.
+///
+/// Only the newest state is kept as a whole. Every older state is stored as the run-length
+/// encoded XOR against the state after it, which is mostly zeros since little of the memory
+/// changes from one frame to the next.
+pub struct Rewind {
+    capacity: usize,
+    latest: Option<Vec<u8>>,
+    /// The deltas that turn a state into the one before it, the newest at the back.
+    deltas: VecDeque<Vec<u8>>,
+}
+
+impl Rewind {
+    /// Creates a buffer for `capacity` states, which is at least the newest one.
+    pub fn new(capacity: usize) -> Self {
+        Rewind {
+            capacity: capacity.max(1),
+            latest: None,
+            deltas: VecDeque::new(),
+        }
+    }
+
+    /// Records the current state of `interpreter`, usually once per frame. The oldest state is
+    /// dropped when the buffer is full.
+    pub fn push(&mut self, interpreter: &Interpreter) {
+        let state = interpreter.save_state();
+
+        if let Some(previous) = self.latest.replace(state) {
+            let delta = encode_delta(self.latest.as_ref().unwrap(), &previous);
+            self.deltas.push_back(delta);
+        }
+
+        while self.len() > self.capacity {
+            self.deltas.pop_front();
+        }
+    }
+
+    /// Restores the state recorded `frames` pushes ago, or the oldest one if there are not
+    /// that many, and drops every newer state. Returns the number of frames actually rewound.
+    pub fn rewind(&mut self, interpreter: &mut Interpreter, frames: usize) -> usize {
+        let mut state = match self.latest.take() {
+            Some(state) => state,
+            None => return 0,
+        };
+
+        let mut rewound = 0;
+        while rewound < frames {
+            match self.deltas.pop_back() {
+                Some(delta) => state = apply_delta(&state, &delta),
+                None => break,
+            }
+            rewound += 1;
+        }
+
+        interpreter.load_state(&state).expect("recorded states can be restored");
+        self.latest = Some(state);
+
+        rewound
+    }
+
+    /// The number of recorded states.
+    pub fn len(&self) -> usize {
+        self.latest.iter().count() + self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.latest.is_none()
+    }
+
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.deltas.clear();
+    }
+}
+
+/// Encodes the difference that turns `from` into `to` as the length of `to` followed by
+/// runs of unchanged bytes and XORed literals, see [`apply_delta`].
+fn encode_delta(from: &[u8], to: &[u8]) -> Vec<u8> {
+    let len = from.len().max(to.len());
+    let xor: Vec<u8> = (0..len)
+        .map(|i| from.get(i).unwrap_or(&0) ^ to.get(i).unwrap_or(&0))
+        .collect();
+
+    let mut delta = Vec::new();
+    delta.extend_from_slice(&(to.len() as u32).to_be_bytes());
+
+    let mut i = 0;
+    while i < xor.len() {
+        let zeros = xor[i..].iter().take_while(|b| **b == 0).count();
+        i += zeros;
+        let literals = xor[i..].iter().take_while(|b| **b != 0).count();
+
+        delta.extend_from_slice(&(zeros as u32).to_be_bytes());
+        delta.extend_from_slice(&(literals as u32).to_be_bytes());
+        delta.extend_from_slice(&xor[i..i + literals]);
+        i += literals;
+    }
+
+    delta
+}
+
+/// Turns `from` into the state the delta was encoded for.
+fn apply_delta(from: &[u8], delta: &[u8]) -> Vec<u8> {
+    let word = |offset: usize| u32::from_be_bytes(delta[offset..offset + 4].try_into().unwrap()) as usize;
+
+    let len = word(0);
+    let mut state = from.to_vec();
+    state.resize(len.max(from.len()), 0);
+
+    let mut offset = 4;
+    let mut i = 0;
+    while offset < delta.len() {
+        let zeros = word(offset);
+        let literals = word(offset + 4);
+        offset += 8;
+        i += zeros;
+
+        for (byte, xor) in state[i..i + literals].iter_mut().zip(&delta[offset..offset + literals]) {
+            *byte ^= xor;
+        }
+        offset += literals;
+        i += literals;
+    }
+
+    state.truncate(len);
+    state
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck_macros::quickcheck;
+
+    #[quickcheck]
+    fn test_delta_roundtrip(from: Vec<u8>, to: Vec<u8>) {
+        let delta = encode_delta(&from, &to);
+
+        assert_eq!(apply_delta(&from, &delta), to);
+    }
+
+    #[test]
+    fn test_delta_of_similar_states_is_small() {
+        let from = vec![7; 4096];
+        let mut to = from.clone();
+        to[100] = 8;
+
+        assert_eq!(encode_delta(&from, &to).len(), 4 + 8 + 1 + 8);
+    }
+
+    #[test]
+    fn test_rewind() {
+        // ADD V0, 1; JP 0x200
+        let mut interpreter = Interpreter::with_rom(&[0x70, 0x01, 0x12, 0x00]);
+        let mut rewind = Rewind::new(4);
+        rewind.push(&interpreter);
+
+        let mut states = vec![interpreter.save_state()];
+        for _ in 0..5 {
+            interpreter.run_frame(2).unwrap();
+            rewind.push(&interpreter);
+            states.push(interpreter.save_state());
+        }
+        assert_eq!(rewind.len(), 4);
+
+        assert_eq!(rewind.rewind(&mut interpreter, 2), 2);
+        assert_eq!(interpreter.save_state(), states[3]);
+
+        assert_eq!(rewind.rewind(&mut interpreter, 5), 1, "older states were dropped");
+        assert_eq!(interpreter.save_state(), states[2]);
+        assert_eq!(rewind.len(), 1);
+    }
+}
@@ -17,3 +17,93 @@ impl AudioCallback for SquareWave {
         }
     }
 }
+
+/// The number of bits in an XO-CHIP audio pattern.
+const PATTERN_BITS: f32 = 128.0;
+
+/// Plays an XO-CHIP audio pattern in a loop, each set bit is a high and each cleared bit a low
+/// sample. Until a pattern is set the classic square wave is played instead.
+pub struct PatternWave {
+    pub pattern: Option<[u8; 16]>,
+    /// The number of pattern bits advanced per output sample.
+    pub bit_inc: f32,
+    /// The position in the pattern, in bits.
+    pub position: f32,
+    pub sample_rate: f32,
+    pub volume: f32,
+    pub fallback: SquareWave,
+}
+
+impl PatternWave {
+    pub fn new(sample_rate: i32, volume: f32) -> Self {
+        let sample_rate = sample_rate as f32;
+
+        PatternWave {
+            pattern: None,
+            bit_inc: 0.0,
+            position: 0.0,
+            sample_rate,
+            volume,
+            fallback: SquareWave {
+                phase_inc: 440.0 / sample_rate,
+                phase: 0.0,
+                volume,
+            },
+        }
+    }
+
+    /// Plays `pattern` at `rate` bits per second, `None` switches back to the square wave.
+    pub fn set_pattern(&mut self, pattern: Option<[u8; 16]>, rate: f32) {
+        self.pattern = pattern;
+        self.bit_inc = rate / self.sample_rate;
+    }
+}
+
+impl AudioCallback for PatternWave {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        let pattern = match self.pattern {
+            Some(pattern) => pattern,
+            None => return self.fallback.callback(out),
+        };
+
+        for x in out.iter_mut() {
+            let bit = self.position as usize;
+            let high = pattern[bit / 8] & (0x80 >> (bit % 8)) != 0;
+
+            *x = if high { self.volume } else { -self.volume };
+            self.position = (self.position + self.bit_inc) % PATTERN_BITS;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pattern_wave() {
+        let mut wave = PatternWave::new(8000, 1.0);
+        let mut pattern = [0; 16];
+        pattern[0] = 0b1010_0000;
+        pattern[15] = 0b0000_0001;
+
+        wave.set_pattern(Some(pattern), 8000.0);
+        let mut out = [0.0; 130];
+        wave.callback(&mut out);
+
+        assert_eq!(out[..4], [1.0, -1.0, 1.0, -1.0]);
+        assert_eq!(out[126..130], [-1.0, 1.0, 1.0, -1.0]);
+    }
+
+    #[test]
+    fn test_pattern_wave_falls_back_to_square_wave() {
+        let mut wave = PatternWave::new(1760, 1.0);
+
+        let mut out = [0.0; 4];
+        wave.callback(&mut out);
+
+        assert_eq!(out, [1.0, 1.0, 1.0, -1.0]);
+    }
+}
@@ -0,0 +1,215 @@
+use std::fmt;
+
+/// Every save state starts with these bytes.
+pub const MAGIC: &[u8; 8] = b"CHIP8SAV";
+/// The version of the format written by [`crate::interpreter::Interpreter::save_state`], bumped
+/// whenever the layout changes.
+pub const VERSION: u16 = 1;
+
+/// Returned when a save state cannot be restored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateError {
+    /// The data does not start with [`MAGIC`], it is not a save state.
+    BadMagic,
+    /// The state was written by a different version of the format.
+    UnsupportedVersion(u16),
+    /// The data ends before the state is complete.
+    Truncated,
+    /// A value is out of range, e.g. a stack pointer larger than the stack.
+    Invalid(&'static str),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::BadMagic => write!(f, "Not a save state"),
+            StateError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported save state version {}, expected {}", version, VERSION)
+            }
+            StateError::Truncated => write!(f, "Save state is truncated"),
+            StateError::Invalid(what) => write!(f, "Invalid save state: {}", what),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+/// Writes the header and big-endian values of a save state.
+pub(crate) struct StateWriter {
+    bytes: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        let mut writer = StateWriter { bytes: Vec::new() };
+        writer.bytes(MAGIC);
+        writer.u16(VERSION);
+        writer
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.u8(value.into());
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.bytes(&value.to_be_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_be_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.bytes(&value.to_be_bytes());
+    }
+
+    pub fn u128(&mut self, value: u128) {
+        self.bytes(&value.to_be_bytes());
+    }
+
+    /// Writes `bytes` as they are, the reader needs to know their length.
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    /// Writes `bytes` prefixed with their length.
+    pub fn vec(&mut self, bytes: &[u8]) {
+        self.u32(bytes.len() as u32);
+        self.bytes(bytes);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+/// Reads what a [`StateWriter`] wrote, in the same order.
+pub(crate) struct StateReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    /// Checks the header and returns a reader positioned after it.
+    pub fn new(bytes: &'a [u8]) -> Result<Self, StateError> {
+        if bytes.len() < MAGIC.len() || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(StateError::BadMagic);
+        }
+
+        let mut reader = StateReader {
+            bytes: &bytes[MAGIC.len()..],
+        };
+        let version = reader.u16()?;
+        if version != VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+
+        Ok(reader)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, StateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::Invalid("boolean is neither 0 nor 1")),
+        }
+    }
+
+    pub fn u16(&mut self) -> Result<u16, StateError> {
+        Ok(u16::from_be_bytes(self.array()?))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, StateError> {
+        Ok(u32::from_be_bytes(self.array()?))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, StateError> {
+        Ok(u64::from_be_bytes(self.array()?))
+    }
+
+    pub fn u128(&mut self) -> Result<u128, StateError> {
+        Ok(u128::from_be_bytes(self.array()?))
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        if self.bytes.len() < len {
+            return Err(StateError::Truncated);
+        }
+
+        let (bytes, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(bytes)
+    }
+
+    pub fn array<const N: usize>(&mut self) -> Result<[u8; N], StateError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.bytes(N)?);
+        Ok(array)
+    }
+
+    /// Reads bytes written with [`StateWriter::vec`].
+    pub fn vec(&mut self) -> Result<Vec<u8>, StateError> {
+        let len = self.u32()? as usize;
+        Ok(self.bytes(len)?.to_vec())
+    }
+
+    /// Fails if anything is left after the state.
+    pub fn finish(self) -> Result<(), StateError> {
+        if self.bytes.is_empty() {
+            Ok(())
+        } else {
+            Err(StateError::Invalid("unexpected data after the state"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let mut writer = StateWriter::new();
+        writer.u8(0x12);
+        writer.bool(true);
+        writer.u16(0x3456);
+        writer.u128(u128::MAX - 1);
+        writer.vec(&[1, 2, 3]);
+        let bytes = writer.finish();
+
+        let mut reader = StateReader::new(&bytes).unwrap();
+        assert_eq!(reader.u8(), Ok(0x12));
+        assert_eq!(reader.bool(), Ok(true));
+        assert_eq!(reader.u16(), Ok(0x3456));
+        assert_eq!(reader.u128(), Ok(u128::MAX - 1));
+        assert_eq!(reader.vec(), Ok(vec![1, 2, 3]));
+        assert_eq!(reader.finish(), Ok(()));
+    }
+
+    #[test]
+    fn test_header() {
+        assert_eq!(StateReader::new(b"CHIP8").err(), Some(StateError::BadMagic));
+        assert_eq!(StateReader::new(b"NOTASAVESTATE").err(), Some(StateError::BadMagic));
+        assert_eq!(
+            StateReader::new(b"CHIP8SAV\x00\x63").err(),
+            Some(StateError::UnsupportedVersion(99))
+        );
+    }
+
+    #[test]
+    fn test_truncated() {
+        let mut writer = StateWriter::new();
+        writer.vec(&[1, 2, 3]);
+        let bytes = writer.finish();
+
+        let mut reader = StateReader::new(&bytes[..bytes.len() - 1]).unwrap();
+        assert_eq!(reader.vec(), Err(StateError::Truncated));
+    }
+}
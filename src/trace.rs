@@ -0,0 +1,290 @@
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use crate::{
+    disassembler::{Item, Line},
+    instruction::Instruction,
+    interpreter::{Observer, Registers},
+};
+
+/// How a [`TraceLog`] writes the executed instructions, one per line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceFormat {
+    /// The disassembly and the registers the instruction changed, e.g.
+    /// `0x202: 7301  ADD V3, 0x01         V3=02`. The program counter is only listed if the
+    /// instruction jumped, called, returned or skipped.
+    Human,
+    /// The program counter, opcode and all registers before the instruction in fixed fields,
+    /// like the logs of other emulators, e.g. `PC:0202 OP:7301 V0:00 .. VF:00 I:0000 SP:00 DT:00 ST:00`.
+    Machine,
+}
+
+impl TraceFormat {
+    pub const ALL: [TraceFormat; 2] = [TraceFormat::Human, TraceFormat::Machine];
+
+    /// The name used to select the format on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            TraceFormat::Human => "human",
+            TraceFormat::Machine => "machine",
+        }
+    }
+}
+
+impl fmt::Display for TraceFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for TraceFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        TraceFormat::ALL
+            .iter()
+            .copied()
+            .find(|format| format.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                let names: Vec<&str> = TraceFormat::ALL.iter().map(|format| format.name()).collect();
+                format!("unknown trace format `{}`, expected one of {}", s, names.join(", "))
+            })
+    }
+}
+
+/// The addresses from `start` to `end` inclusive, written as `START-END` or a single address,
+/// e.g. `0x200-0x2FF`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AddressRange {
+    pub start: u16,
+    pub end: u16,
+}
+
+impl AddressRange {
+    pub fn contains(&self, address: u16) -> bool {
+        (self.start..=self.end).contains(&address)
+    }
+}
+
+impl FromStr for AddressRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let address = |text: &str| {
+            let text = text.trim();
+            match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+                Some(hex) => u16::from_str_radix(hex, 16),
+                None => text.parse(),
+            }
+            .map_err(|_| format!("`{}` is not an address", text))
+        };
+
+        let range = match s.split_once('-') {
+            Some((start, end)) => AddressRange {
+                start: address(start)?,
+                end: address(end)?,
+            },
+            None => AddressRange {
+                start: address(s)?,
+                end: address(s)?,
+            },
+        };
+
+        if range.start > range.end {
+            return Err(format!("the range `{}` ends before it starts", s));
+        }
+
+        Ok(range)
+    }
+}
+
+/// Writes every executed instruction to `writer` in the given format, see
+/// [`Interpreter::add_observer`]. Tracing stops at the first error writing to `writer`.
+///
+/// [`Interpreter::add_observer`]: crate::interpreter::Interpreter::add_observer
+pub struct TraceLog<W: Write> {
+    writer: Option<W>,
+    format: TraceFormat,
+    /// Only instructions at these addresses are traced, all of them if `None`.
+    range: Option<AddressRange>,
+}
+
+impl<W: Write> TraceLog<W> {
+    pub fn new(writer: W, format: TraceFormat, range: Option<AddressRange>) -> Self {
+        TraceLog {
+            writer: Some(writer),
+            format,
+            range,
+        }
+    }
+}
+
+impl<W: Write> Observer for TraceLog<W> {
+    fn executed(&mut self, pc: u16, instruction: Instruction, before: &Registers, after: &Registers) {
+        if self.range.is_some_and(|range| !range.contains(pc)) {
+            return;
+        }
+
+        let writer = match &mut self.writer {
+            Some(writer) => writer,
+            None => return,
+        };
+        let result = match self.format {
+            TraceFormat::Human => write_human(writer, pc, instruction, before, after),
+            TraceFormat::Machine => write_machine(writer, pc, instruction, before),
+        };
+
+        if result.is_err() {
+            self.writer = None;
+        }
+    }
+}
+
+fn write_human(
+    writer: &mut impl Write,
+    pc: u16,
+    instruction: Instruction,
+    before: &Registers,
+    after: &Registers,
+) -> io::Result<()> {
+    let line = Line {
+        address: pc,
+        item: Item::Instruction(instruction),
+    };
+
+    let mut changes = Vec::new();
+    for (x, (before, after)) in before.vx.iter().zip(&after.vx).enumerate() {
+        if before != after {
+            changes.push(format!("V{:X}={:02X}", x, after));
+        }
+    }
+    if before.i != after.i {
+        changes.push(format!("I={:04X}", after.i));
+    }
+    if after.pc != pc.wrapping_add(instruction.size()) {
+        changes.push(format!("PC={:04X}", after.pc));
+    }
+    if before.sp != after.sp {
+        changes.push(format!("SP={:X}", after.sp));
+    }
+    if before.delay != after.delay {
+        changes.push(format!("DT={:02X}", after.delay));
+    }
+    if before.sound != after.sound {
+        changes.push(format!("ST={:02X}", after.sound));
+    }
+
+    if changes.is_empty() {
+        writeln!(writer, "{}", line)
+    } else {
+        writeln!(writer, "{:<32}  {}", line.to_string(), changes.join(" "))
+    }
+}
+
+fn write_machine(writer: &mut impl Write, pc: u16, instruction: Instruction, before: &Registers) -> io::Result<()> {
+    let opcode: String = instruction
+        .to_bytes()
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect();
+    write!(writer, "PC:{:04X} OP:{}", pc, opcode)?;
+    for (x, value) in before.vx.iter().enumerate() {
+        write!(writer, " V{:X}:{:02X}", x, value)?;
+    }
+    writeln!(
+        writer,
+        " I:{:04X} SP:{:02X} DT:{:02X} ST:{:02X}",
+        before.i, before.sp, before.delay, before.sound
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::{Addr, Byte, Vx};
+    use test_case::test_case;
+
+    fn registers(pc: u16) -> Registers {
+        Registers {
+            pc,
+            ..Default::default()
+        }
+    }
+
+    fn trace(format: TraceFormat, range: Option<AddressRange>) -> String {
+        let mut log = TraceLog::new(Vec::new(), format, range);
+
+        let mut after = registers(0x202);
+        after.vx[3] = 0x2A;
+        after.vx[0xF] = 1;
+        log.executed(
+            0x200,
+            Instruction::AddRegisterImmediate(Vx(3), Byte(0x2A)),
+            &registers(0x200),
+            &after,
+        );
+
+        let mut called = after.clone();
+        called.pc = 0x300;
+        called.sp = 1;
+        log.executed(0x202, Instruction::Call(Addr(0x300)), &after, &called);
+
+        String::from_utf8(log.writer.unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_human_format() {
+        assert_eq!(
+            trace(TraceFormat::Human, None),
+            "0x200: 732A  ADD V3, 0x2A         V3=2A VF=01\n\
+             0x202: 2300  CALL 0x300           PC=0300 SP=1\n"
+        );
+    }
+
+    #[test]
+    fn test_machine_format() {
+        let zeros = |from: usize, to: usize| -> String { (from..=to).map(|x| format!(" V{:X}:00", x)).collect() };
+
+        assert_eq!(
+            trace(TraceFormat::Machine, None),
+            format!(
+                "PC:0200 OP:732A{} I:0000 SP:00 DT:00 ST:00\n\
+                 PC:0202 OP:2300{} V3:2A{} VF:01 I:0000 SP:00 DT:00 ST:00\n",
+                zeros(0, 0xF),
+                zeros(0, 2),
+                zeros(4, 0xE)
+            )
+        );
+    }
+
+    #[test]
+    fn test_range() {
+        let range = AddressRange {
+            start: 0x202,
+            end: 0x2FF,
+        };
+
+        assert_eq!(
+            trace(TraceFormat::Human, Some(range)),
+            "0x202: 2300  CALL 0x300           PC=0300 SP=1\n"
+        );
+    }
+
+    #[test_case("0x200-0x2FF" => Ok(AddressRange { start: 0x200, end: 0x2FF }))]
+    #[test_case("512 - 0x2ff" => Ok(AddressRange { start: 0x200, end: 0x2FF }))]
+    #[test_case("0x204" => Ok(AddressRange { start: 0x204, end: 0x204 }))]
+    #[test_case("0x300-0x200" => Err(String::from("the range `0x300-0x200` ends before it starts")))]
+    #[test_case("0x200-" => Err(String::from("`` is not an address")))]
+    fn test_parse_range(text: &str) -> Result<AddressRange, String> {
+        text.parse()
+    }
+
+    #[test]
+    fn test_parse_format() {
+        for format in TraceFormat::ALL {
+            assert_eq!(format.name().parse::<TraceFormat>(), Ok(format));
+        }
+        assert!("json".parse::<TraceFormat>().is_err());
+    }
+}